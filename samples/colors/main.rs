@@ -1,29 +1,30 @@
-use std::{borrow::Cow, iter::once, mem::size_of, time::Instant};
+use std::{borrow::Cow, iter::once, mem::size_of, process, time::Instant};
 
 use bytemuck::cast_slice;
 use bytemuck_derive::{Pod, Zeroable};
-use futures::executor::block_on;
 use glam::{Mat4, Quat, Vec3, Vec4};
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
-    Backends, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
     BindingType, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, Color,
-    CommandEncoderDescriptor, CompareFunction, DepthBiasState, DepthStencilState, Device,
-    DeviceDescriptor, Extent3d, Face, Features, FragmentState, IndexFormat, InstanceDescriptor,
-    LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PowerPreference, PresentMode,
-    PrimitiveState, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
-    RenderPassDescriptor, RenderPipelineDescriptor, RequestAdapterOptions, ShaderModuleDescriptor,
-    ShaderSource, ShaderStages, StencilState, SurfaceConfiguration, Texture, TextureDescriptor,
+    CommandEncoderDescriptor, CompareFunction, DepthBiasState, DepthStencilState, Device, Extent3d,
+    Face, FragmentState, IndexFormat, LoadOp, MultisampleState, Operations,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipelineDescriptor,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState, Texture, TextureDescriptor,
     TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
     VertexAttribute, VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
 };
-use wgpu_samples::camera::{Camera, CameraDescriptor, GpuCamera};
+use wgpu_samples::{
+    camera::{Camera, CameraDescriptor, GpuCamera},
+    config::Config,
+    gpu::GpuContext,
+};
 use winit::{
-    dpi::LogicalSize,
     event::{DeviceEvent, ElementState, Event, MouseScrollDelta, VirtualKeyCode, WindowEvent},
     event_loop::EventLoop,
     platform::run_return::EventLoopExtRunReturn,
-    window::{CursorGrabMode, WindowBuilder},
+    window::CursorGrabMode,
 };
 
 const SCREEN_WIDTH: u32 = 1280;
@@ -115,74 +116,30 @@ impl Model {
 }
 
 fn main() {
+    let config = Config::load();
+
     let mut event_loop = EventLoop::new();
 
-    let logical_size = LogicalSize::new(SCREEN_WIDTH, SCREEN_HEIGHT);
-    let window = WindowBuilder::new()
-        .with_inner_size(logical_size)
+    let window = config
+        .window_builder(SCREEN_WIDTH, SCREEN_HEIGHT)
         .with_title(TITLE)
         .with_visible(false)
         .build(&event_loop)
         .expect("failed to create window");
-    let physical_size = window.inner_size();
 
-    let instance = wgpu::Instance::new(InstanceDescriptor {
-        backends: Backends::PRIMARY,
-        ..Default::default()
+    let GpuContext {
+        device,
+        queue,
+        surface,
+        mut surface_config,
+        ..
+    } = GpuContext::new(&window, &config.gpu_context_options()).unwrap_or_else(|e| {
+        eprintln!("failed to set up the GPU: {}", e);
+        process::exit(1);
     });
 
-    let surface = unsafe {
-        instance
-            .create_surface(&window)
-            .expect("failed to create surface")
-    };
-
-    let adapter = block_on(instance.request_adapter(&RequestAdapterOptions {
-        power_preference: PowerPreference::LowPower,
-        force_fallback_adapter: false,
-        compatible_surface: Some(&surface),
-    }))
-    .expect("failed to get a suitable adapter");
-
-    let (device, queue) = block_on(adapter.request_device(
-        &DeviceDescriptor {
-            label: Some("device"),
-            features: Features::empty(),
-            limits: adapter.limits(),
-        },
-        None,
-    ))
-    .expect("failed to get a device");
-
-    let surface_capabilities = surface.get_capabilities(&adapter);
-    let surface_format = if surface_capabilities
-        .formats
-        .contains(&TextureFormat::Rgba8Unorm)
-    {
-        TextureFormat::Rgba8Unorm
-    } else if surface_capabilities
-        .formats
-        .contains(&TextureFormat::Bgra8Unorm)
-    {
-        TextureFormat::Bgra8Unorm
-    } else {
-        surface_capabilities.formats[0]
-    };
-
-    let mut surface_config = SurfaceConfiguration {
-        usage: TextureUsages::RENDER_ATTACHMENT,
-        format: surface_format,
-        width: physical_size.width,
-        height: physical_size.height,
-        present_mode: PresentMode::Fifo,
-        alpha_mode: surface_capabilities.alpha_modes[0],
-        view_formats: Vec::new(),
-    };
-
-    surface.configure(&device, &surface_config);
-
     let (mut depth_texture, mut depth_texture_view) =
-        create_depth_texture(&device, physical_size.width, physical_size.height);
+        create_depth_texture(&device, surface_config.width, surface_config.height);
 
     let scene_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
         label: Some("bind_group_layout::scene"),
@@ -294,7 +251,7 @@ fn main() {
         fragment: Some(FragmentState {
             module: &light_shader_module,
             entry_point: "fs_main",
-            targets: &[Some(surface_format.into())],
+            targets: &[Some(surface_config.format.into())],
         }),
         multiview: None,
     });
@@ -328,7 +285,7 @@ fn main() {
         fragment: Some(FragmentState {
             module: &model_shader_module,
             entry_point: "fs_main",
-            targets: &[Some(surface_format.into())],
+            targets: &[Some(surface_config.format.into())],
         }),
         multiview: None,
     });
@@ -400,7 +357,7 @@ fn main() {
     let light_color = Vec4::new(1.0, 1.0, 1.0, 1.0);
 
     let mut camera = Camera::new(&CameraDescriptor {
-        aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
+        aspect_ratio: surface_config.width as f32 / surface_config.height as f32,
         ..Default::default()
     });
 
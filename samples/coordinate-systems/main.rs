@@ -1,31 +1,27 @@
-use std::{borrow::Cow, iter::once, mem::size_of, time::Instant};
+use std::{borrow::Cow, iter::once, mem::size_of, process, time::Instant};
 
 use bytemuck::cast_slice;
 use bytemuck_derive::{Pod, Zeroable};
-use futures::executor::block_on;
 use glam::{Mat4, Quat, Vec2, Vec3};
 use image::GenericImageView;
 use wgpu::{
     AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferDescriptor,
     BufferSize, BufferUsages, Color, CommandEncoderDescriptor, CompareFunction, DepthBiasState,
-    DepthStencilState, Device, DeviceDescriptor, Extent3d, Face, Features, FilterMode,
-    FragmentState, ImageDataLayout, IndexFormat, Instance, LoadOp, MultisampleState, Operations,
-    PipelineLayoutDescriptor, PresentMode, PrimitiveState, RenderPassColorAttachment,
-    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipelineDescriptor,
-    RequestAdapterOptions, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor,
-    ShaderSource, ShaderStages, StencilState, SurfaceConfiguration, Texture, TextureDescriptor,
-    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
-    TextureViewDescriptor, TextureViewDimension, VertexAttribute, VertexBufferLayout, VertexFormat,
-    VertexState, VertexStepMode,
+    DepthStencilState, Device, Extent3d, Face, FilterMode, FragmentState, ImageDataLayout,
+    IndexFormat, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RenderPipelineDescriptor, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor,
+    ShaderSource, ShaderStages, StencilState, Texture, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor,
+    TextureViewDimension, VertexAttribute, VertexBufferLayout, VertexFormat, VertexState,
+    VertexStepMode,
 };
-use wgpu_samples::assets;
+use wgpu_samples::{assets, config::Config, gpu::GpuContext};
 use winit::{
-    dpi::LogicalSize,
     event::{Event, WindowEvent},
     event_loop::EventLoop,
     platform::run_return::EventLoopExtRunReturn,
-    window::WindowBuilder,
 };
 
 #[derive(Debug, Default, Clone, Copy, Pod, Zeroable)]
@@ -80,50 +76,27 @@ impl Globals {
 }
 
 fn main() {
+    let config = Config::load();
+
     let mut event_loop = EventLoop::new();
 
-    let window = WindowBuilder::new()
-        .with_inner_size(LogicalSize::new(1280, 720))
+    let window = config
+        .window_builder(1280, 720)
         .with_title("Coordinate systems")
         .with_visible(false)
         .build(&event_loop)
         .expect("failed to create a window");
 
-    let physical_size = window.inner_size();
-
-    let instance = Instance::default();
-    let surface = unsafe { instance.create_surface(&window) }.expect("failed to create surface");
-    let adapter = block_on(instance.request_adapter(&RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::HighPerformance,
-        force_fallback_adapter: false,
-        compatible_surface: Some(&surface),
-    }))
-    .expect("failed to find a suitable adapter");
-
-    let swapchain_capabilities = surface.get_capabilities(&adapter);
-    let swapchian_format = if swapchain_capabilities
-        .formats
-        .contains(&TextureFormat::Bgra8Unorm)
-    {
-        TextureFormat::Bgra8Unorm
-    } else if swapchain_capabilities
-        .formats
-        .contains(&TextureFormat::Rgba8Unorm)
-    {
-        TextureFormat::Rgba8Unorm
-    } else {
-        swapchain_capabilities.formats[0]
-    };
-
-    let (device, queue) = block_on(adapter.request_device(
-        &DeviceDescriptor {
-            label: None,
-            features: Features::empty(),
-            limits: adapter.limits(),
-        },
-        None,
-    ))
-    .expect("failed to create a device");
+    let GpuContext {
+        device,
+        queue,
+        surface,
+        mut surface_config,
+        ..
+    } = GpuContext::new(&window, &config.gpu_context_options()).unwrap_or_else(|e| {
+        eprintln!("failed to set up the GPU: {}", e);
+        process::exit(1);
+    });
 
     let shader_src = include_str!("shader.wgsl");
     let shader = device.create_shader_module(ShaderModuleDescriptor {
@@ -216,13 +189,13 @@ fn main() {
         fragment: Some(FragmentState {
             module: &shader,
             entry_point: "fs_main",
-            targets: &[Some(swapchian_format.into())],
+            targets: &[Some(surface_config.format.into())],
         }),
         multiview: None,
     });
 
     let (mut depth_texture, mut depth_texture_view) =
-        create_depth_texture(&device, physical_size.width, physical_size.height);
+        create_depth_texture(&device, surface_config.width, surface_config.height);
 
     let sampler = device.create_sampler(&SamplerDescriptor {
         label: None,
@@ -233,18 +206,6 @@ fn main() {
         ..Default::default()
     });
 
-    let mut config = SurfaceConfiguration {
-        usage: TextureUsages::RENDER_ATTACHMENT,
-        format: swapchian_format,
-        width: physical_size.width,
-        height: physical_size.height,
-        present_mode: PresentMode::Fifo,
-        alpha_mode: swapchain_capabilities.alpha_modes[0],
-        view_formats: Vec::new(),
-    };
-
-    surface.configure(&device, &config);
-
     let globals_ubo = device.create_buffer(&BufferDescriptor {
         label: Some("ubo::globals"),
         size: size_of::<Globals>() as u64,
@@ -417,7 +378,12 @@ fn main() {
     );
 
     let view = Mat4::from_translation(Vec3::new(0.0, 0.0, -3.0));
-    let projection = Mat4::perspective_rh(45.0_f32.to_radians(), 1280.0 / 720.0, 0.1, 100.0);
+    let projection = Mat4::perspective_rh(
+        45.0_f32.to_radians(),
+        surface_config.width as f32 / surface_config.height as f32,
+        0.1,
+        100.0,
+    );
     let globals = Globals::new(view, projection);
 
     window.set_visible(true);
@@ -432,21 +398,27 @@ fn main() {
                     WindowEvent::CloseRequested => running = false,
 
                     WindowEvent::Resized(size) => {
-                        config.width = size.width;
-                        config.height = size.height;
-                        surface.configure(&device, &config);
-
-                        (depth_texture, depth_texture_view) =
-                            create_depth_texture(&device, config.width, config.height);
+                        surface_config.width = size.width;
+                        surface_config.height = size.height;
+                        surface.configure(&device, &surface_config);
+
+                        (depth_texture, depth_texture_view) = create_depth_texture(
+                            &device,
+                            surface_config.width,
+                            surface_config.height,
+                        );
                     }
 
                     WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        config.width = new_inner_size.width;
-                        config.height = new_inner_size.height;
-                        surface.configure(&device, &config);
-
-                        (depth_texture, depth_texture_view) =
-                            create_depth_texture(&device, config.width, config.height);
+                        surface_config.width = new_inner_size.width;
+                        surface_config.height = new_inner_size.height;
+                        surface.configure(&device, &surface_config);
+
+                        (depth_texture, depth_texture_view) = create_depth_texture(
+                            &device,
+                            surface_config.width,
+                            surface_config.height,
+                        );
                     }
 
                     _ => (),
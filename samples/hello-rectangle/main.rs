@@ -1,22 +1,22 @@
-use std::{borrow::Cow, iter::once, mem::size_of};
+use std::{borrow::Cow, iter::once, mem::size_of, process};
 
 use bytemuck::cast_slice;
 use bytemuck_derive::{Pod, Zeroable};
-use futures::executor::block_on;
 use wgpu::{
-    BufferDescriptor, BufferUsages, Color, CommandEncoderDescriptor, DeviceDescriptor, Features,
-    FragmentState, IndexFormat, Instance, LoadOp, MultisampleState, Operations,
-    PipelineLayoutDescriptor, PolygonMode, PresentMode, PrimitiveState, RenderPassColorAttachment,
-    RenderPassDescriptor, RenderPipelineDescriptor, RequestAdapterOptions, ShaderModuleDescriptor,
-    ShaderSource, SurfaceConfiguration, TextureFormat, TextureUsages, TextureViewDescriptor,
-    VertexAttribute, VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
+    BufferDescriptor, BufferUsages, Color, CommandEncoderDescriptor, Features, FragmentState,
+    IndexFormat, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PolygonMode,
+    PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor, RenderPipelineDescriptor,
+    ShaderModuleDescriptor, ShaderSource, TextureViewDescriptor, VertexAttribute,
+    VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
+};
+use wgpu_samples::{
+    config::Config,
+    gpu::{GpuContext, GpuContextOptions},
 };
 use winit::{
-    dpi::LogicalSize,
     event::{ElementState, Event, VirtualKeyCode, WindowEvent},
     event_loop::EventLoop,
     platform::run_return::EventLoopExtRunReturn,
-    window::WindowBuilder,
 };
 
 #[derive(Debug, Default, Clone, Copy, Pod, Zeroable)]
@@ -46,40 +46,35 @@ impl Vertex {
 }
 
 fn main() {
+    let config = Config::load();
+
     let mut event_loop = EventLoop::new();
 
-    let window = WindowBuilder::new()
-        .with_inner_size(LogicalSize::new(1280, 720))
+    let window = config
+        .window_builder(1280, 720)
         .with_title("Hello rectangle")
         .with_visible(false)
         .build(&event_loop)
         .expect("failed to create a window");
 
-    let physical_size = window.inner_size();
-
-    let instance = Instance::default();
-    let surface = unsafe { instance.create_surface(&window) }.expect("failed to create surface");
-    let adapter = block_on(instance.request_adapter(&RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::HighPerformance,
-        force_fallback_adapter: false,
-        compatible_surface: Some(&surface),
-    }))
-    .expect("failed to find a suitable adapter");
-
-    let mut features = Features::empty();
-    features.insert(
-        Features::from_name("POLYGON_MODE_LINE").expect("POLYGON_MODE_LINE is not supported"),
-    );
-
-    let (device, queue) = block_on(adapter.request_device(
-        &DeviceDescriptor {
-            label: None,
-            features,
-            limits: adapter.limits(),
+    let GpuContext {
+        device,
+        queue,
+        surface,
+        mut surface_config,
+        ..
+    } = GpuContext::new(
+        &window,
+        &GpuContextOptions {
+            // The wireframe is drawn with lines rather than filled triangles.
+            required_features: Features::POLYGON_MODE_LINE,
+            ..config.gpu_context_options()
         },
-        None,
-    ))
-    .expect("failed to create a device");
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("failed to set up the GPU: {}", e);
+        process::exit(1);
+    });
 
     let shader_src = include_str!("shader.wgsl");
     let shader = device.create_shader_module(ShaderModuleDescriptor {
@@ -93,21 +88,6 @@ fn main() {
         push_constant_ranges: &[],
     });
 
-    let swapchain_capabilities = surface.get_capabilities(&adapter);
-    let swapchian_format = if swapchain_capabilities
-        .formats
-        .contains(&TextureFormat::Bgra8Unorm)
-    {
-        TextureFormat::Bgra8Unorm
-    } else if swapchain_capabilities
-        .formats
-        .contains(&TextureFormat::Rgba8Unorm)
-    {
-        TextureFormat::Rgba8Unorm
-    } else {
-        swapchain_capabilities.formats[0]
-    };
-
     let filled_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
         label: Some("filled_pipeline"),
         layout: Some(&pipeline_layout),
@@ -122,7 +102,7 @@ fn main() {
         fragment: Some(FragmentState {
             module: &shader,
             entry_point: "fs_main",
-            targets: &[Some(swapchian_format.into())],
+            targets: &[Some(surface_config.format.into())],
         }),
         multiview: None,
     });
@@ -144,23 +124,11 @@ fn main() {
         fragment: Some(FragmentState {
             module: &shader,
             entry_point: "fs_main",
-            targets: &[Some(swapchian_format.into())],
+            targets: &[Some(surface_config.format.into())],
         }),
         multiview: None,
     });
 
-    let mut config = SurfaceConfiguration {
-        usage: TextureUsages::RENDER_ATTACHMENT,
-        format: swapchian_format,
-        width: physical_size.width,
-        height: physical_size.height,
-        present_mode: PresentMode::Fifo,
-        alpha_mode: swapchain_capabilities.alpha_modes[0],
-        view_formats: Vec::new(),
-    };
-
-    surface.configure(&device, &config);
-
     let vertices = [
         Vertex::new(-0.5, 0.5, 0.0),
         Vertex::new(-0.5, -0.5, 0.0),
@@ -199,15 +167,15 @@ fn main() {
                     WindowEvent::CloseRequested => running = false,
 
                     WindowEvent::Resized(size) => {
-                        config.width = size.width;
-                        config.height = size.height;
-                        surface.configure(&device, &config);
+                        surface_config.width = size.width;
+                        surface_config.height = size.height;
+                        surface.configure(&device, &surface_config);
                     }
 
                     WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        config.width = new_inner_size.width;
-                        config.height = new_inner_size.height;
-                        surface.configure(&device, &config);
+                        surface_config.width = new_inner_size.width;
+                        surface_config.height = new_inner_size.height;
+                        surface.configure(&device, &surface_config);
                     }
 
                     WindowEvent::KeyboardInput { input, .. } => {
@@ -1,40 +1,45 @@
-use std::{borrow::Cow, iter::once, mem::size_of, time::Instant};
+use std::{borrow::Cow, iter::once, mem::size_of, process, time::Instant};
 
 use bytemuck::cast_slice;
 use bytemuck_derive::{Pod, Zeroable};
-use futures::executor::block_on;
 use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
 use image::GenericImageView;
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
-    Backends, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
     BindingResource, BindingType, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages,
-    Color, CommandEncoderDescriptor, CompareFunction, DepthBiasState, DepthStencilState, Device,
-    DeviceDescriptor, Extent3d, Face, Features, FilterMode, FragmentState, ImageDataLayout,
-    IndexFormat, InstanceDescriptor, LoadOp, MultisampleState, Operations,
-    PipelineLayoutDescriptor, PowerPreference, PresentMode, PrimitiveState,
-    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
-    RenderPipelineDescriptor, RequestAdapterOptions, Sampler, SamplerBindingType,
-    SamplerDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState,
-    SurfaceConfiguration, Texture, TextureDescriptor, TextureDimension, TextureFormat,
-    TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
-    VertexAttribute, VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
+    Color, CommandEncoderDescriptor, CompareFunction, DepthBiasState, DepthStencilState, Extent3d,
+    Face, FilterMode, FragmentState, ImageDataLayout, IndexFormat, LoadOp, Operations,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState, Texture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+    TextureViewDescriptor, TextureViewDimension, VertexAttribute, VertexBufferLayout, VertexFormat,
+    VertexState, VertexStepMode,
 };
 use wgpu_samples::{
     assets,
     camera::{Camera, CameraDescriptor, GpuCamera},
+    capture::{capture_frame, capture_poster, save_capture, PosterLayout},
+    config::Config,
+    depth::{create_depth_texture, DEPTH_FORMAT},
+    gpu::{GpuContext, GpuContextOptions},
+    msaa::{choose_sample_count, supported_sample_counts, MultisampleTarget},
+    surface::{DeviceLoss, FrameAcquirer, FrameError, WgpuSurface},
+    uniform_arena::UniformArena,
 };
 use winit::{
-    dpi::LogicalSize,
     event::{DeviceEvent, ElementState, Event, MouseScrollDelta, VirtualKeyCode, WindowEvent},
     event_loop::EventLoop,
     platform::run_return::EventLoopExtRunReturn,
-    window::{CursorGrabMode, WindowBuilder},
+    window::{CursorGrabMode, Window},
 };
 
 const SCREEN_WIDTH: u32 = 1280;
 const SCREEN_HEIGHT: u32 = 720;
 const TITLE: &str = "Light casters (directional)";
+const SCREENSHOT_NAME: &str = "light-casters-directional";
+const POSTER_NAME: &str = "light-casters-directional-poster";
 
 const VERTICES: [Vertex; 24] = [
     // Front
@@ -315,74 +320,82 @@ impl Light {
 }
 
 fn main() {
+    let config = Config::load();
+
     let mut event_loop = EventLoop::new();
 
-    let logical_size = LogicalSize::new(SCREEN_WIDTH, SCREEN_HEIGHT);
-    let window = WindowBuilder::new()
-        .with_inner_size(logical_size)
+    let window = config
+        .window_builder(SCREEN_WIDTH, SCREEN_HEIGHT)
         .with_title(TITLE)
         .with_visible(false)
         .build(&event_loop)
         .expect("failed to create window");
-    let physical_size = window.inner_size();
 
-    let instance = wgpu::Instance::new(InstanceDescriptor {
-        backends: Backends::PRIMARY,
+    let physical_size = window.inner_size();
+    let mut camera = Camera::new(&CameraDescriptor {
+        aspect_ratio: physical_size.width as f32 / physical_size.height as f32,
         ..Default::default()
     });
 
-    let surface = unsafe {
-        instance
-            .create_surface(&window)
-            .expect("failed to create surface")
-    };
+    // Everything made with a lost device has to be made again on a new one, so only the window and the camera
+    // outlive it.
+    while let Exit::DeviceLost = run(&mut event_loop, &window, &config, &mut camera) {
+        eprintln!("the device was lost, recreating GPU resources");
+    }
+}
 
-    let adapter = block_on(instance.request_adapter(&RequestAdapterOptions {
-        power_preference: PowerPreference::LowPower,
-        force_fallback_adapter: false,
-        compatible_surface: Some(&surface),
-    }))
-    .expect("failed to get a suitable adapter");
-
-    let (device, queue) = block_on(adapter.request_device(
-        &DeviceDescriptor {
-            label: Some("device"),
-            features: Features::empty(),
-            limits: adapter.limits(),
-        },
-        None,
-    ))
-    .expect("failed to get a device");
-
-    let surface_capabilities = surface.get_capabilities(&adapter);
-    let surface_format = if surface_capabilities
-        .formats
-        .contains(&TextureFormat::Rgba8Unorm)
-    {
-        TextureFormat::Rgba8Unorm
-    } else if surface_capabilities
-        .formats
-        .contains(&TextureFormat::Bgra8Unorm)
-    {
-        TextureFormat::Bgra8Unorm
-    } else {
-        surface_capabilities.formats[0]
-    };
+/// Why [`run`] returned.
+enum Exit {
+    Quit,
+    DeviceLost,
+}
 
-    let mut surface_config = SurfaceConfiguration {
-        usage: TextureUsages::RENDER_ATTACHMENT,
-        format: surface_format,
-        width: physical_size.width,
-        height: physical_size.height,
-        present_mode: PresentMode::Fifo,
-        alpha_mode: surface_capabilities.alpha_modes[0],
-        view_formats: Vec::new(),
-    };
+/// Set up the GPU and draw until the window is closed or the device is lost.
+fn run(
+    event_loop: &mut EventLoop<()>,
+    window: &Window,
+    config: &Config,
+    camera: &mut Camera,
+) -> Exit {
+    let GpuContext {
+        adapter,
+        device,
+        queue,
+        surface,
+        mut surface_config,
+        ..
+    } = GpuContext::new(
+        window,
+        &GpuContextOptions {
+            optional_surface_usages: TextureUsages::COPY_SRC,
+            ..config.gpu_context_options()
+        },
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("failed to set up the GPU: {}", e);
+        process::exit(1);
+    });
 
-    surface.configure(&device, &surface_config);
+    let device_loss = DeviceLoss::watch(&device);
+    let mut frame_acquirer = FrameAcquirer::new(surface_config.width, surface_config.height);
 
-    let (mut depth_texture, mut depth_texture_view) =
-        create_depth_texture(&device, physical_size.width, physical_size.height);
+    let sample_count = choose_sample_count(
+        config.sample_count,
+        &supported_sample_counts(&adapter, &device, surface_config.format, Some(DEPTH_FORMAT)),
+    );
+    let mut msaa_target = MultisampleTarget::new(
+        &device,
+        surface_config.format,
+        surface_config.width,
+        surface_config.height,
+        sample_count,
+    );
+    let (mut depth_texture, mut depth_texture_view) = create_depth_texture(
+        &device,
+        surface_config.width,
+        surface_config.height,
+        sample_count,
+    );
 
     let scene_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
         label: Some("bind_group_layout::scene"),
@@ -410,19 +423,7 @@ fn main() {
         ],
     });
 
-    let model_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: Some("bind_group_layout::model"),
-        entries: &[BindGroupLayoutEntry {
-            binding: 0,
-            visibility: ShaderStages::VERTEX,
-            ty: BindingType::Buffer {
-                ty: BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: BufferSize::new(size_of::<Model>() as u64),
-            },
-            count: None,
-        }],
-    });
+    let mut model_arena = UniformArena::<Model>::new(&device, "model", ShaderStages::VERTEX);
 
     let material_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
         label: Some("bind_group_layout::material"),
@@ -476,7 +477,7 @@ fn main() {
         label: Some("pipeline_layout::model"),
         bind_group_layouts: &[
             &scene_bind_group_layout,
-            &model_bind_group_layout,
+            model_arena.get_bind_group_layout(),
             &material_bind_group_layout,
         ],
         push_constant_ranges: &[],
@@ -530,17 +531,17 @@ fn main() {
             ..Default::default()
         },
         depth_stencil: Some(DepthStencilState {
-            format: TextureFormat::Depth32Float,
+            format: DEPTH_FORMAT,
             depth_write_enabled: true,
             depth_compare: CompareFunction::Less,
             stencil: StencilState::default(),
             bias: DepthBiasState::default(),
         }),
-        multisample: MultisampleState::default(),
+        multisample: msaa_target.multisample_state(),
         fragment: Some(FragmentState {
             module: &model_shader_module,
             entry_point: "fs_main",
-            targets: &[Some(surface_format.into())],
+            targets: &[Some(surface_config.format.into())],
         }),
         multiview: None,
     });
@@ -569,33 +570,6 @@ fn main() {
         })
         .collect::<Vec<_>>();
 
-    let cube_ubos = models
-        .iter()
-        .enumerate()
-        .map(|(i, cube)| {
-            device.create_buffer_init(&BufferInitDescriptor {
-                label: Some(&format!("vbo::cube_{}", i)),
-                contents: cast_slice(&[*cube]),
-                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-            })
-        })
-        .collect::<Vec<_>>();
-
-    let cube_bind_groups = cube_ubos
-        .iter()
-        .enumerate()
-        .map(|(i, ubo)| {
-            device.create_bind_group(&BindGroupDescriptor {
-                label: Some(&format!("bind_group::cube_{}", i)),
-                layout: &model_bind_group_layout,
-                entries: &[BindGroupEntry {
-                    binding: 0,
-                    resource: ubo.as_entire_binding(),
-                }],
-            })
-        })
-        .collect::<Vec<_>>();
-
     let diffuse_bytes = assets::load("assets/container2.png").expect("unable to open file");
     let diffuse_image = image::load_from_memory(&diffuse_bytes).expect("unable to load image");
     let diffuse_image_data = diffuse_image.to_rgba8();
@@ -733,11 +707,6 @@ fn main() {
         Vec4::new(1.0, 1.0, 1.0, 1.0),
     );
 
-    let mut camera = Camera::new(&CameraDescriptor {
-        aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
-        ..Default::default()
-    });
-
     window.set_cursor_visible(false);
     window
         .set_cursor_grab(CursorGrabMode::Confined)
@@ -753,6 +722,8 @@ fn main() {
         let dt = (current_time - last_time).as_secs_f32();
         last_time = current_time;
 
+        let mut take_screenshot = false;
+        let mut take_poster = false;
         event_loop.run_return(|event, _, control_flow| {
             control_flow.set_wait();
 
@@ -762,26 +733,36 @@ fn main() {
                         WindowEvent::CloseRequested => running = false,
 
                         WindowEvent::Resized(size) => {
-                            surface_config.width = size.width;
-                            surface_config.height = size.height;
-                            surface.configure(&device, &surface_config);
+                            frame_acquirer.resize(size.width, size.height);
+                            if frame_acquirer.is_minimized() {
+                                return;
+                            }
 
+                            msaa_target.resize(&device, size.width, size.height);
                             (depth_texture, depth_texture_view) = create_depth_texture(
                                 &device,
-                                surface_config.width,
-                                surface_config.height,
+                                size.width,
+                                size.height,
+                                sample_count,
                             );
                         }
 
                         WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            surface_config.width = new_inner_size.width;
-                            surface_config.height = new_inner_size.height;
-                            surface.configure(&device, &surface_config);
+                            frame_acquirer.resize(new_inner_size.width, new_inner_size.height);
+                            if frame_acquirer.is_minimized() {
+                                return;
+                            }
 
+                            msaa_target.resize(
+                                &device,
+                                new_inner_size.width,
+                                new_inner_size.height,
+                            );
                             (depth_texture, depth_texture_view) = create_depth_texture(
                                 &device,
-                                surface_config.width,
-                                surface_config.height,
+                                new_inner_size.width,
+                                new_inner_size.height,
+                                sample_count,
                             );
                         }
 
@@ -815,6 +796,12 @@ fn main() {
                                     VirtualKeyCode::D if input.state == ElementState::Pressed => {
                                         camera.skew_right(dt);
                                     }
+                                    VirtualKeyCode::F12 if input.state == ElementState::Pressed => {
+                                        take_screenshot = true;
+                                    }
+                                    VirtualKeyCode::F11 if input.state == ElementState::Pressed => {
+                                        take_poster = true;
+                                    }
 
                                     _ => (),
                                 }
@@ -841,76 +828,111 @@ fn main() {
             }
         });
 
-        queue.write_buffer(&camera_ubo, 0, cast_slice(&[camera.get_gpu_camera()]));
         queue.write_buffer(&lighting_ubo, 0, cast_slice(&[light]));
 
-        let frame = surface
-            .get_current_texture()
-            .expect("failed to get current swapchain texture");
-        let output_texture_view = frame.texture.create_view(&TextureViewDescriptor::default());
+        model_arena.clear();
+        let cube_offsets = models
+            .iter()
+            .map(|model| model_arena.push(model))
+            .collect::<Vec<_>>();
+        model_arena.upload(&device, &queue);
 
-        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
-            label: Some("command_encoder"),
-        });
+        if device_loss.is_lost() {
+            return Exit::DeviceLost;
+        }
 
-        {
-            let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: Some("render_pass"),
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &output_texture_view,
-                    resolve_target: None,
-                    ops: Operations {
-                        load: LoadOp::Clear(Color::BLACK),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                    view: &depth_texture_view,
-                    depth_ops: Some(Operations {
-                        load: LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: Some(Operations {
-                        load: LoadOp::Clear(0),
-                        store: true,
-                    }),
-                }),
+        // Draws the scene as `camera` sees it into `output_texture_view`, which is the frame unless it's being
+        // captured.
+        let draw = |camera: &Camera, output_texture_view: &TextureView| {
+            queue.write_buffer(&camera_ubo, 0, cast_slice(&[camera.get_gpu_camera()]));
+
+            let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("command_encoder"),
             });
 
-            rpass.set_bind_group(0, &scene_bind_group, &[]);
+            {
+                let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: Some("render_pass"),
+                    color_attachments: &[Some(msaa_target.color_attachment(
+                        output_texture_view,
+                        Operations {
+                            load: LoadOp::Clear(Color::BLACK),
+                            store: true,
+                        },
+                    ))],
+                    depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                        view: &depth_texture_view,
+                        depth_ops: Some(Operations {
+                            load: LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: Some(Operations {
+                            load: LoadOp::Clear(0),
+                            store: true,
+                        }),
+                    }),
+                });
+
+                rpass.set_bind_group(0, &scene_bind_group, &[]);
 
-            rpass.set_pipeline(&model_pipeline);
-            for bind_group in &cube_bind_groups {
-                rpass.set_bind_group(1, &bind_group, &[]);
-                rpass.set_bind_group(2, &cube_material_bind_group, &[]);
-                rpass.set_vertex_buffer(0, cube_vbo.slice(..));
-                rpass.set_index_buffer(cube_ibo.slice(..), IndexFormat::Uint32);
-                rpass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+                rpass.set_pipeline(&model_pipeline);
+                for offset in &cube_offsets {
+                    rpass.set_bind_group(1, model_arena.get_bind_group(), &[*offset]);
+                    rpass.set_bind_group(2, &cube_material_bind_group, &[]);
+                    rpass.set_vertex_buffer(0, cube_vbo.slice(..));
+                    rpass.set_index_buffer(cube_ibo.slice(..), IndexFormat::Uint32);
+                    rpass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+                }
             }
+
+            queue.submit(once(encoder.finish()));
+        };
+
+        let mut target = WgpuSurface {
+            surface: &surface,
+            device: &device,
+            config: &mut surface_config,
+        };
+        let frame = match frame_acquirer.acquire(&mut target) {
+            Ok(Some(frame)) => frame,
+            // Minimized, or the surface needs another frame to recover.
+            Ok(None) => continue,
+            Err(FrameError::DeviceLost) => return Exit::DeviceLost,
+            Err(e) => panic!("failed to get current swapchain texture: {}", e),
+        };
+        let output_texture_view = frame.texture.create_view(&TextureViewDescriptor::default());
+
+        draw(camera, &output_texture_view);
+
+        if take_screenshot {
+            let result = capture_frame(&device, &queue, &frame.texture, |view| {
+                draw(camera, view);
+            });
+            save_capture("screenshot", SCREENSHOT_NAME, result);
         }
 
-        queue.submit(once(encoder.finish()));
         frame.present();
-    }
-}
-
-fn create_depth_texture(device: &Device, width: u32, height: u32) -> (Texture, TextureView) {
-    let texture = device.create_texture(&TextureDescriptor {
-        label: Some("texture::depth"),
-        size: Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        },
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: TextureDimension::D2,
-        format: TextureFormat::Depth32Float,
-        usage: TextureUsages::RENDER_ATTACHMENT,
-        view_formats: &[],
-    });
 
-    let texture_view = texture.create_view(&TextureViewDescriptor::default());
+        if take_poster {
+            let layout = PosterLayout {
+                columns: config.poster_tiles.0,
+                rows: config.poster_tiles.1,
+                tile_width: surface_config.width,
+                tile_height: surface_config.height,
+            };
+            let result = capture_poster(
+                &device,
+                &queue,
+                surface_config.format,
+                layout,
+                camera,
+                |camera, view| {
+                    draw(camera, view);
+                },
+            );
+            save_capture("poster", POSTER_NAME, result);
+        }
+    }
 
-    (texture, texture_view)
+    Exit::Quit
 }
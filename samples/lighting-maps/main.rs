@@ -1,40 +1,43 @@
-use std::{borrow::Cow, iter::once, mem::size_of, time::Instant};
+use std::{borrow::Cow, iter::once, mem::size_of, process, time::Instant};
 
 use bytemuck::cast_slice;
 use bytemuck_derive::{Pod, Zeroable};
-use futures::executor::block_on;
 use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
 use image::GenericImageView;
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
-    Backends, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
     BindingResource, BindingType, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages,
-    Color, CommandEncoderDescriptor, CompareFunction, DepthBiasState, DepthStencilState, Device,
-    DeviceDescriptor, Extent3d, Face, Features, FilterMode, FragmentState, ImageDataLayout,
-    IndexFormat, InstanceDescriptor, LoadOp, MultisampleState, Operations,
-    PipelineLayoutDescriptor, PowerPreference, PresentMode, PrimitiveState,
-    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
-    RenderPipelineDescriptor, RequestAdapterOptions, Sampler, SamplerBindingType,
-    SamplerDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState,
-    SurfaceConfiguration, Texture, TextureDescriptor, TextureDimension, TextureFormat,
-    TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
-    VertexAttribute, VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
+    Color, CommandEncoderDescriptor, CompareFunction, DepthBiasState, DepthStencilState, Extent3d,
+    Face, FilterMode, FragmentState, ImageDataLayout, IndexFormat, LoadOp, Operations,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState, Texture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+    TextureViewDescriptor, TextureViewDimension, VertexAttribute, VertexBufferLayout, VertexFormat,
+    VertexState, VertexStepMode,
 };
 use wgpu_samples::{
     assets,
     camera::{Camera, CameraDescriptor, GpuCamera},
+    capture::{capture_frame, capture_poster, save_capture, PosterLayout},
+    config::Config,
+    depth::{create_depth_texture, DEPTH_FORMAT},
+    gpu::{GpuContext, GpuContextOptions},
+    msaa::{choose_sample_count, supported_sample_counts, MultisampleTarget},
 };
 use winit::{
-    dpi::LogicalSize,
     event::{DeviceEvent, ElementState, Event, MouseScrollDelta, VirtualKeyCode, WindowEvent},
     event_loop::EventLoop,
     platform::run_return::EventLoopExtRunReturn,
-    window::{CursorGrabMode, WindowBuilder},
+    window::CursorGrabMode,
 };
 
 const SCREEN_WIDTH: u32 = 1280;
 const SCREEN_HEIGHT: u32 = 720;
 const TITLE: &str = "Lighting maps";
+const SCREENSHOT_NAME: &str = "lighting-maps";
+const POSTER_NAME: &str = "lighting-maps-poster";
 
 const VERTICES: [Vertex; 24] = [
     // Front
@@ -302,74 +305,53 @@ impl Light {
 }
 
 fn main() {
+    let config = Config::load();
+
     let mut event_loop = EventLoop::new();
 
-    let logical_size = LogicalSize::new(SCREEN_WIDTH, SCREEN_HEIGHT);
-    let window = WindowBuilder::new()
-        .with_inner_size(logical_size)
+    let window = config
+        .window_builder(SCREEN_WIDTH, SCREEN_HEIGHT)
         .with_title(TITLE)
         .with_visible(false)
         .build(&event_loop)
         .expect("failed to create window");
-    let physical_size = window.inner_size();
-
-    let instance = wgpu::Instance::new(InstanceDescriptor {
-        backends: Backends::PRIMARY,
-        ..Default::default()
-    });
-
-    let surface = unsafe {
-        instance
-            .create_surface(&window)
-            .expect("failed to create surface")
-    };
 
-    let adapter = block_on(instance.request_adapter(&RequestAdapterOptions {
-        power_preference: PowerPreference::LowPower,
-        force_fallback_adapter: false,
-        compatible_surface: Some(&surface),
-    }))
-    .expect("failed to get a suitable adapter");
-
-    let (device, queue) = block_on(adapter.request_device(
-        &DeviceDescriptor {
-            label: Some("device"),
-            features: Features::empty(),
-            limits: adapter.limits(),
+    let GpuContext {
+        adapter,
+        device,
+        queue,
+        surface,
+        mut surface_config,
+        ..
+    } = GpuContext::new(
+        &window,
+        &GpuContextOptions {
+            optional_surface_usages: TextureUsages::COPY_SRC,
+            ..config.gpu_context_options()
         },
-        None,
-    ))
-    .expect("failed to get a device");
-
-    let surface_capabilities = surface.get_capabilities(&adapter);
-    let surface_format = if surface_capabilities
-        .formats
-        .contains(&TextureFormat::Rgba8Unorm)
-    {
-        TextureFormat::Rgba8Unorm
-    } else if surface_capabilities
-        .formats
-        .contains(&TextureFormat::Bgra8Unorm)
-    {
-        TextureFormat::Bgra8Unorm
-    } else {
-        surface_capabilities.formats[0]
-    };
-
-    let mut surface_config = SurfaceConfiguration {
-        usage: TextureUsages::RENDER_ATTACHMENT,
-        format: surface_format,
-        width: physical_size.width,
-        height: physical_size.height,
-        present_mode: PresentMode::Fifo,
-        alpha_mode: surface_capabilities.alpha_modes[0],
-        view_formats: Vec::new(),
-    };
-
-    surface.configure(&device, &surface_config);
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("failed to set up the GPU: {}", e);
+        process::exit(1);
+    });
 
-    let (mut depth_texture, mut depth_texture_view) =
-        create_depth_texture(&device, physical_size.width, physical_size.height);
+    let sample_count = choose_sample_count(
+        config.sample_count,
+        &supported_sample_counts(&adapter, &device, surface_config.format, Some(DEPTH_FORMAT)),
+    );
+    let mut msaa_target = MultisampleTarget::new(
+        &device,
+        surface_config.format,
+        surface_config.width,
+        surface_config.height,
+        sample_count,
+    );
+    let (mut depth_texture, mut depth_texture_view) = create_depth_texture(
+        &device,
+        surface_config.width,
+        surface_config.height,
+        sample_count,
+    );
 
     let scene_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
         label: Some("bind_group_layout::scene"),
@@ -542,17 +524,17 @@ fn main() {
             ..Default::default()
         },
         depth_stencil: Some(DepthStencilState {
-            format: TextureFormat::Depth32Float,
+            format: DEPTH_FORMAT,
             depth_write_enabled: true,
             depth_compare: CompareFunction::Less,
             stencil: StencilState::default(),
             bias: DepthBiasState::default(),
         }),
-        multisample: MultisampleState::default(),
+        multisample: msaa_target.multisample_state(),
         fragment: Some(FragmentState {
             module: &light_shader_module,
             entry_point: "fs_main",
-            targets: &[Some(surface_format.into())],
+            targets: &[Some(surface_config.format.into())],
         }),
         multiview: None,
     });
@@ -576,17 +558,17 @@ fn main() {
             ..Default::default()
         },
         depth_stencil: Some(DepthStencilState {
-            format: TextureFormat::Depth32Float,
+            format: DEPTH_FORMAT,
             depth_write_enabled: true,
             depth_compare: CompareFunction::Less,
             stencil: StencilState::default(),
             bias: DepthBiasState::default(),
         }),
-        multisample: MultisampleState::default(),
+        multisample: msaa_target.multisample_state(),
         fragment: Some(FragmentState {
             module: &model_shader_module,
             entry_point: "fs_main",
-            targets: &[Some(surface_format.into())],
+            targets: &[Some(surface_config.format.into())],
         }),
         multiview: None,
     });
@@ -810,7 +792,7 @@ fn main() {
     );
 
     let mut camera = Camera::new(&CameraDescriptor {
-        aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
+        aspect_ratio: surface_config.width as f32 / surface_config.height as f32,
         ..Default::default()
     });
 
@@ -829,6 +811,8 @@ fn main() {
         let dt = (current_time - last_time).as_secs_f32();
         last_time = current_time;
 
+        let mut take_screenshot = false;
+        let mut take_poster = false;
         event_loop.run_return(|event, _, control_flow| {
             control_flow.set_wait();
 
@@ -842,10 +826,16 @@ fn main() {
                             surface_config.height = size.height;
                             surface.configure(&device, &surface_config);
 
+                            msaa_target.resize(
+                                &device,
+                                surface_config.width,
+                                surface_config.height,
+                            );
                             (depth_texture, depth_texture_view) = create_depth_texture(
                                 &device,
                                 surface_config.width,
                                 surface_config.height,
+                                sample_count,
                             );
                         }
 
@@ -854,10 +844,16 @@ fn main() {
                             surface_config.height = new_inner_size.height;
                             surface.configure(&device, &surface_config);
 
+                            msaa_target.resize(
+                                &device,
+                                surface_config.width,
+                                surface_config.height,
+                            );
                             (depth_texture, depth_texture_view) = create_depth_texture(
                                 &device,
                                 surface_config.width,
                                 surface_config.height,
+                                sample_count,
                             );
                         }
 
@@ -891,6 +887,12 @@ fn main() {
                                     VirtualKeyCode::D if input.state == ElementState::Pressed => {
                                         camera.skew_right(dt);
                                     }
+                                    VirtualKeyCode::F12 if input.state == ElementState::Pressed => {
+                                        take_screenshot = true;
+                                    }
+                                    VirtualKeyCode::F11 if input.state == ElementState::Pressed => {
+                                        take_poster = true;
+                                    }
 
                                     _ => (),
                                 }
@@ -917,81 +919,94 @@ fn main() {
             }
         });
 
-        queue.write_buffer(&camera_ubo, 0, cast_slice(&[camera.get_gpu_camera()]));
         queue.write_buffer(&lighting_ubo, 0, cast_slice(&[light]));
 
+        // Draws the scene as `camera` sees it into `output_texture_view`, which is the frame unless it's being
+        // captured.
+        let draw = |camera: &Camera, output_texture_view: &TextureView| {
+            queue.write_buffer(&camera_ubo, 0, cast_slice(&[camera.get_gpu_camera()]));
+
+            let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("command_encoder"),
+            });
+
+            {
+                let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: Some("render_pass"),
+                    color_attachments: &[Some(msaa_target.color_attachment(
+                        output_texture_view,
+                        Operations {
+                            load: LoadOp::Clear(Color::BLACK),
+                            store: true,
+                        },
+                    ))],
+                    depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                        view: &depth_texture_view,
+                        depth_ops: Some(Operations {
+                            load: LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: Some(Operations {
+                            load: LoadOp::Clear(0),
+                            store: true,
+                        }),
+                    }),
+                });
+
+                rpass.set_bind_group(0, &scene_bind_group, &[]);
+
+                rpass.set_pipeline(&light_pipeline);
+                rpass.set_bind_group(1, &light_cube_bind_group, &[]);
+                rpass.set_bind_group(2, &light_cube_color_bind_group, &[]);
+                rpass.set_vertex_buffer(0, light_cube_vbo.slice(..));
+                rpass.set_index_buffer(light_cube_ibo.slice(..), IndexFormat::Uint32);
+                rpass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+
+                rpass.set_pipeline(&model_pipeline);
+                rpass.set_bind_group(1, &cube_bind_group, &[]);
+                rpass.set_bind_group(2, &cube_material_bind_group, &[]);
+                rpass.set_vertex_buffer(0, cube_vbo.slice(..));
+                rpass.set_index_buffer(cube_ibo.slice(..), IndexFormat::Uint32);
+                rpass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+            }
+
+            queue.submit(once(encoder.finish()));
+        };
+
         let frame = surface
             .get_current_texture()
             .expect("failed to get current swapchain texture");
         let output_texture_view = frame.texture.create_view(&TextureViewDescriptor::default());
 
-        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
-            label: Some("command_encoder"),
-        });
+        draw(&camera, &output_texture_view);
 
-        {
-            let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: Some("render_pass"),
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &output_texture_view,
-                    resolve_target: None,
-                    ops: Operations {
-                        load: LoadOp::Clear(Color::BLACK),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                    view: &depth_texture_view,
-                    depth_ops: Some(Operations {
-                        load: LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: Some(Operations {
-                        load: LoadOp::Clear(0),
-                        store: true,
-                    }),
-                }),
+        if take_screenshot {
+            let result = capture_frame(&device, &queue, &frame.texture, |view| {
+                draw(&camera, view);
             });
-
-            rpass.set_bind_group(0, &scene_bind_group, &[]);
-
-            rpass.set_pipeline(&light_pipeline);
-            rpass.set_bind_group(1, &light_cube_bind_group, &[]);
-            rpass.set_bind_group(2, &light_cube_color_bind_group, &[]);
-            rpass.set_vertex_buffer(0, light_cube_vbo.slice(..));
-            rpass.set_index_buffer(light_cube_ibo.slice(..), IndexFormat::Uint32);
-            rpass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
-
-            rpass.set_pipeline(&model_pipeline);
-            rpass.set_bind_group(1, &cube_bind_group, &[]);
-            rpass.set_bind_group(2, &cube_material_bind_group, &[]);
-            rpass.set_vertex_buffer(0, cube_vbo.slice(..));
-            rpass.set_index_buffer(cube_ibo.slice(..), IndexFormat::Uint32);
-            rpass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+            save_capture("screenshot", SCREENSHOT_NAME, result);
         }
 
-        queue.submit(once(encoder.finish()));
         frame.present();
-    }
-}
-
-fn create_depth_texture(device: &Device, width: u32, height: u32) -> (Texture, TextureView) {
-    let texture = device.create_texture(&TextureDescriptor {
-        label: Some("texture::depth"),
-        size: Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        },
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: TextureDimension::D2,
-        format: TextureFormat::Depth32Float,
-        usage: TextureUsages::RENDER_ATTACHMENT,
-        view_formats: &[],
-    });
-
-    let texture_view = texture.create_view(&TextureViewDescriptor::default());
 
-    (texture, texture_view)
+        if take_poster {
+            let layout = PosterLayout {
+                columns: config.poster_tiles.0,
+                rows: config.poster_tiles.1,
+                tile_width: surface_config.width,
+                tile_height: surface_config.height,
+            };
+            let result = capture_poster(
+                &device,
+                &queue,
+                surface_config.format,
+                layout,
+                &camera,
+                |camera, view| {
+                    draw(camera, view);
+                },
+            );
+            save_capture("poster", POSTER_NAME, result);
+        }
+    }
 }
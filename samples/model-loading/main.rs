@@ -1,43 +1,71 @@
 // todo: assimp stuff.
 
-use std::{borrow::Cow, iter::once, mem::size_of, path::Path, time::Instant};
+use std::{
+    borrow::Cow,
+    iter::once,
+    mem::size_of,
+    path::Path,
+    process,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use bytemuck::cast_slice;
 use bytemuck_derive::{Pod, Zeroable};
-use futures::executor::block_on;
 use glam::{Mat4, Quat, Vec2, Vec3};
-use image::GenericImageView;
+use image::RgbaImage;
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
-    Adapter, Backends, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
-    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
-    BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, Color, CommandEncoderDescriptor,
-    CompareFunction, DepthBiasState, DepthStencilState, Device, DeviceDescriptor, Extent3d, Face,
-    FilterMode, FragmentState, FrontFace, ImageDataLayout, IndexFormat, Instance,
-    InstanceDescriptor, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor,
-    PowerPreference, PresentMode, PrimitiveState, Queue, RenderPass, RenderPassColorAttachment,
-    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipelineDescriptor,
-    RequestAdapterOptions, Sampler, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor,
-    ShaderSource, ShaderStages, StencilState, Surface, SurfaceConfiguration, TextureDescriptor,
-    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
-    TextureViewDescriptor, TextureViewDimension, VertexAttribute, VertexBufferLayout, VertexFormat,
-    VertexState, VertexStepMode,
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource, Buffer,
+    BufferDescriptor, BufferUsages, Color, CommandEncoderDescriptor, Device, Extent3d, Features,
+    FilterMode, ImageDataLayout, IndexFormat, LoadOp, Operations, PipelineLayout,
+    PipelineLayoutDescriptor, PolygonMode, Queue, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline, Sampler,
+    SamplerDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource, SurfaceConfiguration,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+    TextureViewDescriptor, VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode,
 };
 use wgpu_samples::{
+    actions::{Action, ActionMap, BINDINGS_FILE},
     assets,
-    camera::{Camera, CameraDescriptor, GpuCamera},
+    bookmarks::{BookmarkEvent, Bookmarks},
+    camera::{Camera, CameraDescriptor, CameraMode, GpuCamera},
+    camera_path::{CameraPath, CameraPathPlayer},
+    capture::{capture_frame, capture_poster, save_capture, PosterLayout},
+    config::Config,
+    controller::{FlyController, FlyControllerDescriptor},
+    depth::{DepthMode, DEPTH_FORMAT},
+    gpu::{GpuContext, GpuContextOptions},
+    input::InputState,
+    msaa::choose_sample_count,
+    pacing::{next_present_mode, FrameLimiter, DEFAULT_TARGET_FPS},
+    pipeline::{PipelineBuilder, PipelineCache, PipelineError},
+    profiler::Profiler,
+    recording::{start_recording, stop_recording, Recorder},
+    reflection::ShaderBindings,
+    render_graph::{RenderGraph, ResourceId, TextureSize, TransientDescriptor},
+    surface::{DeviceLoss, FrameAcquirer, FrameError, WgpuSurface},
+    viewport::Viewport,
 };
 use winit::{
-    dpi::LogicalSize,
-    event::{DeviceEvent, ElementState, Event, MouseScrollDelta, VirtualKeyCode, WindowEvent},
+    dpi::PhysicalSize,
+    event::{Event, WindowEvent},
     event_loop::EventLoop,
     platform::run_return::EventLoopExtRunReturn,
-    window::{CursorGrabMode, Window, WindowBuilder},
+    window::{CursorGrabMode, Window},
 };
 
 const SCREEN_WIDTH: u32 = 1280;
 const SCREEN_HEIGHT: u32 = 720;
 const TITLE: &'static str = "Model loading";
+const BOOKMARKS_FILE: &str = "model-loading.bookmarks";
+const CAMERA_PATH_FILE: &str = "model-loading.camera-path";
+const CAMERA_PATH_KEYFRAME_INTERVAL: f32 = 0.25;
+const SCREENSHOT_NAME: &str = "model-loading";
+const POSTER_NAME: &str = "model-loading-poster";
+const RECORDING_NAME: &str = "model-loading-recording";
+const PROFILE_FILE: &str = "model-loading.profile";
+const PROFILE_TITLE_INTERVAL: Duration = Duration::from_millis(500);
 
 #[derive(Debug, Default, Clone, Copy, Pod, Zeroable)]
 #[repr(C)]
@@ -100,9 +128,7 @@ struct Texture {
 }
 
 impl Texture {
-    fn from_bytes(bytes: &[u8], device: &Device, queue: &Queue, label: Option<&str>) -> Self {
-        let image = image::load_from_memory(&bytes).expect("unable to load image from bytes");
-        let image_data = image.to_rgba8();
+    fn from_image(image: &RgbaImage, device: &Device, queue: &Queue, label: Option<&str>) -> Self {
         let image_size = image.dimensions();
 
         let extent = Extent3d {
@@ -133,7 +159,7 @@ impl Texture {
 
         queue.write_texture(
             texture.as_image_copy(),
-            &image_data,
+            image,
             ImageDataLayout {
                 offset: 0,
                 bytes_per_row: Some(4 * image_size.0),
@@ -246,373 +272,729 @@ impl Model {
         let indices = vec![0, 1, 2, 0, 2, 3];
 
         let label = path.as_ref().to_str();
-        let texture_bytes = assets::load(path.as_ref()).expect("failed to load asset");
-        let texture = Texture::from_bytes(&texture_bytes, &device, &queue, label);
-        let material = Material::new(&device, &layout, texture, label);
+        let image = assets::load_image_or_missing(path.as_ref());
+        let texture = Texture::from_image(&image, device, queue, label);
+        let material = Material::new(device, layout, texture, label);
 
         let material_id = materials.len();
         materials.push(material);
 
-        let mesh = Mesh::new(&device, vertices, indices, material_id, label);
+        let mesh = Mesh::new(device, vertices, indices, material_id, label);
         meshes.push(mesh);
 
         Self { materials, meshes }
     }
 }
 
-fn main() {
-    let mut event_loop = EventLoop::new();
+/// The passes in the renderer's graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pass {
+    Scene,
+}
 
-    let logical_size = LogicalSize::new(SCREEN_WIDTH, SCREEN_HEIGHT);
-    let window = WindowBuilder::new()
-        .with_inner_size(logical_size)
-        .with_title(TITLE)
-        .with_visible(false)
-        .build(&event_loop)
-        .expect("failed to create a window");
-    let physical_size = window.inner_size();
+impl Pass {
+    /// The name the pass is profiled under.
+    fn name(self) -> &'static str {
+        match self {
+            Pass::Scene => "scene",
+        }
+    }
+}
 
-    let (_instance, adapter, device, queue, surface) = setup_gpu(&window);
-    let mut surface_config = setup_surface(
-        &surface,
-        physical_size.width,
-        physical_size.height,
-        &adapter,
-        &device,
-    );
+/// The pipelines the renderer draws with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PipelineKind {
+    Solid,
+    Wireframe,
+}
 
-    let global_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: Some("bind_group_layout::global"),
-        entries: &[BindGroupLayoutEntry {
-            binding: 0,
-            visibility: ShaderStages::VERTEX,
-            ty: BindingType::Buffer {
-                ty: BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: BufferSize::new(size_of::<GpuCamera>() as u64),
-            },
-            count: None,
-        }],
-    });
+/// Everything created on the device, so that it can all be recreated on a new device if the old one is lost.
+struct Renderer {
+    shader_module: ShaderModule,
+    pipeline_layout: PipelineLayout,
+    color_format: TextureFormat,
+    depth_mode: DepthMode,
+    sample_count: u32,
+    pipelines: PipelineCache<PipelineKind>,
+    graph: RenderGraph<Pass>,
+    /// The multisampled color target, or `None` when drawing straight to the surface without MSAA.
+    color: Option<ResourceId>,
+    depth: ResourceId,
+    camera_ubo: Buffer,
+    global_bind_group: BindGroup,
+    model: Model,
+    transform_bind_group: BindGroup,
+    profiler: Profiler,
+}
 
-    let transform_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: Some("bind_group_layout::transform"),
-        entries: &[BindGroupLayoutEntry {
-            binding: 0,
-            visibility: ShaderStages::VERTEX,
-            ty: BindingType::Buffer {
-                ty: BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: BufferSize::new(size_of::<Transform>() as u64),
-            },
-            count: None,
-        }],
-    });
+impl Renderer {
+    fn new(
+        device: &Device,
+        queue: &Queue,
+        surface_config: &SurfaceConfiguration,
+        depth_mode: DepthMode,
+        sample_count: u32,
+    ) -> Self {
+        // The layouts are read from the shader, so they always match its bindings.
+        let shader_src = include_str!("shader.wgsl");
+        let bindings = ShaderBindings::from_wgsl(shader_src).expect("invalid shader");
+        let global_bind_group_layout =
+            bindings.create_bind_group_layout(device, "bind_group_layout::global", 0);
+        let transform_bind_group_layout =
+            bindings.create_bind_group_layout(device, "bind_group_layout::transform", 1);
+        let texture_bind_group_layout =
+            bindings.create_bind_group_layout(device, "bind_group_layout::texture", 2);
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("pipeline_layout"),
+            bind_group_layouts: &[
+                &global_bind_group_layout,
+                &transform_bind_group_layout,
+                &texture_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
 
-    let texture_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: Some("bind_group_layout::texture"),
-        entries: &[
-            BindGroupLayoutEntry {
+        // Define pipelines.
+
+        let mut graph = RenderGraph::new();
+        let surface = graph.surface();
+        let color = (sample_count > 1).then(|| {
+            graph.create_transient(TransientDescriptor {
+                label: "texture::multisample",
+                format: surface_config.format,
+                size: TextureSize::Relative(1.0),
+                sample_count,
+            })
+        });
+        let depth = graph.create_transient(TransientDescriptor {
+            label: "texture::depth",
+            format: DEPTH_FORMAT,
+            size: TextureSize::Relative(1.0),
+            sample_count,
+        });
+        let scene_writes = color
+            .into_iter()
+            .chain([depth, surface])
+            .collect::<Vec<_>>();
+        graph.add_pass(Pass::Scene, &[], &scene_writes);
+        graph
+            .prepare(device, surface_config.width, surface_config.height)
+            .expect("invalid render graph");
+
+        let shader_module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("shader_module"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(shader_src)),
+        });
+
+        let camera_ubo = device.create_buffer(&BufferDescriptor {
+            label: Some("ubo::camera"),
+            size: size_of::<GpuCamera>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let global_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("bind_group::global"),
+            layout: &global_bind_group_layout,
+            entries: &[BindGroupEntry {
                 binding: 0,
-                visibility: ShaderStages::FRAGMENT,
-                ty: BindingType::Sampler(SamplerBindingType::Filtering),
-                count: None,
-            },
-            BindGroupLayoutEntry {
-                binding: 1,
-                visibility: ShaderStages::FRAGMENT,
-                ty: BindingType::Texture {
-                    sample_type: TextureSampleType::Float { filterable: true },
-                    view_dimension: TextureViewDimension::D2,
-                    multisampled: false,
-                },
-                count: None,
-            },
-        ],
-    });
+                resource: camera_ubo.as_entire_binding(),
+            }],
+        });
 
-    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-        label: Some("pipeline_layout"),
-        bind_group_layouts: &[
-            &global_bind_group_layout,
-            &transform_bind_group_layout,
+        let model = Model::load(
+            "assets/awesomeface.png",
+            device,
+            queue,
             &texture_bind_group_layout,
-        ],
-        push_constant_ranges: &[],
-    });
+        );
 
-    // Define pipelines.
+        let transform = Transform::new(Mat4::from_scale_rotation_translation(
+            Vec3::ONE,
+            Quat::IDENTITY,
+            Vec3::ZERO,
+        ));
+
+        let transform_ubo = device.create_buffer(&BufferDescriptor {
+            label: Some("ubo::transform"),
+            size: size_of::<Transform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
 
-    let (mut depth_texture, mut depth_texture_view) =
-        create_depth_texture(&device, physical_size.width, physical_size.height);
+        let transform_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("bind_group::transform"),
+            layout: &transform_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: transform_ubo.as_entire_binding(),
+            }],
+        });
 
-    let shader_src = include_str!("shader.wgsl");
-    let shader_module = device.create_shader_module(ShaderModuleDescriptor {
-        label: Some("shader_module"),
-        source: ShaderSource::Wgsl(Cow::Borrowed(shader_src)),
-    });
+        queue.write_buffer(&transform_ubo, 0, cast_slice(&[transform]));
+
+        let mut renderer = Self {
+            shader_module,
+            pipeline_layout,
+            color_format: surface_config.format,
+            depth_mode,
+            sample_count,
+            pipelines: PipelineCache::new(),
+            graph,
+            color,
+            depth,
+            camera_ubo,
+            global_bind_group,
+            model,
+            transform_bind_group,
+            profiler: Profiler::new(device, queue),
+        };
+        renderer
+            .pipeline(device, PipelineKind::Solid)
+            .expect("invalid scene pipeline");
 
-    let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-        label: Some("render_pipeline"),
-        layout: Some(&pipeline_layout),
-        vertex: VertexState {
-            module: &shader_module,
-            entry_point: "vs_main",
-            buffers: &[Vertex::layout()],
-        },
-        primitive: PrimitiveState {
-            front_face: FrontFace::Ccw,
-            cull_mode: Some(Face::Back),
-            ..Default::default()
-        },
-        depth_stencil: Some(DepthStencilState {
-            format: TextureFormat::Depth32Float,
-            depth_write_enabled: true,
-            depth_compare: CompareFunction::Less,
-            stencil: StencilState::default(),
-            bias: DepthBiasState::default(),
-        }),
-        multisample: MultisampleState::default(),
-        fragment: Some(FragmentState {
-            module: &shader_module,
-            entry_point: "fs_main",
-            targets: &[Some(surface_config.format.into())],
-        }),
-        multiview: None,
-    });
+        renderer
+    }
 
-    // Game objects.
-    let mut camera = Camera::new(&CameraDescriptor {
-        aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
-        ..Default::default()
-    });
+    /// The pipeline of the given kind, built the first time it's needed. Wireframes need line polygons, which are an
+    /// optional feature, so they aren't available everywhere.
+    fn pipeline(
+        &mut self,
+        device: &Device,
+        kind: PipelineKind,
+    ) -> Result<Arc<RenderPipeline>, PipelineError> {
+        let builder = PipelineBuilder::new(&self.shader_module, self.color_format)
+            .layout(&self.pipeline_layout)
+            .vertex_buffer(Vertex::layout())
+            .depth_mode(self.depth_mode)
+            .sample_count(self.sample_count);
+        let builder = match kind {
+            PipelineKind::Solid => builder.label("render_pipeline"),
+            PipelineKind::Wireframe => builder
+                .label("render_pipeline::wireframe")
+                .cull_mode(None)
+                .polygon_mode(PolygonMode::Line),
+        };
 
-    let camera_ubo = device.create_buffer(&BufferDescriptor {
-        label: Some("ubo::camera"),
-        size: size_of::<GpuCamera>() as u64,
-        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
+        self.pipelines.get_or_build(device, kind, &builder)
+    }
 
-    let global_bind_group = device.create_bind_group(&BindGroupDescriptor {
-        label: Some("bind_group::global"),
-        layout: &global_bind_group_layout,
-        entries: &[BindGroupEntry {
-            binding: 0,
-            resource: camera_ubo.as_entire_binding(),
-        }],
-    });
+    fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.graph
+            .prepare(device, width, height)
+            .expect("invalid render graph");
+    }
 
-    let model = Model::load(
-        "assets/awesomeface.png",
-        &device,
-        &queue,
-        &texture_bind_group_layout,
-    );
+    /// Draw the scene into `output_texture_view`. Only frames drawn with `profile` set are timed, so that drawing a
+    /// frame again for a capture doesn't count towards the profile.
+    fn render(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        output_texture_view: &TextureView,
+        camera: &Camera,
+        wireframe: bool,
+        profile: bool,
+    ) {
+        queue.write_buffer(&self.camera_ubo, 0, cast_slice(&[camera.get_gpu_camera()]));
 
-    let transform = Transform::new(Mat4::from_scale_rotation_translation(
-        Vec3::ONE,
-        Quat::IDENTITY,
-        Vec3::ZERO,
-    ));
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("command_encoder"),
+        });
 
-    let transform_ubo = device.create_buffer(&BufferDescriptor {
-        label: Some("ubo::transform"),
-        size: size_of::<Transform>() as u64,
-        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
+        // Wireframes are only switched on where they're supported, but the device may have been replaced since.
+        let kind = if wireframe {
+            PipelineKind::Wireframe
+        } else {
+            PipelineKind::Solid
+        };
+        let pipeline = match self.pipeline(device, kind) {
+            Ok(pipeline) => pipeline,
+            Err(_) => self
+                .pipeline(device, PipelineKind::Solid)
+                .expect("invalid scene pipeline"),
+        };
 
-    let transform_bind_group = device.create_bind_group(&BindGroupDescriptor {
-        label: Some("bind_group::transform"),
-        layout: &transform_bind_group_layout,
-        entries: &[BindGroupEntry {
-            binding: 0,
-            resource: transform_ubo.as_entire_binding(),
-        }],
-    });
+        if profile {
+            self.profiler.begin_frame();
+        }
 
-    queue.write_buffer(&transform_ubo, 0, cast_slice(&[transform]));
+        let surface = self.graph.surface();
+        let profiler = &mut self.profiler;
+        self.graph
+            .execute(&mut encoder, output_texture_view, |pass, encoder, views| {
+                let scope = profiler.begin_scope(pass.name(), encoder);
+
+                match pass {
+                    Pass::Scene => {
+                        // With MSAA the samples are resolved into the surface and then discarded.
+                        let (view, resolve_target) = match self.color {
+                            Some(color) => (views.get(color), Some(views.get(surface))),
+                            None => (views.get(surface), None),
+                        };
+
+                        let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+                            label: Some("render_pass"),
+                            color_attachments: &[Some(RenderPassColorAttachment {
+                                view,
+                                resolve_target,
+                                ops: Operations {
+                                    load: LoadOp::Clear(Color::BLACK),
+                                    store: resolve_target.is_none(),
+                                },
+                            })],
+                            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                                view: views.get(self.depth),
+                                depth_ops: Some(camera.get_depth_mode().depth_ops()),
+                                stencil_ops: Some(Operations {
+                                    load: LoadOp::Clear(0),
+                                    store: true,
+                                }),
+                            }),
+                        });
+
+                        rpass.set_bind_group(0, &self.global_bind_group, &[]);
+                        rpass.set_bind_group(1, &self.transform_bind_group, &[]);
+                        rpass.set_pipeline(&pipeline);
+
+                        for mesh in &self.model.meshes {
+                            let material = &self.model.materials[mesh.material_id];
+
+                            rpass.set_vertex_buffer(0, mesh.vbo.slice(..));
+                            rpass.set_index_buffer(mesh.ibo.slice(..), IndexFormat::Uint32);
+                            rpass.set_bind_group(2, &material.bind_group, &[]);
+                            rpass.draw_indexed(0..mesh.indices.len() as u32, 0, 0..1);
+                        }
+                    }
+                }
 
-    window.set_cursor_visible(false);
-    window
-        .set_cursor_grab(CursorGrabMode::Confined)
-        .expect("failed to grab cursor");
+                profiler.end_scope(scope, encoder);
+            });
+
+        if profile {
+            self.profiler.end_frame(&mut encoder);
+        }
+        queue.submit(once(encoder.finish()));
+        if profile {
+            self.profiler.frame_submitted(device);
+        }
+    }
+}
+
+fn main() {
+    let config = Config::load();
+
+    let mut event_loop = EventLoop::new();
+
+    let window = config
+        .window_builder(SCREEN_WIDTH, SCREEN_HEIGHT)
+        .with_title(TITLE)
+        .with_visible(false)
+        .build(&event_loop)
+        .expect("failed to create a window");
+    let physical_size = window.inner_size();
+
+    let depth_mode = DepthMode::ReverseInfinite;
+
+    let mut gpu = create_gpu_context(&window, &config);
+    let mut renderer = create_renderer(&gpu, &config, depth_mode);
+    let mut device_loss = DeviceLoss::watch(&gpu.device);
+    let mut frame_acquirer = FrameAcquirer::new(physical_size.width, physical_size.height);
+
+    // Game objects.
+    let mut camera = Camera::new(&CameraDescriptor {
+        aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
+        depth_mode,
+        ..Default::default()
+    });
+    camera.set_viewport(&Viewport::from_size(
+        physical_size.width,
+        physical_size.height,
+    ));
+
+    let mut cursor_grabbed = set_cursor_grabbed(&window, true);
+    camera.set_has_mouse(cursor_grabbed);
     window.set_visible(true);
 
+    let mut controller = FlyController::new(&FlyControllerDescriptor::default());
+    let mut input = InputState::new();
+    let actions = ActionMap::load_asset_or_default(BINDINGS_FILE);
+    let mut wireframe = false;
+    let mut bookmarks = Bookmarks::load_output_or_default(BOOKMARKS_FILE);
+    let mut camera_path_recording: Option<(CameraPath, f32)> = None;
+    let mut camera_path_player: Option<CameraPathPlayer> = None;
+
+    let mut frame_limiter = FrameLimiter::new(config.target_fps);
+    let mut recorder: Option<Recorder> = None;
+    let mut last_title_update = Instant::now();
+
     let mut last_time = Instant::now();
     let mut running = true;
     while running {
+        frame_limiter.wait();
+
         let current_time = Instant::now();
-        let dt = (current_time - last_time).as_secs_f32();
+        // Recordings step time by a fixed amount per frame, so they play back at the same speed however long frames
+        // took to draw.
+        let dt = match &recorder {
+            Some(recorder) => recorder.get_timestep(),
+            None => (current_time - last_time).as_secs_f32(),
+        };
         last_time = current_time;
 
         running = process_events(
             &mut event_loop,
             &window,
-            &device,
-            &surface,
-            &mut surface_config,
-            &mut depth_texture,
-            &mut depth_texture_view,
+            &gpu.device,
+            &mut renderer,
+            &mut frame_acquirer,
             &mut camera,
-            dt,
+            &mut input,
         );
 
-        queue.write_buffer(&camera_ubo, 0, cast_slice(&[camera.get_gpu_camera()]));
+        if actions.was_pressed(&input, Action::Quit) {
+            break;
+        }
 
-        let frame = surface
-            .get_current_texture()
-            .expect("failed to get current swapchain texture");
-        let output_texture_view = frame.texture.create_view(&TextureViewDescriptor::default());
+        if actions.was_pressed(&input, Action::ToggleCameraMode) {
+            camera.toggle_mode();
+            controller.stop();
+            window.set_cursor_visible(!cursor_grabbed || camera.get_mode() == CameraMode::Orbit);
+        }
 
-        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
-            label: Some("command_encoder"),
-        });
+        if actions.was_pressed(&input, Action::ToggleCursorGrab) {
+            cursor_grabbed = set_cursor_grabbed(&window, !cursor_grabbed);
+            camera.set_has_mouse(cursor_grabbed);
+            window.set_cursor_visible(!cursor_grabbed || camera.get_mode() == CameraMode::Orbit);
+        }
+
+        if actions.was_pressed(&input, Action::ToggleWireframe) {
+            let kind = if wireframe {
+                PipelineKind::Solid
+            } else {
+                PipelineKind::Wireframe
+            };
+            match renderer.pipeline(&gpu.device, kind) {
+                Ok(_) => wireframe = !wireframe,
+                Err(e) => eprintln!("wireframe rendering isn't available: {}", e),
+            }
+        }
 
-        {
-            let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: Some("render_pass"),
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &output_texture_view,
-                    resolve_target: None,
-                    ops: Operations {
-                        load: LoadOp::Clear(Color::BLACK),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                    view: &depth_texture_view,
-                    depth_ops: Some(Operations {
-                        load: LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: Some(Operations {
-                        load: LoadOp::Clear(0),
-                        store: true,
-                    }),
-                }),
+        if actions.was_pressed(&input, Action::CyclePresentMode) {
+            gpu.surface_config.present_mode = next_present_mode(
+                gpu.surface_config.present_mode,
+                &gpu.surface_capabilities.present_modes,
+            );
+            gpu.surface.configure(&gpu.device, &gpu.surface_config);
+            println!("present mode: {:?}", gpu.surface_config.present_mode);
+        }
+
+        if actions.was_pressed(&input, Action::ToggleRecording) {
+            if recorder.is_some() {
+                stop_recording(&mut recorder, &gpu.device);
+            } else {
+                recorder = start_recording(
+                    RECORDING_NAME,
+                    &config,
+                    gpu.surface_config.width,
+                    gpu.surface_config.height,
+                );
+            }
+        }
+
+        if actions.was_pressed(&input, Action::ToggleFrameLimiter) {
+            frame_limiter.set_target_fps(match frame_limiter.get_target_fps() {
+                Some(_) => None,
+                None => Some(config.target_fps.unwrap_or(DEFAULT_TARGET_FPS)),
             });
+            match frame_limiter.get_target_fps() {
+                Some(fps) => println!("frame rate capped to {} fps", fps.round()),
+                None => println!("frame rate uncapped"),
+            }
+        }
 
-            rpass.set_bind_group(0, &global_bind_group, &[]);
-            rpass.set_bind_group(1, &transform_bind_group, &[]);
-            rpass.set_pipeline(&pipeline);
+        if let Some(BookmarkEvent::Saved(_)) = bookmarks.update(&input, &actions, &mut camera) {
+            bookmarks.save_output(BOOKMARKS_FILE);
+        }
+
+        if actions.was_pressed(&input, Action::ToggleProjection) {
+            camera.toggle_projection();
+        }
 
-            for mesh in &model.meshes {
-                let material = &model.materials[mesh.material_id];
+        if actions.was_pressed(&input, Action::RecordCameraPath) {
+            camera_path_recording = match camera_path_recording.take() {
+                None => Some((CameraPath::new(), 0.0)),
+                Some((camera_path, _)) => {
+                    save_camera_path(&camera_path);
+                    None
+                }
+            };
+        }
+
+        if actions.was_pressed(&input, Action::PlayCameraPath) {
+            camera_path_player = match camera_path_player.take() {
+                None => load_camera_path().map(CameraPathPlayer::new),
+                Some(_) => None,
+            };
+        }
 
-                rpass.set_vertex_buffer(0, mesh.vbo.slice(..));
-                rpass.set_index_buffer(mesh.ibo.slice(..), IndexFormat::Uint32);
-                rpass.set_bind_group(2, &material.bind_group, &[]);
-                rpass.draw_indexed(0..mesh.indices.len() as u32, 0, 0..1);
+        if let Some(player) = &mut camera_path_player {
+            if actions.was_pressed(&input, Action::IncreaseSpeed) {
+                player.set_speed(player.get_speed() * 2.0);
+            }
+            if actions.was_pressed(&input, Action::DecreaseSpeed) {
+                player.set_speed(player.get_speed() * 0.5);
+            }
+
+            player.update(dt, &mut camera);
+            if player.is_finished() {
+                camera_path_player = None;
+            }
+        } else {
+            controller.update(&input, &actions, &mut camera, dt);
+        }
+
+        if let Some((camera_path, elapsed)) = &mut camera_path_recording {
+            let next_keyframe_time = camera_path.duration() + CAMERA_PATH_KEYFRAME_INTERVAL;
+            if camera_path.is_empty() || *elapsed >= next_keyframe_time {
+                camera_path.record(&camera, *elapsed);
+            }
+            *elapsed += dt;
+        }
+
+        let take_screenshot = actions.was_pressed(&input, Action::Screenshot);
+        let take_poster = actions.was_pressed(&input, Action::CapturePoster);
+
+        input.end_frame();
+
+        if device_loss.is_lost() {
+            stop_recording(&mut recorder, &gpu.device);
+            (gpu, renderer, device_loss) =
+                recreate_gpu(&window, &config, depth_mode, gpu, renderer);
+            continue;
+        }
+
+        let mut surface = WgpuSurface {
+            surface: &gpu.surface,
+            device: &gpu.device,
+            config: &mut gpu.surface_config,
+        };
+        let frame = match frame_acquirer.acquire(&mut surface) {
+            Ok(Some(frame)) => frame,
+            // Minimized, or the surface needs another frame to recover.
+            Ok(None) => continue,
+            Err(FrameError::DeviceLost) => {
+                stop_recording(&mut recorder, &gpu.device);
+                (gpu, renderer, device_loss) =
+                    recreate_gpu(&window, &config, depth_mode, gpu, renderer);
+                continue;
+            }
+            Err(e) => panic!("failed to get current swapchain texture: {}", e),
+        };
+        let output_texture_view = frame.texture.create_view(&TextureViewDescriptor::default());
+
+        renderer.render(
+            &gpu.device,
+            &gpu.queue,
+            &output_texture_view,
+            &camera,
+            wireframe,
+            true,
+        );
+
+        if take_screenshot {
+            let result = capture_frame(&gpu.device, &gpu.queue, &frame.texture, |view| {
+                renderer.render(&gpu.device, &gpu.queue, view, &camera, wireframe, false)
+            });
+            save_capture("screenshot", SCREENSHOT_NAME, result);
+        }
+
+        if let Some(active_recorder) = &mut recorder {
+            let result = active_recorder.capture(&gpu.device, &gpu.queue, &frame.texture, |view| {
+                renderer.render(&gpu.device, &gpu.queue, view, &camera, wireframe, false)
+            });
+            if let Err(e) = result {
+                eprintln!("unable to record the frame: {}", e);
+                stop_recording(&mut recorder, &gpu.device);
             }
         }
 
-        queue.submit(once(encoder.finish()));
         frame.present();
+
+        if take_poster {
+            let layout = PosterLayout {
+                columns: config.poster_tiles.0,
+                rows: config.poster_tiles.1,
+                tile_width: gpu.surface_config.width,
+                tile_height: gpu.surface_config.height,
+            };
+            let result = capture_poster(
+                &gpu.device,
+                &gpu.queue,
+                gpu.surface_config.format,
+                layout,
+                &camera,
+                |camera, view| {
+                    renderer.render(&gpu.device, &gpu.queue, view, camera, wireframe, false)
+                },
+            );
+            save_capture("poster", POSTER_NAME, result);
+        }
+
+        if current_time - last_title_update >= PROFILE_TITLE_INTERVAL {
+            window.set_title(&format!(
+                "{} - {}",
+                TITLE,
+                renderer.profiler.stats().summary()
+            ));
+            last_title_update = current_time;
+        }
     }
-}
 
-fn setup_gpu(window: &Window) -> (Instance, Adapter, Device, Queue, Surface) {
-    let instance = Instance::new(InstanceDescriptor {
-        backends: Backends::PRIMARY,
-        ..Default::default()
-    });
+    stop_recording(&mut recorder, &gpu.device);
+    save_profile(&renderer.profiler);
+}
 
-    let surface = unsafe {
-        instance
-            .create_surface(window)
-            .expect("failed to create a surface")
+/// The GPU, with line polygons for wireframe rendering, timestamp queries for profiling and a surface that can be
+/// copied from for screenshots where they're available. Exits if there's no usable GPU.
+fn create_gpu_context(window: &Window, config: &Config) -> GpuContext {
+    let options = GpuContextOptions {
+        optional_features: Features::POLYGON_MODE_LINE | Features::TIMESTAMP_QUERY,
+        optional_surface_usages: TextureUsages::COPY_SRC,
+        ..config.gpu_context_options()
     };
 
-    let adapter = block_on(instance.request_adapter(&RequestAdapterOptions {
-        power_preference: PowerPreference::HighPerformance,
-        force_fallback_adapter: false,
-        compatible_surface: Some(&surface),
-    }))
-    .expect("failed to get a compatible adapter");
-
-    let (device, queue) = block_on(adapter.request_device(
-        &DeviceDescriptor {
-            label: Some("device"),
-            features: adapter.features(),
-            limits: adapter.limits(),
-        },
-        None,
-    ))
-    .expect("failed to get a compatible device");
-
-    (instance, adapter, device, queue, surface)
+    GpuContext::new(window, &options).unwrap_or_else(|e| {
+        eprintln!("failed to set up the GPU: {}", e);
+        process::exit(1);
+    })
 }
 
-fn setup_surface(
-    surface: &Surface,
-    width: u32,
-    height: u32,
-    adapter: &Adapter,
-    device: &Device,
-) -> SurfaceConfiguration {
-    let surface_capabilities = surface.get_capabilities(&adapter);
-    let surface_format = if surface_capabilities
-        .formats
-        .contains(&TextureFormat::Rgba8Unorm)
-    {
-        TextureFormat::Rgba8Unorm
-    } else if surface_capabilities
-        .formats
-        .contains(&TextureFormat::Bgra8Unorm)
-    {
-        TextureFormat::Bgra8Unorm
-    } else {
-        surface_capabilities.formats[0]
-    };
+/// The renderer, multisampled with the configured sample count or the nearest lower one the adapter supports.
+fn create_renderer(gpu: &GpuContext, config: &Config, depth_mode: DepthMode) -> Renderer {
+    let sample_count = choose_sample_count(
+        config.sample_count,
+        &gpu.supported_sample_counts(Some(DEPTH_FORMAT)),
+    );
 
-    let config = SurfaceConfiguration {
-        usage: TextureUsages::RENDER_ATTACHMENT,
-        format: surface_format,
-        width,
-        height,
-        present_mode: PresentMode::Fifo,
-        alpha_mode: surface_capabilities.alpha_modes[0],
-        view_formats: Vec::new(),
-    };
+    Renderer::new(
+        &gpu.device,
+        &gpu.queue,
+        &gpu.surface_config,
+        depth_mode,
+        sample_count,
+    )
+}
+
+/// Throw away the lost device and everything made with it, and start again on a new one.
+///
+/// The old context is dropped before the new one is created, since some backends, e.g. Vulkan, refuse to create a
+/// second surface for a window while the first is still alive.
+fn recreate_gpu(
+    window: &Window,
+    config: &Config,
+    depth_mode: DepthMode,
+    gpu: GpuContext,
+    renderer: Renderer,
+) -> (GpuContext, Renderer, DeviceLoss) {
+    eprintln!("the device was lost, recreating GPU resources");
+
+    drop(renderer);
+    drop(gpu);
 
-    surface.configure(&device, &config);
+    let gpu = create_gpu_context(window, config);
+    let renderer = create_renderer(&gpu, config, depth_mode);
+    let device_loss = DeviceLoss::watch(&gpu.device);
 
-    config
+    (gpu, renderer, device_loss)
 }
 
-fn create_depth_texture(device: &Device, width: u32, height: u32) -> (wgpu::Texture, TextureView) {
-    let texture = device.create_texture(&TextureDescriptor {
-        label: Some("depth_texture"),
-        size: Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        },
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: TextureDimension::D2,
-        format: TextureFormat::Depth32Float,
-        usage: TextureUsages::RENDER_ATTACHMENT,
-        view_formats: &[],
+/// Grab or release the cursor, returning whether it's now grabbed. Platforms that can't confine the cursor to the
+/// window get it locked in place instead.
+fn set_cursor_grabbed(window: &Window, grabbed: bool) -> bool {
+    if !grabbed {
+        window
+            .set_cursor_grab(CursorGrabMode::None)
+            .expect("failed to release cursor");
+        window.set_cursor_visible(true);
+        return false;
+    }
+
+    let result = window
+        .set_cursor_grab(CursorGrabMode::Confined)
+        .or_else(|_| window.set_cursor_grab(CursorGrabMode::Locked));
+
+    match result {
+        Ok(()) => {
+            window.set_cursor_visible(false);
+            true
+        }
+        Err(e) => {
+            eprintln!("unable to grab cursor: {}", e);
+            false
+        }
+    }
+}
+
+fn save_camera_path(camera_path: &CameraPath) {
+    let result = assets::output_path(CAMERA_PATH_FILE).and_then(|path| {
+        camera_path.save(&path)?;
+        Ok(path)
     });
 
-    let texture_view = texture.create_view(&TextureViewDescriptor::default());
+    match result {
+        Ok(path) => println!("saved camera path to {}", path.display()),
+        Err(e) => eprintln!("unable to save camera path: {}", e),
+    }
+}
+
+/// Write the pass timings as CSV and JSON, which are GPU times if the adapter has timestamp queries or the time taken
+/// to record the passes if not.
+fn save_profile(profiler: &Profiler) {
+    let result = assets::output_path(format!("{}.csv", PROFILE_FILE)).and_then(|csv_path| {
+        profiler.stats().save_csv(&csv_path)?;
+        let json_path = csv_path.with_extension("json");
+        profiler.stats().save_json(&json_path)?;
+        Ok((csv_path, json_path))
+    });
+
+    match result {
+        Ok((csv_path, json_path)) => println!(
+            "saved {} pass timings to {} and {}",
+            if profiler.has_timestamps() {
+                "GPU"
+            } else {
+                "CPU"
+            },
+            csv_path.display(),
+            json_path.display()
+        ),
+        Err(e) => eprintln!("unable to save pass timings: {}", e),
+    }
+}
+
+fn load_camera_path() -> Option<CameraPath> {
+    let result = assets::output_path(CAMERA_PATH_FILE).and_then(CameraPath::load);
 
-    (texture, texture_view)
+    match result {
+        Ok(camera_path) if !camera_path.is_empty() => Some(camera_path),
+        Ok(_) => None,
+        Err(e) => {
+            eprintln!("unable to load camera path: {}", e);
+            None
+        }
+    }
 }
 
 fn process_events(
     event_loop: &mut EventLoop<()>,
     window: &Window,
     device: &Device,
-    surface: &Surface,
-    surface_config: &mut SurfaceConfiguration,
-    depth_texture: &mut wgpu::Texture,
-    depth_texture_view: &mut TextureView,
+    renderer: &mut Renderer,
+    frame_acquirer: &mut FrameAcquirer,
     camera: &mut Camera,
-    dt: f32,
+    input: &mut InputState,
 ) -> bool {
     let mut quit = false;
 
@@ -620,73 +1002,23 @@ fn process_events(
         control_flow.set_wait();
 
         match event {
-            Event::WindowEvent { window_id, event } if window.id() == window_id => match event {
-                WindowEvent::CloseRequested => quit = true,
-
-                WindowEvent::Resized(size) => {
-                    surface_config.width = size.width;
-                    surface_config.height = size.height;
-                    surface.configure(&device, &surface_config);
-
-                    (*depth_texture, *depth_texture_view) =
-                        create_depth_texture(&device, surface_config.width, surface_config.height);
-                }
-
-                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                    surface_config.width = new_inner_size.width;
-                    surface_config.height = new_inner_size.height;
-                    surface.configure(&device, &surface_config);
+            Event::WindowEvent { window_id, event } if window.id() == window_id => {
+                camera.process_window_event(&event);
 
-                    (*depth_texture, *depth_texture_view) =
-                        create_depth_texture(&device, surface_config.width, surface_config.height);
-                }
+                match event {
+                    WindowEvent::CloseRequested => quit = true,
 
-                WindowEvent::CursorEntered { .. } => {
-                    camera.set_has_mouse(true);
-                }
+                    WindowEvent::Resized(size) => resize(size, device, renderer, frame_acquirer),
 
-                WindowEvent::MouseWheel { delta, .. } => {
-                    if let MouseScrollDelta::LineDelta(_, y) = delta {
-                        camera.zoom(y);
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        resize(*new_inner_size, device, renderer, frame_acquirer);
                     }
-                }
 
-                WindowEvent::KeyboardInput { input, .. } => {
-                    if let Some(key) = input.virtual_keycode {
-                        match key {
-                            VirtualKeyCode::Escape if input.state == ElementState::Pressed => {
-                                quit = true;
-                            }
-                            VirtualKeyCode::W if input.state == ElementState::Pressed => {
-                                camera.move_forward(dt);
-                            }
-                            VirtualKeyCode::S if input.state == ElementState::Pressed => {
-                                camera.move_backward(dt);
-                            }
-                            VirtualKeyCode::A if input.state == ElementState::Pressed => {
-                                camera.skew_left(dt);
-                            }
-                            VirtualKeyCode::D if input.state == ElementState::Pressed => {
-                                camera.skew_right(dt);
-                            }
-
-                            _ => (),
-                        }
-                    }
-                }
-
-                _ => (),
-            },
-
-            Event::DeviceEvent { event, .. } if camera.has_mouse() => match event {
-                DeviceEvent::MouseMotion { delta } => {
-                    let (x, y) = delta;
-
-                    camera.yaw_pitch(x as f32, -y as f32);
+                    event => input.process_window_event(&event),
                 }
+            }
 
-                _ => (),
-            },
+            Event::DeviceEvent { event, .. } => input.process_device_event(&event),
 
             Event::MainEventsCleared => control_flow.set_exit(),
 
@@ -696,3 +1028,21 @@ fn process_events(
 
     !quit
 }
+
+/// Resize everything that depends on the window size, apart from the camera which follows the window events itself.
+/// The surface is reconfigured when the next frame is acquired. A minimized window reports a size of zero, so the rest
+/// is left until the window is restored.
+fn resize(
+    size: PhysicalSize<u32>,
+    device: &Device,
+    renderer: &mut Renderer,
+    frame_acquirer: &mut FrameAcquirer,
+) {
+    frame_acquirer.resize(size.width, size.height);
+
+    if frame_acquirer.is_minimized() {
+        return;
+    }
+
+    renderer.resize(device, size.width, size.height);
+}
@@ -1,35 +1,42 @@
-use std::{borrow::Cow, iter::once, mem::size_of};
+use std::{borrow::Cow, iter::once, mem::size_of, process};
 
 use bytemuck::cast_slice;
 use bytemuck_derive::{Pod, Zeroable};
-use futures::executor::block_on;
 use glam::{Mat4, Quat, Vec2, Vec3};
 use image::GenericImageView;
 use wgpu::{
     AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferDescriptor,
     BufferSize, BufferUsages, Color, CommandEncoderDescriptor, CompareFunction, DepthBiasState,
-    DepthStencilState, Device, DeviceDescriptor, Extent3d, Face, Features, FilterMode,
-    FragmentState, ImageDataLayout, IndexFormat, LoadOp, MultisampleState, Operations,
-    PipelineLayoutDescriptor, PresentMode, PrimitiveState, RenderPassColorAttachment,
-    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipelineDescriptor,
-    RequestAdapterOptions, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor,
-    ShaderSource, ShaderStages, StencilState, SurfaceConfiguration, Texture, TextureDescriptor,
-    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
-    TextureViewDescriptor, TextureViewDimension, VertexAttribute, VertexBufferLayout, VertexFormat,
-    VertexState, VertexStepMode,
+    DepthStencilState, Device, Extent3d, Face, FilterMode, FragmentState, ImageDataLayout,
+    IndexFormat, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RenderPipelineDescriptor, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor,
+    ShaderSource, ShaderStages, StencilState, Texture, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor,
+    TextureViewDimension, VertexAttribute, VertexBufferLayout, VertexFormat, VertexState,
+    VertexStepMode,
+};
+use wgpu_samples::{
+    assets,
+    bounds::Aabb,
+    config::Config,
+    depth::DepthMode,
+    frustum::{cull, Frustum},
+    gpu::GpuContext,
+    surface::{DeviceLoss, FrameAcquirer, FrameError, WgpuSurface},
+    uniform_arena::UniformArena,
 };
-use wgpu_samples::assets;
 use winit::{
-    dpi::LogicalSize,
     event::{Event, WindowEvent},
     event_loop::EventLoop,
     platform::run_return::EventLoopExtRunReturn,
-    window::WindowBuilder,
+    window::Window,
 };
 
 const SCREEN_WIDTH: u32 = 1280;
 const SCREEN_HEIGHT: u32 = 720;
+const TITLE: &str = "More cubes";
 
 #[derive(Debug, Default, Clone, Copy, Pod, Zeroable)]
 #[repr(C)]
@@ -97,50 +104,41 @@ impl Globals {
 }
 
 fn main() {
+    let config = Config::load();
+
     let mut event_loop = EventLoop::new();
 
-    let window = WindowBuilder::new()
-        .with_inner_size(LogicalSize::new(SCREEN_WIDTH, SCREEN_HEIGHT))
-        .with_title("More cubes")
+    let window = config
+        .window_builder(SCREEN_WIDTH, SCREEN_HEIGHT)
+        .with_title(TITLE)
         .with_visible(false)
         .build(&event_loop)
         .expect("failed to create a window");
 
-    let physical_size = window.inner_size();
-
-    let instance = wgpu::Instance::default();
-    let surface = unsafe { instance.create_surface(&window) }.expect("failed to create surface");
-    let adapter = block_on(instance.request_adapter(&RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::HighPerformance,
-        force_fallback_adapter: false,
-        compatible_surface: Some(&surface),
-    }))
-    .expect("failed to find a suitable adapter");
-
-    let swapchain_capabilities = surface.get_capabilities(&adapter);
-    let swapchian_format = if swapchain_capabilities
-        .formats
-        .contains(&TextureFormat::Bgra8Unorm)
-    {
-        TextureFormat::Bgra8Unorm
-    } else if swapchain_capabilities
-        .formats
-        .contains(&TextureFormat::Rgba8Unorm)
-    {
-        TextureFormat::Rgba8Unorm
-    } else {
-        swapchain_capabilities.formats[0]
-    };
+    // Everything made with a lost device has to be made again on a new one, so only the window outlives it.
+    while let Exit::DeviceLost = run(&mut event_loop, &window, &config) {
+        eprintln!("the device was lost, recreating GPU resources");
+    }
+}
 
-    let (device, queue) = block_on(adapter.request_device(
-        &DeviceDescriptor {
-            label: None,
-            features: Features::empty(),
-            limits: adapter.limits(),
-        },
-        None,
-    ))
-    .expect("failed to create a device");
+/// Why [`run`] returned.
+enum Exit {
+    Quit,
+    DeviceLost,
+}
+
+/// Set up the GPU and draw until the window is closed or the device is lost.
+fn run(event_loop: &mut EventLoop<()>, window: &Window, config: &Config) -> Exit {
+    let GpuContext {
+        device,
+        queue,
+        surface,
+        mut surface_config,
+        ..
+    } = GpuContext::new(window, &config.gpu_context_options()).unwrap_or_else(|e| {
+        eprintln!("failed to set up the GPU: {}", e);
+        process::exit(1);
+    });
 
     let shader_src = include_str!("shader.wgsl");
     let shader = device.create_shader_module(ShaderModuleDescriptor {
@@ -162,27 +160,20 @@ fn main() {
         }],
     });
 
-    let model_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: Some("bind_group_layout::model"),
+    // Every cube's model matrix is drawn from one buffer, at its own offset.
+    let mut model_arena = UniformArena::<Model>::new(&device, "model", ShaderStages::VERTEX);
+
+    let texture_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("bind_group_layout::texture"),
         entries: &[
             BindGroupLayoutEntry {
                 binding: 0,
-                visibility: ShaderStages::VERTEX,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: BufferSize::new(size_of::<Model>() as u64),
-                },
-                count: None,
-            },
-            BindGroupLayoutEntry {
-                binding: 1,
                 visibility: ShaderStages::FRAGMENT,
                 ty: BindingType::Sampler(SamplerBindingType::Filtering),
                 count: None,
             },
             BindGroupLayoutEntry {
-                binding: 2,
+                binding: 1,
                 visibility: ShaderStages::FRAGMENT,
                 ty: BindingType::Texture {
                     sample_type: TextureSampleType::Float { filterable: true },
@@ -192,7 +183,7 @@ fn main() {
                 count: None,
             },
             BindGroupLayoutEntry {
-                binding: 3,
+                binding: 2,
                 visibility: ShaderStages::FRAGMENT,
                 ty: BindingType::Texture {
                     sample_type: TextureSampleType::Float { filterable: true },
@@ -206,7 +197,11 @@ fn main() {
 
     let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
         label: None,
-        bind_group_layouts: &[&global_bind_group_layout, &model_bind_group_layout],
+        bind_group_layouts: &[
+            &global_bind_group_layout,
+            model_arena.get_bind_group_layout(),
+            &texture_bind_group_layout,
+        ],
         push_constant_ranges: &[],
     });
 
@@ -233,13 +228,13 @@ fn main() {
         fragment: Some(FragmentState {
             module: &shader,
             entry_point: "fs_main",
-            targets: &[Some(swapchian_format.into())],
+            targets: &[Some(surface_config.format.into())],
         }),
         multiview: None,
     });
 
     let (mut depth_texture, mut depth_texture_view) =
-        create_depth_texture(&device, physical_size.width, physical_size.height);
+        create_depth_texture(&device, surface_config.width, surface_config.height);
 
     let sampler = device.create_sampler(&SamplerDescriptor {
         label: None,
@@ -250,17 +245,8 @@ fn main() {
         ..Default::default()
     });
 
-    let mut config = SurfaceConfiguration {
-        usage: TextureUsages::RENDER_ATTACHMENT,
-        format: swapchian_format,
-        width: physical_size.width,
-        height: physical_size.height,
-        present_mode: PresentMode::Fifo,
-        alpha_mode: swapchain_capabilities.alpha_modes[0],
-        view_formats: Vec::new(),
-    };
-
-    surface.configure(&device, &config);
+    let device_loss = DeviceLoss::watch(&device);
+    let mut frame_acquirer = FrameAcquirer::new(surface_config.width, surface_config.height);
 
     let globals_ubo = device.create_buffer(&BufferDescriptor {
         label: Some("ubo::globals"),
@@ -347,19 +333,6 @@ fn main() {
         mapped_at_creation: false,
     });
 
-    let model_ubos = positions
-        .iter()
-        .enumerate()
-        .map(|(i, _)| {
-            device.create_buffer(&BufferDescriptor {
-                label: Some(&format!("buffer::model_ubo_{}", i)),
-                size: size_of::<Model>() as u64,
-                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-                mapped_at_creation: false,
-            })
-        })
-        .collect::<Vec<_>>();
-
     let container_bytes = assets::load("assets/container.jpg").expect("unable to open file");
     let container_image = image::load_from_memory(&container_bytes).expect("unable to load image");
     let container_image_data = container_image.to_rgba8();
@@ -406,34 +379,24 @@ fn main() {
     });
     let face_texture_view = face_texture.create_view(&TextureViewDescriptor::default());
 
-    let model_bind_groups = model_ubos
-        .iter()
-        .enumerate()
-        .map(|(i, ubo)| {
-            device.create_bind_group(&BindGroupDescriptor {
-                label: Some(&format!("bind_group::model_{}", i)),
-                layout: &model_bind_group_layout,
-                entries: &[
-                    BindGroupEntry {
-                        binding: 0,
-                        resource: ubo.as_entire_binding(),
-                    },
-                    BindGroupEntry {
-                        binding: 1,
-                        resource: BindingResource::Sampler(&sampler),
-                    },
-                    BindGroupEntry {
-                        binding: 2,
-                        resource: BindingResource::TextureView(&container_texture_view),
-                    },
-                    BindGroupEntry {
-                        binding: 3,
-                        resource: BindingResource::TextureView(&face_texture_view),
-                    },
-                ],
-            })
-        })
-        .collect::<Vec<_>>();
+    let texture_bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: Some("bind_group::texture"),
+        layout: &texture_bind_group_layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Sampler(&sampler),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(&container_texture_view),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::TextureView(&face_texture_view),
+            },
+        ],
+    });
 
     queue.write_buffer(&vbo, 0, cast_slice(&vertices));
     queue.write_buffer(&ibo, 0, cast_slice(&indices));
@@ -461,33 +424,30 @@ fn main() {
     let view = Mat4::from_translation(Vec3::new(0.0, 0.0, -3.0));
     let projection = Mat4::perspective_rh(
         45.0_f32.to_radians(),
-        SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
+        surface_config.width as f32 / surface_config.height as f32,
         0.1,
         100.0,
     );
     let globals = Globals::new(view, projection);
 
-    let models = positions
-        .iter()
-        .enumerate()
-        .map(|(i, position)| {
-            let angle = 20.0_f32 * i as f32;
-            let rotation =
-                Quat::from_axis_angle(Vec3::new(1.0, 0.3, 0.5).normalize(), angle.to_radians());
-            let transform = Mat4::from_rotation_translation(rotation, *position);
-            Model::new(transform)
-        })
-        .collect::<Vec<_>>();
-    assert!(models.len() == model_ubos.len());
-
-    model_ubos
-        .iter()
-        .zip(models.iter())
-        .for_each(|(ubo, model)| {
-            queue.write_buffer(ubo, 0, cast_slice(&[*model]));
-        });
+    let cube_bounds = Aabb::from_center_half_extents(Vec3::ZERO, Vec3::splat(0.5));
+    let cubes = positions.iter().enumerate().map(|(i, position)| {
+        let angle = 20.0_f32 * i as f32;
+        let rotation =
+            Quat::from_axis_angle(Vec3::new(1.0, 0.3, 0.5).normalize(), angle.to_radians());
+        let transform = Mat4::from_rotation_translation(rotation, *position);
+        (Model::new(transform), cube_bounds.transform(transform))
+    });
     queue.write_buffer(&globals_ubo, 0, cast_slice(&[globals]));
 
+    // The camera never moves, so the cubes outside its view only need to be dropped once.
+    let frustum = Frustum::from_view_projection(projection * view, DepthMode::Standard);
+    let (visible_cubes, cull_stats) = cull(&frustum, cubes, |(_, bounds)| *bounds);
+    window.set_title(&format!(
+        "{} (visible: {}, culled: {})",
+        TITLE, cull_stats.visible, cull_stats.culled
+    ));
+
     window.set_visible(true);
     let mut running = true;
     while running {
@@ -499,21 +459,26 @@ fn main() {
                     WindowEvent::CloseRequested => running = false,
 
                     WindowEvent::Resized(size) => {
-                        config.width = size.width;
-                        config.height = size.height;
-                        surface.configure(&device, &config);
+                        frame_acquirer.resize(size.width, size.height);
+                        if frame_acquirer.is_minimized() {
+                            return;
+                        }
 
                         (depth_texture, depth_texture_view) =
-                            create_depth_texture(&device, config.width, config.height);
+                            create_depth_texture(&device, size.width, size.height);
                     }
 
                     WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        config.width = new_inner_size.width;
-                        config.height = new_inner_size.height;
-                        surface.configure(&device, &config);
-
-                        (depth_texture, depth_texture_view) =
-                            create_depth_texture(&device, config.width, config.height);
+                        frame_acquirer.resize(new_inner_size.width, new_inner_size.height);
+                        if frame_acquirer.is_minimized() {
+                            return;
+                        }
+
+                        (depth_texture, depth_texture_view) = create_depth_texture(
+                            &device,
+                            new_inner_size.width,
+                            new_inner_size.height,
+                        );
                     }
 
                     _ => (),
@@ -525,9 +490,29 @@ fn main() {
             }
         });
 
-        let frame = surface
-            .get_current_texture()
-            .expect("failed to get next swapchain texture");
+        model_arena.clear();
+        let model_offsets = visible_cubes
+            .iter()
+            .map(|(model, _)| model_arena.push(model))
+            .collect::<Vec<_>>();
+        model_arena.upload(&device, &queue);
+
+        if device_loss.is_lost() {
+            return Exit::DeviceLost;
+        }
+
+        let mut target = WgpuSurface {
+            surface: &surface,
+            device: &device,
+            config: &mut surface_config,
+        };
+        let frame = match frame_acquirer.acquire(&mut target) {
+            Ok(Some(frame)) => frame,
+            // Minimized, or the surface needs another frame to recover.
+            Ok(None) => continue,
+            Err(FrameError::DeviceLost) => return Exit::DeviceLost,
+            Err(e) => panic!("failed to get next swapchain texture: {}", e),
+        };
         let view = frame.texture.create_view(&TextureViewDescriptor::default());
         let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: None });
 
@@ -562,8 +547,9 @@ fn main() {
 
             rpass.set_pipeline(&render_pipeline);
             rpass.set_bind_group(0, &globals_bind_group, &[]);
-            for bind_group in &model_bind_groups {
-                rpass.set_bind_group(1, bind_group, &[]);
+            rpass.set_bind_group(2, &texture_bind_group, &[]);
+            for offset in &model_offsets {
+                rpass.set_bind_group(1, model_arena.get_bind_group(), &[*offset]);
                 rpass.set_vertex_buffer(0, vbo.slice(..));
                 rpass.set_index_buffer(ibo.slice(..), IndexFormat::Uint32);
                 rpass.draw_indexed(0..indices.len() as _, 0, 0..1);
@@ -573,6 +559,8 @@ fn main() {
         queue.submit(once(encoder.finish()));
         frame.present();
     }
+
+    Exit::Quit
 }
 
 fn create_depth_texture(device: &Device, width: u32, height: u32) -> (Texture, TextureView) {
@@ -35,16 +35,16 @@ fn vs_main(in: VsIn) -> VsOut {
     );
 }
 
-@group(1)
-@binding(1)
+@group(2)
+@binding(0)
 var texture_sampler: sampler;
 
-@group(1)
-@binding(2)
+@group(2)
+@binding(1)
 var texture_1: texture_2d<f32>;
 
-@group(1)
-@binding(3)
+@group(2)
+@binding(2)
 var texture_2: texture_2d<f32>;
 
 @fragment
@@ -1,41 +1,62 @@
-use std::{borrow::Cow, iter::once, mem::size_of, time::Instant};
+use std::{borrow::Cow, iter::once, mem::size_of, process, time::Instant};
 
 use bytemuck::cast_slice;
 use bytemuck_derive::{Pod, Zeroable};
-use futures::executor::block_on;
 use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
 use image::GenericImageView;
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
-    Backends, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
     BindingResource, BindingType, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages,
-    Color, CommandEncoderDescriptor, CompareFunction, DepthBiasState, DepthStencilState, Device,
-    DeviceDescriptor, Extent3d, Face, Features, FilterMode, FragmentState, ImageDataLayout,
-    IndexFormat, InstanceDescriptor, LoadOp, MultisampleState, Operations,
-    PipelineLayoutDescriptor, PowerPreference, PresentMode, PrimitiveState,
-    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
-    RenderPipelineDescriptor, RequestAdapterOptions, Sampler, SamplerBindingType,
-    SamplerDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState,
-    SurfaceConfiguration, Texture, TextureDescriptor, TextureDimension, TextureFormat,
-    TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
-    VertexAttribute, VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
+    Color, CommandEncoderDescriptor, Extent3d, FilterMode, ImageDataLayout, IndexFormat, LoadOp,
+    Operations, PipelineLayoutDescriptor, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    Sampler, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor, ShaderSource,
+    ShaderStages, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
+    TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension, VertexAttribute,
+    VertexBufferLayout, VertexFormat, VertexStepMode,
 };
 use wgpu_samples::{
+    actions::{Action, ActionMap, BINDINGS_FILE},
     assets,
+    bookmarks::{BookmarkEvent, Bookmarks},
+    bounds::Aabb,
     camera::{Camera, CameraDescriptor, GpuCamera},
+    capture::{capture_frame, capture_poster, save_capture, PosterLayout},
+    config::Config,
+    controller::{
+        CollisionWorld, FlyController, FlyControllerDescriptor, WalkController,
+        WalkControllerDescriptor,
+    },
+    depth::{create_depth_texture, DEPTH_FORMAT},
+    frustum::{cull, CullStats},
+    gpu::{GpuContext, GpuContextOptions},
+    input::InputState,
+    msaa::{choose_sample_count, supported_sample_counts, MultisampleTarget},
+    pacing::{next_present_mode, FrameLimiter, DEFAULT_TARGET_FPS},
+    picking::Ray,
+    pipeline::PipelineBuilder,
+    recording::{start_recording, stop_recording, Recorder},
+    surface::{DeviceLoss, FrameAcquirer, FrameError, WgpuSurface},
+    uniform_arena::UniformArena,
+    viewport::Viewport,
 };
 use winit::{
-    dpi::LogicalSize,
-    event::{DeviceEvent, ElementState, Event, MouseScrollDelta, VirtualKeyCode, WindowEvent},
+    event::{Event, WindowEvent},
     event_loop::EventLoop,
     platform::run_return::EventLoopExtRunReturn,
-    window::{CursorGrabMode, WindowBuilder},
+    window::{CursorGrabMode, Window},
 };
 
 const SCREEN_WIDTH: u32 = 1280;
 const SCREEN_HEIGHT: u32 = 720;
 const TITLE: &str = "Multiple lights";
 const POINT_LIGHT_COUNT: u32 = 4;
+/// Height of the invisible floor walked on in walk mode, just below the lowest cube.
+const GROUND_HEIGHT: f32 = -3.5;
+const BOOKMARKS_FILE: &str = "multiple-lights.bookmarks";
+const SCREENSHOT_NAME: &str = "multiple-lights";
+const POSTER_NAME: &str = "multiple-lights-poster";
+const RECORDING_NAME: &str = "multiple-lights-recording";
 
 const VERTICES: [Vertex; 24] = [
     // Front
@@ -246,6 +267,11 @@ impl Model {
             normal_matrix: normal_matrix.to_cols_array(),
         }
     }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::from_center_half_extents(Vec3::ZERO, Vec3::splat(0.5))
+            .transform(Mat4::from_cols_array(&self.model_matrix))
+    }
 }
 
 #[derive(Debug)]
@@ -399,74 +425,85 @@ impl SpotLight {
 }
 
 fn main() {
+    let config = Config::load();
+
     let mut event_loop = EventLoop::new();
 
-    let logical_size = LogicalSize::new(SCREEN_WIDTH, SCREEN_HEIGHT);
-    let window = WindowBuilder::new()
-        .with_inner_size(logical_size)
+    let window = config
+        .window_builder(SCREEN_WIDTH, SCREEN_HEIGHT)
         .with_title(TITLE)
         .with_visible(false)
         .build(&event_loop)
         .expect("failed to create window");
-    let physical_size = window.inner_size();
 
-    let instance = wgpu::Instance::new(InstanceDescriptor {
-        backends: Backends::PRIMARY,
+    let physical_size = window.inner_size();
+    let mut camera = Camera::new(&CameraDescriptor {
+        aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
         ..Default::default()
     });
+    camera.set_viewport(&Viewport::from_size(
+        physical_size.width,
+        physical_size.height,
+    ));
+
+    // Everything made with a lost device has to be made again on a new one, so only the window and the camera
+    // outlive it.
+    while let Exit::DeviceLost = run(&mut event_loop, &window, &config, &mut camera) {
+        eprintln!("the device was lost, recreating GPU resources");
+    }
+}
 
-    let surface = unsafe {
-        instance
-            .create_surface(&window)
-            .expect("failed to create surface")
-    };
+/// Why [`run`] returned.
+enum Exit {
+    Quit,
+    DeviceLost,
+}
 
-    let adapter = block_on(instance.request_adapter(&RequestAdapterOptions {
-        power_preference: PowerPreference::LowPower,
-        force_fallback_adapter: false,
-        compatible_surface: Some(&surface),
-    }))
-    .expect("failed to get a suitable adapter");
-
-    let (device, queue) = block_on(adapter.request_device(
-        &DeviceDescriptor {
-            label: Some("device"),
-            features: Features::empty(),
-            limits: adapter.limits(),
+/// Set up the GPU and draw until the window is closed or the device is lost.
+fn run(
+    event_loop: &mut EventLoop<()>,
+    window: &Window,
+    config: &Config,
+    camera: &mut Camera,
+) -> Exit {
+    let GpuContext {
+        instance: _instance,
+        adapter,
+        device,
+        queue,
+        surface,
+        mut surface_config,
+        surface_capabilities,
+        ..
+    } = GpuContext::new(
+        window,
+        &GpuContextOptions {
+            optional_surface_usages: TextureUsages::COPY_SRC,
+            ..config.gpu_context_options()
         },
-        None,
-    ))
-    .expect("failed to get a device");
-
-    let surface_capabilities = surface.get_capabilities(&adapter);
-    let surface_format = if surface_capabilities
-        .formats
-        .contains(&TextureFormat::Rgba8Unorm)
-    {
-        TextureFormat::Rgba8Unorm
-    } else if surface_capabilities
-        .formats
-        .contains(&TextureFormat::Bgra8Unorm)
-    {
-        TextureFormat::Bgra8Unorm
-    } else {
-        surface_capabilities.formats[0]
-    };
-
-    let mut surface_config = SurfaceConfiguration {
-        usage: TextureUsages::RENDER_ATTACHMENT,
-        format: surface_format,
-        width: physical_size.width,
-        height: physical_size.height,
-        present_mode: PresentMode::Fifo,
-        alpha_mode: surface_capabilities.alpha_modes[0],
-        view_formats: Vec::new(),
-    };
-
-    surface.configure(&device, &surface_config);
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("failed to set up the GPU: {}", e);
+        process::exit(1);
+    });
 
-    let (mut depth_texture, mut depth_texture_view) =
-        create_depth_texture(&device, physical_size.width, physical_size.height);
+    let sample_count = choose_sample_count(
+        config.sample_count,
+        &supported_sample_counts(&adapter, &device, surface_config.format, Some(DEPTH_FORMAT)),
+    );
+    let mut msaa_target = MultisampleTarget::new(
+        &device,
+        surface_config.format,
+        surface_config.width,
+        surface_config.height,
+        sample_count,
+    );
+    let (mut depth_texture, mut depth_texture_view) = create_depth_texture(
+        &device,
+        surface_config.width,
+        surface_config.height,
+        sample_count,
+    );
 
     let scene_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
         label: Some("bind_group_layout::scene"),
@@ -514,19 +551,8 @@ fn main() {
         ],
     });
 
-    let model_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: Some("bind_group_layout::model"),
-        entries: &[BindGroupLayoutEntry {
-            binding: 0,
-            visibility: ShaderStages::VERTEX,
-            ty: BindingType::Buffer {
-                ty: BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: BufferSize::new(size_of::<Model>() as u64),
-            },
-            count: None,
-        }],
-    });
+    // Every cube's transform is drawn from one buffer, at its own offset.
+    let mut model_arena = UniformArena::<Model>::new(&device, "model", ShaderStages::VERTEX);
 
     let material_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
         label: Some("bind_group_layout::material"),
@@ -595,7 +621,7 @@ fn main() {
         label: Some("pipeline_layout::light"),
         bind_group_layouts: &[
             &scene_bind_group_layout,
-            &model_bind_group_layout,
+            model_arena.get_bind_group_layout(),
             &light_cube_color_bind_group_layout,
         ],
         push_constant_ranges: &[],
@@ -605,7 +631,7 @@ fn main() {
         label: Some("pipeline_layout::model"),
         bind_group_layouts: &[
             &scene_bind_group_layout,
-            &model_bind_group_layout,
+            model_arena.get_bind_group_layout(),
             &material_bind_group_layout,
         ],
         push_constant_ranges: &[],
@@ -668,33 +694,13 @@ fn main() {
         source: ShaderSource::Wgsl(Cow::Borrowed(light_shader_src)),
     });
 
-    let light_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-        label: Some("render_pipeline::light"),
-        layout: Some(&light_pipeline_layout),
-        vertex: VertexState {
-            module: &light_shader_module,
-            entry_point: "vs_main",
-            buffers: &[Vertex::layout()],
-        },
-        primitive: PrimitiveState {
-            cull_mode: Some(Face::Back),
-            ..Default::default()
-        },
-        depth_stencil: Some(DepthStencilState {
-            format: TextureFormat::Depth32Float,
-            depth_write_enabled: true,
-            depth_compare: CompareFunction::Less,
-            stencil: StencilState::default(),
-            bias: DepthBiasState::default(),
-        }),
-        multisample: MultisampleState::default(),
-        fragment: Some(FragmentState {
-            module: &light_shader_module,
-            entry_point: "fs_main",
-            targets: &[Some(surface_format.into())],
-        }),
-        multiview: None,
-    });
+    let light_pipeline = PipelineBuilder::new(&light_shader_module, surface_config.format)
+        .label("render_pipeline::light")
+        .layout(&light_pipeline_layout)
+        .vertex_buffer(Vertex::layout())
+        .sample_count(msaa_target.get_sample_count())
+        .build(&device)
+        .expect("invalid light pipeline");
 
     let model_shader_src = include_str!("model.wgsl");
     let model_shader_module = device.create_shader_module(ShaderModuleDescriptor {
@@ -702,33 +708,13 @@ fn main() {
         source: ShaderSource::Wgsl(Cow::Borrowed(model_shader_src)),
     });
 
-    let model_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-        label: Some("render_pipeline::model"),
-        layout: Some(&model_pipeline_layout),
-        vertex: VertexState {
-            module: &model_shader_module,
-            entry_point: "vs_main",
-            buffers: &[Vertex::layout()],
-        },
-        primitive: PrimitiveState {
-            cull_mode: Some(Face::Back),
-            ..Default::default()
-        },
-        depth_stencil: Some(DepthStencilState {
-            format: TextureFormat::Depth32Float,
-            depth_write_enabled: true,
-            depth_compare: CompareFunction::Less,
-            stencil: StencilState::default(),
-            bias: DepthBiasState::default(),
-        }),
-        multisample: MultisampleState::default(),
-        fragment: Some(FragmentState {
-            module: &model_shader_module,
-            entry_point: "fs_main",
-            targets: &[Some(surface_format.into())],
-        }),
-        multiview: None,
-    });
+    let model_pipeline = PipelineBuilder::new(&model_shader_module, surface_config.format)
+        .label("render_pipeline::model")
+        .layout(&model_pipeline_layout)
+        .vertex_buffer(Vertex::layout())
+        .sample_count(msaa_target.get_sample_count())
+        .build(&device)
+        .expect("invalid model pipeline");
 
     let light_cube_vbo = device.create_buffer_init(&BufferInitDescriptor {
         label: Some("vbo::light_cube"),
@@ -820,33 +806,6 @@ fn main() {
         })
         .collect::<Vec<_>>();
 
-    let light_cube_ubos = light_cubes
-        .iter()
-        .enumerate()
-        .map(|(i, light_cube)| {
-            device.create_buffer_init(&BufferInitDescriptor {
-                label: Some(&format!("ubo::light_cube_{}", i)),
-                contents: cast_slice(&[*light_cube]),
-                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-            })
-        })
-        .collect::<Vec<_>>();
-
-    let light_cube_bind_groups = light_cube_ubos
-        .iter()
-        .enumerate()
-        .map(|(i, ubo)| {
-            device.create_bind_group(&BindGroupDescriptor {
-                label: Some(&format!("bind_group::light_cube_{}", i)),
-                layout: &model_bind_group_layout,
-                entries: &[BindGroupEntry {
-                    binding: 0,
-                    resource: ubo.as_entire_binding(),
-                }],
-            })
-        })
-        .collect::<Vec<_>>();
-
     let light_cube_color = Vec4::new(1.0, 1.0, 1.0, 1.0);
     let light_cube_color_ubo = device.create_buffer_init(&BufferInitDescriptor {
         label: Some("ubo::light_color"),
@@ -863,6 +822,22 @@ fn main() {
         }],
     });
 
+    let highlight_color = Vec4::new(1.0, 0.8, 0.2, 1.0);
+    let highlight_color_ubo = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("ubo::highlight_color"),
+        contents: cast_slice(&highlight_color.to_array()),
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+    });
+
+    let highlight_color_bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: Some("bind_group::highlight_color"),
+        layout: &light_cube_color_bind_group_layout,
+        entries: &[BindGroupEntry {
+            binding: 0,
+            resource: highlight_color_ubo.as_entire_binding(),
+        }],
+    });
+
     let models = POSITIONS
         .iter()
         .enumerate()
@@ -875,33 +850,6 @@ fn main() {
         })
         .collect::<Vec<_>>();
 
-    let cube_ubos = models
-        .iter()
-        .enumerate()
-        .map(|(i, cube)| {
-            device.create_buffer_init(&BufferInitDescriptor {
-                label: Some(&format!("vbo::cube_{}", i)),
-                contents: cast_slice(&[*cube]),
-                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-            })
-        })
-        .collect::<Vec<_>>();
-
-    let cube_bind_groups = cube_ubos
-        .iter()
-        .enumerate()
-        .map(|(i, ubo)| {
-            device.create_bind_group(&BindGroupDescriptor {
-                label: Some(&format!("bind_group::cube_{}", i)),
-                layout: &model_bind_group_layout,
-                entries: &[BindGroupEntry {
-                    binding: 0,
-                    resource: ubo.as_entire_binding(),
-                }],
-            })
-        })
-        .collect::<Vec<_>>();
-
     let diffuse_bytes = assets::load("assets/container2.png").expect("unable to open file");
     let diffuse_image = image::load_from_memory(&diffuse_bytes).expect("unable to load image");
     let diffuse_image_data = diffuse_image.to_rgba8();
@@ -1032,24 +980,48 @@ fn main() {
         ],
     });
 
-    let mut camera = Camera::new(&CameraDescriptor {
-        aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
-        ..Default::default()
-    });
-
-    window.set_cursor_visible(false);
-    window
-        .set_cursor_grab(CursorGrabMode::Confined)
-        .expect("failed to grab cursor");
+    let mut cursor_grabbed = set_cursor_grabbed(window, true);
+    camera.set_has_mouse(cursor_grabbed);
     window.set_visible(true);
 
+    let light_cube_bounds = light_cubes.iter().map(Model::bounds).collect::<Vec<_>>();
+    let cube_bounds = models.iter().map(Model::bounds).collect::<Vec<_>>();
+    let cube_positions = VERTICES
+        .iter()
+        .map(|vertex| Vec3::from_array(vertex.position))
+        .collect::<Vec<_>>();
+    let mut last_cull_stats = CullStats::default();
+
     let mut last_time = Instant::now();
 
-    let mut mouse_in_window = false;
+    let world = CollisionWorld {
+        ground_height: GROUND_HEIGHT,
+        boxes: cube_bounds.clone(),
+    };
+
+    let mut input = InputState::new();
+    let actions = ActionMap::load_asset_or_default(BINDINGS_FILE);
+    let mut fly_controller = FlyController::new(&FlyControllerDescriptor::default());
+    let walk_controller_desc = WalkControllerDescriptor::default();
+    let mut walk_controller: Option<WalkController> = None;
+    let mut bookmarks = Bookmarks::load_output_or_default(BOOKMARKS_FILE);
+
+    let device_loss = DeviceLoss::watch(&device);
+    let mut frame_acquirer = FrameAcquirer::new(surface_config.width, surface_config.height);
+    let mut frame_limiter = FrameLimiter::new(config.target_fps);
+    let mut recorder: Option<Recorder> = None;
+
     let mut running = true;
     while running {
+        frame_limiter.wait();
+
         let current_time = Instant::now();
-        let dt = (current_time - last_time).as_secs_f32();
+        // Recordings step time by a fixed amount per frame, so they play back at the same speed however long frames
+        // took to draw.
+        let dt = match &recorder {
+            Some(recorder) => recorder.get_timestep(),
+            None => (current_time - last_time).as_secs_f32(),
+        };
         last_time = current_time;
 
         event_loop.run_return(|event, _, control_flow| {
@@ -1057,82 +1029,54 @@ fn main() {
 
             match event {
                 Event::WindowEvent { window_id, event } if window.id() == window_id => {
+                    camera.process_window_event(&event);
+
                     match event {
                         WindowEvent::CloseRequested => running = false,
 
                         WindowEvent::Resized(size) => {
-                            surface_config.width = size.width;
-                            surface_config.height = size.height;
-                            surface.configure(&device, &surface_config);
+                            frame_acquirer.resize(size.width, size.height);
+                            if frame_acquirer.is_minimized() {
+                                return;
+                            }
 
+                            msaa_target.resize(&device, size.width, size.height);
                             (depth_texture, depth_texture_view) = create_depth_texture(
                                 &device,
-                                surface_config.width,
-                                surface_config.height,
+                                size.width,
+                                size.height,
+                                sample_count,
                             );
                         }
 
                         WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            surface_config.width = new_inner_size.width;
-                            surface_config.height = new_inner_size.height;
-                            surface.configure(&device, &surface_config);
+                            frame_acquirer.resize(new_inner_size.width, new_inner_size.height);
+                            if frame_acquirer.is_minimized() {
+                                return;
+                            }
 
+                            msaa_target.resize(
+                                &device,
+                                new_inner_size.width,
+                                new_inner_size.height,
+                            );
                             (depth_texture, depth_texture_view) = create_depth_texture(
                                 &device,
-                                surface_config.width,
-                                surface_config.height,
+                                new_inner_size.width,
+                                new_inner_size.height,
+                                sample_count,
                             );
                         }
 
                         WindowEvent::CursorEntered { .. } => {
-                            mouse_in_window = true;
-                        }
-
-                        WindowEvent::MouseWheel { delta, .. } => {
-                            if let MouseScrollDelta::LineDelta(_, y) = delta {
-                                camera.zoom(y);
-                            }
-                        }
-
-                        WindowEvent::KeyboardInput { input, .. } => {
-                            if let Some(key) = input.virtual_keycode {
-                                match key {
-                                    VirtualKeyCode::Escape
-                                        if input.state == ElementState::Pressed =>
-                                    {
-                                        running = false;
-                                    }
-                                    VirtualKeyCode::W if input.state == ElementState::Pressed => {
-                                        camera.move_forward(dt);
-                                    }
-                                    VirtualKeyCode::S if input.state == ElementState::Pressed => {
-                                        camera.move_backward(dt);
-                                    }
-                                    VirtualKeyCode::A if input.state == ElementState::Pressed => {
-                                        camera.skew_left(dt);
-                                    }
-                                    VirtualKeyCode::D if input.state == ElementState::Pressed => {
-                                        camera.skew_right(dt);
-                                    }
-
-                                    _ => (),
-                                }
-                            }
+                            camera.set_has_mouse(cursor_grabbed);
                         }
 
-                        _ => (),
+                        event => input.process_window_event(&event),
                     }
                 }
 
-                Event::DeviceEvent { event, .. } if mouse_in_window => match event {
-                    DeviceEvent::MouseMotion { delta } => {
-                        let (x, y) = delta;
-
-                        camera.yaw_pitch(x as f32, -y as f32);
-                    }
-
-                    _ => (),
-                },
+                Event::DeviceEvent { event, .. } => input.process_device_event(&event),
 
                 Event::MainEventsCleared => control_flow.set_exit(),
 
@@ -1140,98 +1084,322 @@ fn main() {
             }
         });
 
-        let spot_light = SpotLight::new(
-            camera.get_position(),
-            camera.get_direction(),
-            12.5_f32.to_radians().cos(),
-            17.5_f32.to_radians().cos(),
-            1.0,
-            0.09,
-            0.032,
-            Vec4::new(0.0, 0.0, 0.0, 1.0),
-            Vec4::new(1.0, 1.0, 1.0, 1.0),
-            Vec4::new(1.0, 1.0, 1.0, 1.0),
-        );
+        if actions.was_pressed(&input, Action::Quit) {
+            break;
+        }
 
-        queue.write_buffer(&camera_ubo, 0, cast_slice(&[camera.get_gpu_camera()]));
-        queue.write_buffer(&spot_lighting_ubo, 0, cast_slice(&[spot_light]));
+        if actions.was_pressed(&input, Action::ToggleCursorGrab) {
+            cursor_grabbed = set_cursor_grabbed(window, !cursor_grabbed);
+            camera.set_has_mouse(cursor_grabbed);
+        }
 
-        let frame = surface
-            .get_current_texture()
-            .expect("failed to get current swapchain texture");
-        let output_texture_view = frame.texture.create_view(&TextureViewDescriptor::default());
+        if actions.was_pressed(&input, Action::ToggleWalkMode) {
+            walk_controller = match walk_controller.take() {
+                None => {
+                    let feet = camera.get_position() - Vec3::Y * walk_controller_desc.eye_height;
+                    Some(WalkController::new(&walk_controller_desc, feet))
+                }
+                Some(_) => {
+                    fly_controller.stop();
+                    None
+                }
+            };
+        }
+
+        if actions.was_pressed(&input, Action::CyclePresentMode) {
+            surface_config.present_mode = next_present_mode(
+                surface_config.present_mode,
+                &surface_capabilities.present_modes,
+            );
+            if !frame_acquirer.is_minimized() {
+                surface.configure(&device, &surface_config);
+            }
+            println!("present mode: {:?}", surface_config.present_mode);
+        }
 
-        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
-            label: Some("command_encoder"),
+        if actions.was_pressed(&input, Action::ToggleRecording) {
+            if recorder.is_some() {
+                stop_recording(&mut recorder, &device);
+            } else {
+                let (width, height) = frame_acquirer.size();
+                recorder = start_recording(RECORDING_NAME, config, width, height);
+            }
+        }
+
+        if actions.was_pressed(&input, Action::ToggleFrameLimiter) {
+            frame_limiter.set_target_fps(match frame_limiter.get_target_fps() {
+                Some(_) => None,
+                None => Some(config.target_fps.unwrap_or(DEFAULT_TARGET_FPS)),
+            });
+            match frame_limiter.get_target_fps() {
+                Some(fps) => println!("frame rate capped to {} fps", fps.round()),
+                None => println!("frame rate uncapped"),
+            }
+        }
+
+        match bookmarks.update(&input, &actions, camera) {
+            Some(BookmarkEvent::Saved(_)) => bookmarks.save_output(BOOKMARKS_FILE),
+            Some(BookmarkEvent::Restored(_)) => {
+                if let Some(walk_controller) = &mut walk_controller {
+                    let feet = camera.get_position() - Vec3::Y * walk_controller_desc.eye_height;
+                    walk_controller.set_position(feet);
+                }
+            }
+            None => (),
+        }
+
+        match &mut walk_controller {
+            Some(walk_controller) => {
+                walk_controller.update(&input, &actions, &world, camera, dt);
+            }
+            None => fly_controller.update(&input, &actions, camera, dt),
+        }
+
+        let cursor_position = input.cursor_position();
+        let take_screenshot = actions.was_pressed(&input, Action::Screenshot);
+        let take_poster = actions.was_pressed(&input, Action::CapturePoster);
+        input.end_frame();
+
+        if device_loss.is_lost() {
+            stop_recording(&mut recorder, &device);
+            return Exit::DeviceLost;
+        }
+
+        // Nothing can be drawn to a minimized window.
+        if frame_acquirer.is_minimized() {
+            continue;
+        }
+
+        let picked_cube = cursor_position.and_then(|cursor_position| {
+            let (width, height) = frame_acquirer.size();
+            let viewport = Viewport::from_size(width, height);
+            let ray = camera.screen_to_ray(cursor_position, &viewport);
+            pick_cube(&ray, &models, &cube_bounds, &cube_positions)
         });
 
-        {
-            let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: Some("render_pass"),
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &output_texture_view,
-                    resolve_target: None,
-                    ops: Operations {
-                        load: LoadOp::Clear(Color::BLACK),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                    view: &depth_texture_view,
-                    depth_ops: Some(Operations {
-                        load: LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: Some(Operations {
-                        load: LoadOp::Clear(0),
-                        store: true,
-                    }),
-                }),
+        model_arena.clear();
+        let light_cube_offsets = light_cubes
+            .iter()
+            .map(|light_cube| model_arena.push(light_cube))
+            .collect::<Vec<_>>();
+        let cube_offsets = models
+            .iter()
+            .map(|model| model_arena.push(model))
+            .collect::<Vec<_>>();
+        model_arena.upload(&device, &queue);
+
+        // Draws the scene as `camera` sees it into `output_texture_view`, which is the frame unless it's being
+        // captured, and returns how many cubes were culled.
+        let draw = |camera: &Camera, output_texture_view: &TextureView| {
+            let spot_light = SpotLight::new(
+                camera.get_position(),
+                camera.get_direction(),
+                12.5_f32.to_radians().cos(),
+                17.5_f32.to_radians().cos(),
+                1.0,
+                0.09,
+                0.032,
+                Vec4::new(0.0, 0.0, 0.0, 1.0),
+                Vec4::new(1.0, 1.0, 1.0, 1.0),
+                Vec4::new(1.0, 1.0, 1.0, 1.0),
+            );
+
+            queue.write_buffer(&camera_ubo, 0, cast_slice(&[camera.get_gpu_camera()]));
+            queue.write_buffer(&spot_lighting_ubo, 0, cast_slice(&[spot_light]));
+
+            let frustum = camera.frustum();
+            let (visible_light_cubes, light_cube_stats) = cull(
+                &frustum,
+                light_cube_offsets.iter().zip(&light_cube_bounds),
+                |(_, bounds)| **bounds,
+            );
+            let (visible_cubes, cube_stats) = cull(
+                &frustum,
+                cube_offsets.iter().zip(&cube_bounds).enumerate(),
+                |(_, (_, bounds))| **bounds,
+            );
+
+            let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("command_encoder"),
             });
 
-            rpass.set_bind_group(0, &scene_bind_group, &[]);
+            {
+                let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: Some("render_pass"),
+                    color_attachments: &[Some(msaa_target.color_attachment(
+                        output_texture_view,
+                        Operations {
+                            load: LoadOp::Clear(Color::BLACK),
+                            store: true,
+                        },
+                    ))],
+                    depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                        view: &depth_texture_view,
+                        depth_ops: Some(Operations {
+                            load: LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: Some(Operations {
+                            load: LoadOp::Clear(0),
+                            store: true,
+                        }),
+                    }),
+                });
+
+                rpass.set_bind_group(0, &scene_bind_group, &[]);
+
+                rpass.set_pipeline(&light_pipeline);
+                for (offset, _) in &visible_light_cubes {
+                    rpass.set_bind_group(1, model_arena.get_bind_group(), &[**offset]);
+                    rpass.set_bind_group(2, &light_cube_color_bind_group, &[]);
+                    rpass.set_vertex_buffer(0, light_cube_vbo.slice(..));
+                    rpass.set_index_buffer(light_cube_ibo.slice(..), IndexFormat::Uint32);
+                    rpass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+                }
+
+                rpass.set_pipeline(&model_pipeline);
+                for (i, (offset, _)) in &visible_cubes {
+                    if picked_cube == Some(*i) {
+                        continue;
+                    }
 
-            rpass.set_pipeline(&light_pipeline);
-            for bind_group in &light_cube_bind_groups {
-                rpass.set_bind_group(1, bind_group, &[]);
-                rpass.set_bind_group(2, &light_cube_color_bind_group, &[]);
-                rpass.set_vertex_buffer(0, light_cube_vbo.slice(..));
-                rpass.set_index_buffer(light_cube_ibo.slice(..), IndexFormat::Uint32);
-                rpass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+                    rpass.set_bind_group(1, model_arena.get_bind_group(), &[**offset]);
+                    rpass.set_bind_group(2, &cube_material_bind_group, &[]);
+                    rpass.set_vertex_buffer(0, cube_vbo.slice(..));
+                    rpass.set_index_buffer(cube_ibo.slice(..), IndexFormat::Uint32);
+                    rpass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+                }
+
+                if let Some(i) = picked_cube {
+                    rpass.set_pipeline(&light_pipeline);
+                    rpass.set_bind_group(1, model_arena.get_bind_group(), &[cube_offsets[i]]);
+                    rpass.set_bind_group(2, &highlight_color_bind_group, &[]);
+                    rpass.set_vertex_buffer(0, cube_vbo.slice(..));
+                    rpass.set_index_buffer(cube_ibo.slice(..), IndexFormat::Uint32);
+                    rpass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+                }
             }
 
-            rpass.set_pipeline(&model_pipeline);
-            for bind_group in &cube_bind_groups {
-                rpass.set_bind_group(1, &bind_group, &[]);
-                rpass.set_bind_group(2, &cube_material_bind_group, &[]);
-                rpass.set_vertex_buffer(0, cube_vbo.slice(..));
-                rpass.set_index_buffer(cube_ibo.slice(..), IndexFormat::Uint32);
-                rpass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+            queue.submit(once(encoder.finish()));
+
+            CullStats {
+                visible: light_cube_stats.visible + cube_stats.visible,
+                culled: light_cube_stats.culled + cube_stats.culled,
+            }
+        };
+
+        let mut target = WgpuSurface {
+            surface: &surface,
+            device: &device,
+            config: &mut surface_config,
+        };
+        let frame = match frame_acquirer.acquire(&mut target) {
+            Ok(Some(frame)) => frame,
+            // The surface needs another frame to recover.
+            Ok(None) => continue,
+            Err(FrameError::DeviceLost) => {
+                stop_recording(&mut recorder, &device);
+                return Exit::DeviceLost;
+            }
+            Err(e) => panic!("failed to get current swapchain texture: {}", e),
+        };
+        let output_texture_view = frame.texture.create_view(&TextureViewDescriptor::default());
+
+        let cull_stats = draw(camera, &output_texture_view);
+        if cull_stats != last_cull_stats {
+            window.set_title(&format!(
+                "{} (visible: {}, culled: {})",
+                TITLE, cull_stats.visible, cull_stats.culled
+            ));
+            last_cull_stats = cull_stats;
+        }
+
+        if take_screenshot {
+            let result = capture_frame(&device, &queue, &frame.texture, |view| {
+                draw(camera, view);
+            });
+            save_capture("screenshot", SCREENSHOT_NAME, result);
+        }
+
+        if let Some(active_recorder) = &mut recorder {
+            let result = active_recorder.capture(&device, &queue, &frame.texture, |view| {
+                draw(camera, view);
+            });
+            if let Err(e) = result {
+                eprintln!("unable to record the frame: {}", e);
+                stop_recording(&mut recorder, &device);
             }
         }
 
-        queue.submit(once(encoder.finish()));
         frame.present();
+
+        if take_poster {
+            let layout = PosterLayout {
+                columns: config.poster_tiles.0,
+                rows: config.poster_tiles.1,
+                tile_width: surface_config.width,
+                tile_height: surface_config.height,
+            };
+            let result = capture_poster(
+                &device,
+                &queue,
+                surface_config.format,
+                layout,
+                camera,
+                |camera, view| {
+                    draw(camera, view);
+                },
+            );
+            save_capture("poster", POSTER_NAME, result);
+        }
     }
+
+    stop_recording(&mut recorder, &device);
+
+    Exit::Quit
 }
 
-fn create_depth_texture(device: &Device, width: u32, height: u32) -> (Texture, TextureView) {
-    let texture = device.create_texture(&TextureDescriptor {
-        label: Some("texture::depth"),
-        size: Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        },
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: TextureDimension::D2,
-        format: TextureFormat::Depth32Float,
-        usage: TextureUsages::RENDER_ATTACHMENT,
-        view_formats: &[],
-    });
+/// Grab or release the cursor, returning whether it's now grabbed. Platforms that can't confine the cursor to the
+/// window get it locked in place instead.
+fn set_cursor_grabbed(window: &Window, grabbed: bool) -> bool {
+    if !grabbed {
+        window
+            .set_cursor_grab(CursorGrabMode::None)
+            .expect("failed to release cursor");
+        window.set_cursor_visible(true);
+        return false;
+    }
+
+    let result = window
+        .set_cursor_grab(CursorGrabMode::Confined)
+        .or_else(|_| window.set_cursor_grab(CursorGrabMode::Locked));
 
-    let texture_view = texture.create_view(&TextureViewDescriptor::default());
+    match result {
+        Ok(()) => {
+            window.set_cursor_visible(false);
+            true
+        }
+        Err(e) => {
+            eprintln!("unable to grab cursor: {}", e);
+            false
+        }
+    }
+}
 
-    (texture, texture_view)
+/// Index of the nearest cube hit by `ray`. Cubes are tested against their bounding boxes first and only the boxes
+/// that are hit are tested triangle by triangle, in model space.
+fn pick_cube(ray: &Ray, models: &[Model], bounds: &[Aabb], positions: &[Vec3]) -> Option<usize> {
+    models
+        .iter()
+        .zip(bounds)
+        .enumerate()
+        .filter(|(_, (_, bounds))| ray.intersect_aabb(bounds).is_some())
+        .filter_map(|(i, (model, _))| {
+            let model_matrix = Mat4::from_cols_array(&model.model_matrix);
+            ray.transform(model_matrix.inverse())
+                .intersect_mesh(positions, &INDICES)
+                .map(|hit| (i, hit.distance))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
 }
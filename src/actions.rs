@@ -0,0 +1,537 @@
+use std::{collections::HashMap, fmt, fs, io, path::Path, str::FromStr};
+
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::{assets, input::InputState};
+
+/// The bindings file the samples read, relative to the assets folder.
+pub const BINDINGS_FILE: &str = "assets/bindings.txt";
+
+/// Something the user can do, independent of the keys or buttons that do it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Sprint,
+    Jump,
+    RollLeft,
+    RollRight,
+    Zoom,
+    Orbit,
+    Pan,
+    ToggleCameraMode,
+    ToggleProjection,
+    ToggleWireframe,
+    ToggleCursorGrab,
+    ToggleWalkMode,
+    RecordCameraPath,
+    PlayCameraPath,
+    IncreaseSpeed,
+    DecreaseSpeed,
+    /// Switch to the next present mode the surface supports.
+    CyclePresentMode,
+    /// Switch the frame limiter between its target frame rate and uncapped.
+    ToggleFrameLimiter,
+    /// Save the current frame as a PNG.
+    Screenshot,
+    /// Save a poster of the current view, several times the size of the window, as a PNG.
+    CapturePoster,
+    /// Start recording every frame, or stop and save the recording.
+    ToggleRecording,
+    /// Held while pressing a bookmark to save into it rather than restore it.
+    SaveBookmark,
+    /// Restore, or save, the camera bookmark in the given slot, counting from 1.
+    Bookmark(u8),
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Action; 38] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Sprint,
+        Action::Jump,
+        Action::RollLeft,
+        Action::RollRight,
+        Action::Zoom,
+        Action::Orbit,
+        Action::Pan,
+        Action::ToggleCameraMode,
+        Action::ToggleProjection,
+        Action::ToggleWireframe,
+        Action::ToggleCursorGrab,
+        Action::ToggleWalkMode,
+        Action::RecordCameraPath,
+        Action::PlayCameraPath,
+        Action::IncreaseSpeed,
+        Action::DecreaseSpeed,
+        Action::CyclePresentMode,
+        Action::ToggleFrameLimiter,
+        Action::Screenshot,
+        Action::CapturePoster,
+        Action::ToggleRecording,
+        Action::SaveBookmark,
+        Action::Bookmark(1),
+        Action::Bookmark(2),
+        Action::Bookmark(3),
+        Action::Bookmark(4),
+        Action::Bookmark(5),
+        Action::Bookmark(6),
+        Action::Bookmark(7),
+        Action::Bookmark(8),
+        Action::Bookmark(9),
+        Action::Quit,
+    ];
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Bookmark(slot) => write!(f, "Bookmark{}", slot),
+            action => fmt::Debug::fmt(action, f),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.to_string() == s)
+            .ok_or_else(|| format!("unknown action `{}`", s))
+    }
+}
+
+/// An input that triggers an action.
+///
+/// Bindings are written as the name of a [`VirtualKeyCode`], e.g. `W` or `LShift`, `MouseLeft`, `MouseRight`,
+/// `MouseMiddle` or `Mouse<n>` for other mouse buttons, `MouseWheel` for scrolling, or keys and buttons joined with
+/// `+` for a chord, e.g. `LControl+S`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    MouseButton(MouseButton),
+    MouseWheel,
+    /// Keys and mouse buttons held together. The chord is pressed when the last of them goes down.
+    Chord(Vec<Binding>),
+}
+
+impl Binding {
+    /// Whether the binding is held. Scrolling is never held.
+    pub fn is_down(&self, input: &InputState) -> bool {
+        match self {
+            Binding::Key(key) => input.is_key_down(*key),
+            Binding::MouseButton(button) => input.is_mouse_button_down(*button),
+            Binding::MouseWheel => false,
+            Binding::Chord(bindings) => bindings.iter().all(|binding| binding.is_down(input)),
+        }
+    }
+
+    /// Whether the binding was triggered since the last frame.
+    pub fn was_pressed(&self, input: &InputState) -> bool {
+        match self {
+            Binding::Key(key) => input.was_key_pressed(*key),
+            Binding::MouseButton(button) => input.was_mouse_button_pressed(*button),
+            Binding::MouseWheel => input.scroll_delta() != 0.0,
+            Binding::Chord(bindings) => {
+                self.is_down(input) && bindings.iter().any(|binding| binding.was_pressed(input))
+            }
+        }
+    }
+
+    /// How much the binding moved since the last frame: lines scrolled for the mouse wheel and one step per press
+    /// otherwise.
+    pub fn value(&self, input: &InputState) -> f32 {
+        match self {
+            Binding::MouseWheel => input.scroll_delta(),
+            binding => binding.was_pressed(input) as i32 as f32,
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::MouseButton(MouseButton::Left) => write!(f, "MouseLeft"),
+            Binding::MouseButton(MouseButton::Right) => write!(f, "MouseRight"),
+            Binding::MouseButton(MouseButton::Middle) => write!(f, "MouseMiddle"),
+            Binding::MouseButton(MouseButton::Other(button)) => write!(f, "Mouse{}", button),
+            Binding::MouseWheel => write!(f, "MouseWheel"),
+            Binding::Chord(bindings) => {
+                for (i, binding) in bindings.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "+")?;
+                    }
+                    write!(f, "{}", binding)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for Binding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if s.contains('+') {
+            let bindings = s
+                .split('+')
+                .map(|part| match part.parse()? {
+                    Binding::MouseWheel => Err(String::from("chords can't contain `MouseWheel`")),
+                    binding => Ok(binding),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(Binding::Chord(bindings));
+        }
+
+        match s {
+            "MouseWheel" => Ok(Binding::MouseWheel),
+            "MouseLeft" => Ok(Binding::MouseButton(MouseButton::Left)),
+            "MouseRight" => Ok(Binding::MouseButton(MouseButton::Right)),
+            "MouseMiddle" => Ok(Binding::MouseButton(MouseButton::Middle)),
+            _ => {
+                if let Some(button) = s.strip_prefix("Mouse").and_then(|n| n.parse().ok()) {
+                    return Ok(Binding::MouseButton(MouseButton::Other(button)));
+                }
+
+                KEYS.iter()
+                    .find(|(name, _)| *name == s)
+                    .map(|(_, key)| Binding::Key(*key))
+                    .ok_or_else(|| format!("unknown key or button `{}`", s))
+            }
+        }
+    }
+}
+
+/// Maps actions to the bindings that trigger them, so samples ask "should the camera move forward?" rather than "is W
+/// held?" and users on other keyboard layouts can rebind everything from a config file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionMap {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for ActionMap {
+    /// WASD movement, Space and left control up and down, left shift sprint, Space to jump, Q/E roll, scroll to
+    /// zoom, left drag to orbit, middle or right drag to pan, Tab to switch camera mode, P to switch projection, F to
+    /// toggle wireframe, G to grab or release the cursor, V to switch between flying and walking, F5/F6 to record and
+    /// play camera paths, `=`/`-` for playback speed, F7 to cycle present modes, F8 to toggle the frame limiter, F12
+    /// to take a screenshot, F11 to capture a poster, F10 to start or stop recording, 1-9 to restore camera bookmarks,
+    /// or save them while holding left alt, and Escape to quit.
+    fn default() -> Self {
+        let mut action_map = Self::new();
+
+        action_map.bind(Action::MoveForward, Binding::Key(VirtualKeyCode::W));
+        action_map.bind(Action::MoveBackward, Binding::Key(VirtualKeyCode::S));
+        action_map.bind(Action::MoveLeft, Binding::Key(VirtualKeyCode::A));
+        action_map.bind(Action::MoveRight, Binding::Key(VirtualKeyCode::D));
+        action_map.bind(Action::MoveUp, Binding::Key(VirtualKeyCode::Space));
+        action_map.bind(Action::MoveDown, Binding::Key(VirtualKeyCode::LControl));
+        action_map.bind(Action::Sprint, Binding::Key(VirtualKeyCode::LShift));
+        action_map.bind(Action::Jump, Binding::Key(VirtualKeyCode::Space));
+        action_map.bind(Action::RollLeft, Binding::Key(VirtualKeyCode::Q));
+        action_map.bind(Action::RollRight, Binding::Key(VirtualKeyCode::E));
+        action_map.bind(Action::Zoom, Binding::MouseWheel);
+        action_map.bind(Action::Orbit, Binding::MouseButton(MouseButton::Left));
+        action_map.bind(Action::Pan, Binding::MouseButton(MouseButton::Middle));
+        action_map.bind(Action::Pan, Binding::MouseButton(MouseButton::Right));
+        action_map.bind(Action::ToggleCameraMode, Binding::Key(VirtualKeyCode::Tab));
+        action_map.bind(Action::ToggleProjection, Binding::Key(VirtualKeyCode::P));
+        action_map.bind(Action::ToggleWireframe, Binding::Key(VirtualKeyCode::F));
+        action_map.bind(Action::ToggleCursorGrab, Binding::Key(VirtualKeyCode::G));
+        action_map.bind(Action::ToggleWalkMode, Binding::Key(VirtualKeyCode::V));
+        action_map.bind(Action::RecordCameraPath, Binding::Key(VirtualKeyCode::F5));
+        action_map.bind(Action::PlayCameraPath, Binding::Key(VirtualKeyCode::F6));
+        action_map.bind(Action::IncreaseSpeed, Binding::Key(VirtualKeyCode::Equals));
+        action_map.bind(Action::DecreaseSpeed, Binding::Key(VirtualKeyCode::Minus));
+        action_map.bind(Action::CyclePresentMode, Binding::Key(VirtualKeyCode::F7));
+        action_map.bind(Action::ToggleFrameLimiter, Binding::Key(VirtualKeyCode::F8));
+        action_map.bind(Action::Screenshot, Binding::Key(VirtualKeyCode::F12));
+        action_map.bind(Action::CapturePoster, Binding::Key(VirtualKeyCode::F11));
+        action_map.bind(Action::ToggleRecording, Binding::Key(VirtualKeyCode::F10));
+        action_map.bind(Action::SaveBookmark, Binding::Key(VirtualKeyCode::LAlt));
+        for (slot, key) in (1..).zip([
+            VirtualKeyCode::Key1,
+            VirtualKeyCode::Key2,
+            VirtualKeyCode::Key3,
+            VirtualKeyCode::Key4,
+            VirtualKeyCode::Key5,
+            VirtualKeyCode::Key6,
+            VirtualKeyCode::Key7,
+            VirtualKeyCode::Key8,
+            VirtualKeyCode::Key9,
+        ]) {
+            action_map.bind(Action::Bookmark(slot), Binding::Key(key));
+        }
+        action_map.bind(Action::Quit, Binding::Key(VirtualKeyCode::Escape));
+
+        action_map
+    }
+}
+
+impl ActionMap {
+    /// An action map with nothing bound.
+    pub fn new() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Add `binding` to the bindings of `action`.
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Remove every binding of `action`.
+    pub fn unbind(&mut self, action: Action) {
+        self.bindings.remove(&action);
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether any binding of `action` is held.
+    pub fn is_down(&self, input: &InputState, action: Action) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| binding.is_down(input))
+    }
+
+    /// Whether any binding of `action` was triggered since the last frame.
+    pub fn was_pressed(&self, input: &InputState, action: Action) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| binding.was_pressed(input))
+    }
+
+    /// The total movement of the bindings of `action` since the last frame, see [`Binding::value`].
+    pub fn value(&self, input: &InputState, action: Action) -> f32 {
+        self.bindings(action)
+            .iter()
+            .map(|binding| binding.value(input))
+            .sum()
+    }
+
+    /// 1 while `positive` is held, -1 while `negative` is held and 0 while both or neither are.
+    pub fn axis(&self, input: &InputState, positive: Action, negative: Action) -> f32 {
+        self.is_down(input, positive) as i32 as f32 - self.is_down(input, negative) as i32 as f32
+    }
+
+    /// The default bindings overridden by `contents`, one action per line: `Action = Binding, Binding`. An action
+    /// with nothing after the `=` is unbound. Blank lines and lines starting with `#` are ignored.
+    pub fn parse(contents: &str) -> io::Result<Self> {
+        let mut action_map = Self::default();
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (action, bindings) = line
+                .split_once('=')
+                .ok_or_else(|| assets::invalid_data(line_number, "expected `Action = Binding`"))?;
+
+            let action = action
+                .trim()
+                .parse()
+                .map_err(|e: String| assets::invalid_data(line_number, &e))?;
+
+            action_map.unbind(action);
+            for binding in bindings
+                .split(',')
+                .filter(|binding| !binding.trim().is_empty())
+            {
+                let binding = binding
+                    .parse()
+                    .map_err(|e: String| assets::invalid_data(line_number, &e))?;
+                action_map.bind(action, binding);
+            }
+        }
+
+        Ok(action_map)
+    }
+
+    /// Read bindings written by [`ActionMap::save`] or by hand, see [`ActionMap::parse`].
+    pub fn load<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// The default bindings overridden by the bindings asset at `path`, see [`assets::load`], or just the defaults if
+    /// it can't be loaded so that a broken bindings file doesn't bring the whole sample down.
+    pub fn load_asset_or_default<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let result = assets::load(path.as_ref())
+            .and_then(|contents| Self::parse(&String::from_utf8_lossy(&contents)));
+
+        match result {
+            Ok(action_map) => action_map,
+            Err(e) => {
+                eprintln!(
+                    "unable to load {}, using the default bindings: {}",
+                    path.as_ref().display(),
+                    e
+                );
+                Self::default()
+            }
+        }
+    }
+
+    /// Write every action and its bindings in the format read by [`ActionMap::load`].
+    pub fn save<P>(&self, path: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let mut contents = String::from("# Action = Binding, Binding\n");
+        for action in Action::ALL {
+            let bindings = self
+                .bindings(action)
+                .iter()
+                .map(Binding::to_string)
+                .collect::<Vec<_>>();
+            contents.push_str(&format!("{} = {}\n", action, bindings.join(", ")));
+        }
+
+        fs::write(path, contents)
+    }
+}
+
+macro_rules! keys {
+    ($($key:ident)*) => {
+        /// Every key with the name it's bound by, which is the name of its [`VirtualKeyCode`] variant.
+        const KEYS: &[(&str, VirtualKeyCode)] = &[$((stringify!($key), VirtualKeyCode::$key)),*];
+    };
+}
+
+keys! {
+    Key1 Key2 Key3 Key4 Key5 Key6 Key7 Key8 Key9 Key0
+    A B C D E F G H I J K L M N O P Q R S T U V W X Y Z
+    Escape F1 F2 F3 F4 F5 F6 F7 F8 F9 F10 F11 F12 F13 F14 F15 F16 F17 F18 F19 F20 F21 F22 F23 F24
+    Snapshot Scroll Pause Insert Home Delete End PageDown PageUp Left Up Right Down Back Return Space Compose Caret
+    Numlock Numpad0 Numpad1 Numpad2 Numpad3 Numpad4 Numpad5 Numpad6 Numpad7 Numpad8 Numpad9 NumpadAdd NumpadDivide
+    NumpadDecimal NumpadComma NumpadEnter NumpadEquals NumpadMultiply NumpadSubtract
+    AbntC1 AbntC2 Apostrophe Apps Asterisk At Ax Backslash Calculator Capital Colon Comma Convert Equals Grave Kana
+    Kanji LAlt LBracket LControl LShift LWin Mail MediaSelect MediaStop Minus Mute MyComputer NavigateForward
+    NavigateBackward NextTrack NoConvert OEM102 Period PlayPause Plus Power PrevTrack RAlt RBracket RControl RShift
+    RWin Semicolon Slash Sleep Stop Sysrq Tab Underline Unlabeled VolumeDown VolumeUp Wake WebBack WebFavorites
+    WebForward WebHome WebRefresh WebSearch WebStop Yen Copy Paste Cut
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io::ErrorKind, process};
+
+    use super::*;
+
+    #[test]
+    fn keys_and_buttons_parse_by_name() {
+        assert_eq!("W".parse(), Ok(Binding::Key(VirtualKeyCode::W)));
+        assert_eq!(" LShift ".parse(), Ok(Binding::Key(VirtualKeyCode::LShift)));
+        assert_eq!(
+            "MouseRight".parse(),
+            Ok(Binding::MouseButton(MouseButton::Right))
+        );
+        assert_eq!(
+            "Mouse4".parse(),
+            Ok(Binding::MouseButton(MouseButton::Other(4)))
+        );
+        assert_eq!("MouseWheel".parse(), Ok(Binding::MouseWheel));
+        assert!("NotAKey".parse::<Binding>().is_err());
+    }
+
+    #[test]
+    fn chords_parse_and_display_joined_with_plus() {
+        let chord = Binding::Chord(vec![
+            Binding::Key(VirtualKeyCode::LControl),
+            Binding::MouseButton(MouseButton::Left),
+        ]);
+
+        assert_eq!("LControl + MouseLeft".parse(), Ok(chord.clone()));
+        assert_eq!(chord.to_string(), "LControl+MouseLeft");
+        assert!("LControl+MouseWheel".parse::<Binding>().is_err());
+        assert!("LControl+".parse::<Binding>().is_err());
+    }
+
+    #[test]
+    fn every_action_displays_as_it_parses() {
+        for action in Action::ALL {
+            assert_eq!(action.to_string().parse(), Ok(action));
+        }
+        assert!("Bookmark0".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn parse_overrides_only_the_listed_actions() {
+        let contents = "# a comment\n\nMoveForward = Up, I\nJump =\n";
+        let action_map = ActionMap::parse(contents).unwrap();
+
+        assert_eq!(
+            action_map.bindings(Action::MoveForward),
+            [
+                Binding::Key(VirtualKeyCode::Up),
+                Binding::Key(VirtualKeyCode::I)
+            ]
+        );
+        assert!(action_map.bindings(Action::Jump).is_empty());
+        assert_eq!(
+            action_map.bindings(Action::MoveBackward),
+            ActionMap::default().bindings(Action::MoveBackward)
+        );
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        for contents in [
+            "MoveForward = W\nMoveForward W",
+            "\nNotAnAction = W",
+            "\nJump = NotAKey",
+        ] {
+            let error = ActionMap::parse(contents).unwrap_err();
+
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert!(error.to_string().starts_with("line 2: "), "{}", error);
+        }
+    }
+
+    #[test]
+    fn saved_bindings_load_the_same() {
+        let mut action_map = ActionMap::default();
+        action_map.unbind(Action::Quit);
+        action_map.bind(
+            Action::Screenshot,
+            Binding::Chord(vec![
+                Binding::Key(VirtualKeyCode::LControl),
+                Binding::Key(VirtualKeyCode::S),
+            ]),
+        );
+
+        let path = env::temp_dir().join(format!("round-trip-{}.bindings", process::id()));
+        action_map.save(&path).unwrap();
+        let loaded = ActionMap::load(&path);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.unwrap(), action_map);
+    }
+}
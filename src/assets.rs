@@ -1,8 +1,13 @@
 use std::{
-    env, fs, io,
+    env, fs,
+    io::{self, ErrorKind},
     path::{Path, PathBuf},
 };
 
+use image::RgbaImage;
+
+use crate::procedural;
+
 fn get_base_path() -> PathBuf {
     if let Ok(cargo_manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
         PathBuf::from(cargo_manifest_dir)
@@ -23,3 +28,47 @@ where
     let abs_path = get_base_path().join(path);
     fs::read(abs_path)
 }
+
+/// Path for a file the samples write, such as a screenshot, relative to the output folder. The output folder is
+/// created if it doesn't exist yet.
+pub fn output_path<P>(path: P) -> io::Result<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    let output_dir = get_base_path().join("output");
+    fs::create_dir_all(&output_dir)?;
+
+    Ok(output_dir.join(path))
+}
+
+/// Error for a malformed line in one of the text files the samples read, where `line_number` counts from zero and is
+/// reported counting from one.
+pub fn invalid_data(line_number: usize, message: &str) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("line {}: {}", line_number + 1, message),
+    )
+}
+
+/// Load and decode the image at the given `path` relative to the assets folder. If the image can't be read or decoded
+/// then the missing texture is returned instead so that a bad material path doesn't bring the whole sample down.
+pub fn load_image_or_missing<P>(path: P) -> RgbaImage
+where
+    P: AsRef<Path>,
+{
+    let image = load(path.as_ref())
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| image::load_from_memory(&bytes).map_err(|e| e.to_string()));
+
+    match image {
+        Ok(image) => image.to_rgba8(),
+        Err(e) => {
+            eprintln!(
+                "unable to load image {}, using missing texture: {}",
+                path.as_ref().display(),
+                e
+            );
+            procedural::missing_texture()
+        }
+    }
+}
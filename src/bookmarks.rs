@@ -0,0 +1,376 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use glam::{EulerRot, Quat, Vec3};
+
+use crate::{
+    actions::{Action, ActionMap},
+    assets,
+    camera::{Camera, CameraMode, Projection},
+    input::InputState,
+};
+
+/// Number of bookmark slots, numbered from 1.
+pub const BOOKMARK_SLOTS: u8 = 9;
+
+/// Everything needed to put a camera back exactly where it was. Angles are in degrees, with yaw and pitch following
+/// the camera's fly mode conventions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bookmark {
+    pub mode: CameraMode,
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Only non-zero for cameras in free mode.
+    pub roll: f32,
+    /// The orbit target.
+    pub target: Vec3,
+    pub projection: Projection,
+}
+
+impl Bookmark {
+    pub fn from_camera(camera: &Camera) -> Self {
+        let (yaw, pitch, roll) = camera.get_orientation().to_euler(EulerRot::YXZ);
+
+        Self {
+            mode: camera.get_mode(),
+            position: camera.get_position(),
+            // Yaw is measured from +X, while the orientation's rotation is measured from the default view down -Z.
+            yaw: wrap_degrees(-90.0 - yaw.to_degrees()),
+            pitch: pitch.to_degrees(),
+            roll: roll.to_degrees(),
+            target: camera.get_target(),
+            projection: camera.get_projection(),
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        let orientation = Quat::from_euler(
+            EulerRot::YXZ,
+            (-90.0 - self.yaw).to_radians(),
+            self.pitch.to_radians(),
+            self.roll.to_radians(),
+        );
+
+        // Free mode takes the position and orientation as they are; switching to the bookmarked mode afterwards
+        // derives the yaw and pitch or the orbit around the target from them.
+        camera.set_mode(CameraMode::Free);
+        camera.set_position(self.position);
+        camera.set_orientation(orientation);
+        camera.set_target(self.target);
+        camera.set_projection(self.projection);
+        if self.mode != CameraMode::Free {
+            camera.set_mode(self.mode);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookmarkEvent {
+    Saved(u8),
+    Restored(u8),
+}
+
+/// Camera bookmarks in numbered slots.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Bookmarks {
+    slots: BTreeMap<u8, Bookmark>,
+}
+
+impl Bookmarks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, slot: u8) -> Option<&Bookmark> {
+        self.slots.get(&slot)
+    }
+
+    pub fn set(&mut self, slot: u8, bookmark: Bookmark) {
+        self.slots.insert(slot, bookmark);
+    }
+
+    pub fn remove(&mut self, slot: u8) -> Option<Bookmark> {
+        self.slots.remove(&slot)
+    }
+
+    /// The bookmarks ordered by slot.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &Bookmark)> {
+        self.slots.iter().map(|(slot, bookmark)| (*slot, bookmark))
+    }
+
+    /// Save the camera into a slot when its bookmark action is pressed while [`Action::SaveBookmark`] is held, or
+    /// restore the slot into the camera when it's pressed alone. Empty slots aren't restored.
+    pub fn update(
+        &mut self,
+        input: &InputState,
+        actions: &ActionMap,
+        camera: &mut Camera,
+    ) -> Option<BookmarkEvent> {
+        let slot = (1..=BOOKMARK_SLOTS)
+            .find(|slot| actions.was_pressed(input, Action::Bookmark(*slot)))?;
+
+        if actions.is_down(input, Action::SaveBookmark) {
+            self.set(slot, Bookmark::from_camera(camera));
+            Some(BookmarkEvent::Saved(slot))
+        } else {
+            self.get(slot)?.apply(camera);
+            Some(BookmarkEvent::Restored(slot))
+        }
+    }
+
+    /// Write the bookmarks as text, one per line: `slot mode px py pz yaw pitch roll tx ty tz projection values...`,
+    /// where the projection is `perspective fov_y`, `orthographic height` or `off-axis left right bottom top`.
+    pub fn save<P>(&self, path: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let mut contents =
+            String::from("# slot mode px py pz yaw pitch roll tx ty tz projection values...\n");
+        for (slot, bookmark) in self.iter() {
+            let p = bookmark.position;
+            let t = bookmark.target;
+            let projection = match bookmark.projection {
+                Projection::Perspective { fov_y } => format!("perspective {}", fov_y),
+                Projection::Orthographic { height } => format!("orthographic {}", height),
+                Projection::OffAxis {
+                    left,
+                    right,
+                    bottom,
+                    top,
+                } => format!("off-axis {} {} {} {}", left, right, bottom, top),
+            };
+            contents.push_str(&format!(
+                "{} {} {} {} {} {} {} {} {} {} {} {}\n",
+                slot,
+                mode_name(bookmark.mode),
+                p.x,
+                p.y,
+                p.z,
+                bookmark.yaw,
+                bookmark.pitch,
+                bookmark.roll,
+                t.x,
+                t.y,
+                t.z,
+                projection
+            ));
+        }
+
+        fs::write(path, contents)
+    }
+
+    /// Read bookmarks written by [`Bookmarks::save`]. Blank lines and lines starting with `#` are ignored, and slots
+    /// outside 1 to [`BOOKMARK_SLOTS`] are invalid.
+    pub fn load<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let contents = fs::read_to_string(path)?;
+        let mut bookmarks = Self::new();
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let [slot, mode, px, py, pz, yaw, pitch, roll, tx, ty, tz, projection, values @ ..] =
+                &fields[..]
+            else {
+                return Err(assets::invalid_data(
+                    line_number,
+                    "expected at least 13 values",
+                ));
+            };
+
+            let slot = slot
+                .parse::<u8>()
+                .map_err(|e| assets::invalid_data(line_number, &e.to_string()))?;
+            if !(1..=BOOKMARK_SLOTS).contains(&slot) {
+                return Err(assets::invalid_data(
+                    line_number,
+                    &format!("slot must be from 1 to {}", BOOKMARK_SLOTS),
+                ));
+            }
+            let mode = parse_mode(mode)
+                .ok_or_else(|| assets::invalid_data(line_number, "unknown camera mode"))?;
+            let numbers = [px, py, pz, yaw, pitch, roll, tx, ty, tz]
+                .into_iter()
+                .chain(values)
+                .map(|value| value.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| assets::invalid_data(line_number, &e.to_string()))?;
+
+            let projection = match (*projection, &numbers[9..]) {
+                ("perspective", [fov_y]) => Projection::Perspective { fov_y: *fov_y },
+                ("orthographic", [height]) => Projection::Orthographic { height: *height },
+                ("off-axis", [left, right, bottom, top]) => Projection::OffAxis {
+                    left: *left,
+                    right: *right,
+                    bottom: *bottom,
+                    top: *top,
+                },
+                _ => return Err(assets::invalid_data(line_number, "invalid projection")),
+            };
+
+            bookmarks.set(
+                slot,
+                Bookmark {
+                    mode,
+                    position: Vec3::new(numbers[0], numbers[1], numbers[2]),
+                    yaw: numbers[3],
+                    pitch: numbers[4],
+                    roll: numbers[5],
+                    target: Vec3::new(numbers[6], numbers[7], numbers[8]),
+                    projection,
+                },
+            );
+        }
+
+        Ok(bookmarks)
+    }
+
+    /// The bookmarks saved at `path` in the output folder, see [`assets::output_path`], or none if there aren't any
+    /// yet or they can't be read.
+    pub fn load_output_or_default<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        match assets::output_path(path).and_then(Self::load) {
+            Ok(bookmarks) => bookmarks,
+            Err(e) if e.kind() == ErrorKind::NotFound => Self::new(),
+            Err(e) => {
+                eprintln!("unable to load camera bookmarks: {}", e);
+                Self::new()
+            }
+        }
+    }
+
+    /// Save the bookmarks to `path` in the output folder, reporting where they went or why they couldn't be saved.
+    pub fn save_output<P>(&self, path: P)
+    where
+        P: AsRef<Path>,
+    {
+        let result = assets::output_path(path).and_then(|path| {
+            self.save(&path)?;
+            Ok(path)
+        });
+
+        match result {
+            Ok(path) => println!("saved camera bookmarks to {}", path.display()),
+            Err(e) => eprintln!("unable to save camera bookmarks: {}", e),
+        }
+    }
+}
+
+fn mode_name(mode: CameraMode) -> &'static str {
+    match mode {
+        CameraMode::Fly => "fly",
+        CameraMode::Orbit => "orbit",
+        CameraMode::Free => "free",
+    }
+}
+
+fn parse_mode(name: &str) -> Option<CameraMode> {
+    match name {
+        "fly" => Some(CameraMode::Fly),
+        "orbit" => Some(CameraMode::Orbit),
+        "free" => Some(CameraMode::Free),
+        _ => None,
+    }
+}
+
+/// `degrees` wrapped into `(-180, 180]`.
+fn wrap_degrees(degrees: f32) -> f32 {
+    let wrapped = degrees.rem_euclid(360.0);
+    if wrapped > 180.0 {
+        wrapped - 360.0
+    } else {
+        wrapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::PathBuf, process};
+
+    use super::*;
+
+    /// A file in the temporary folder holding `contents`, named after `name` and this process so tests don't collide.
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("{}-{}.bookmarks", name, process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn load(name: &str, contents: &str) -> io::Result<Bookmarks> {
+        let path = temp_file(name, contents);
+        let result = Bookmarks::load(&path);
+        fs::remove_file(path).unwrap();
+        result
+    }
+
+    #[test]
+    fn saved_bookmarks_load_the_same() {
+        let mut bookmarks = Bookmarks::new();
+        bookmarks.set(
+            1,
+            Bookmark {
+                mode: CameraMode::Fly,
+                position: Vec3::new(1.0, 2.0, 3.0),
+                yaw: -90.0,
+                pitch: 10.0,
+                roll: 0.0,
+                target: Vec3::ZERO,
+                projection: Projection::Perspective { fov_y: 45.0 },
+            },
+        );
+        bookmarks.set(
+            9,
+            Bookmark {
+                mode: CameraMode::Orbit,
+                position: Vec3::new(0.0, 5.0, 5.0),
+                yaw: 0.0,
+                pitch: -45.0,
+                roll: 0.0,
+                target: Vec3::new(0.0, 1.0, 0.0),
+                projection: Projection::Orthographic { height: 4.0 },
+            },
+        );
+
+        let path = temp_file("round-trip", "");
+        bookmarks.save(&path).unwrap();
+        let loaded = Bookmarks::load(&path);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.unwrap(), bookmarks);
+    }
+
+    #[test]
+    fn slots_outside_the_range_are_invalid() {
+        for slot in [0, BOOKMARK_SLOTS + 1] {
+            let contents = format!("{} fly 0 0 0 -90 0 0 0 0 0 perspective 45\n", slot);
+            let error = load("out-of-range", &contents).unwrap_err();
+
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let contents = "# a comment\n\n3 free 0 0 0 0 0 15 0 0 0 perspective 30\n";
+        let bookmarks = load("comments", contents).unwrap();
+
+        assert_eq!(
+            bookmarks.iter().map(|(slot, _)| slot).collect::<Vec<_>>(),
+            [3]
+        );
+        assert_eq!(bookmarks.get(3).unwrap().roll, 15.0);
+    }
+}
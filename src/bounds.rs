@@ -0,0 +1,86 @@
+use glam::{Mat4, Vec3};
+
+/// Axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self {
+            min: min.min(max),
+            max: min.max(max),
+        }
+    }
+
+    pub fn from_center_half_extents(center: Vec3, half_extents: Vec3) -> Self {
+        Self::new(center - half_extents, center + half_extents)
+    }
+
+    /// The smallest box containing all of `points`, or `None` if there are no points.
+    pub fn from_points<I>(points: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = Vec3>,
+    {
+        points.into_iter().fold(None, |aabb, point| match aabb {
+            None => Some(Self::new(point, point)),
+            Some(aabb) => Some(Self::new(aabb.min.min(point), aabb.max.max(point))),
+        })
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.max).all() && self.max.cmpge(other.min).all()
+    }
+
+    /// The box grown by `amount` on every side.
+    pub fn expand(&self, amount: Vec3) -> Self {
+        Self::new(self.min - amount, self.max + amount)
+    }
+
+    /// The axis aligned box containing this box after it's been transformed by `matrix`.
+    pub fn transform(&self, matrix: Mat4) -> Self {
+        let center = matrix.transform_point3(self.center());
+        let half_extents = self.half_extents();
+        // Each world axis extent is the sum of the local half extents projected onto that axis.
+        let half_extents = Vec3::new(
+            Vec3::new(matrix.x_axis.x, matrix.y_axis.x, matrix.z_axis.x)
+                .abs()
+                .dot(half_extents),
+            Vec3::new(matrix.x_axis.y, matrix.y_axis.y, matrix.z_axis.y)
+                .abs()
+                .dot(half_extents),
+            Vec3::new(matrix.x_axis.z, matrix.y_axis.z, matrix.z_axis.z)
+                .abs()
+                .dot(half_extents),
+        );
+
+        Self::from_center_half_extents(center, half_extents)
+    }
+
+    pub fn bounding_sphere(&self) -> Sphere {
+        Sphere {
+            center: self.center(),
+            radius: self.half_extents().length(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
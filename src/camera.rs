@@ -1,9 +1,57 @@
 use bytemuck_derive::{Pod, Zeroable};
-use glam::{Mat4, Vec3};
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3, Vec4};
+use winit::event::WindowEvent;
+
+use crate::{depth::DepthMode, frustum::Frustum, picking::Ray, viewport::Viewport};
+
+pub const DEFAULT_FOV_Y: f32 = 45.0;
+
+const MIN_FOV_Y: f32 = 1.0;
+const MAX_FOV_Y: f32 = 45.0;
+const MIN_ORTHOGRAPHIC_HEIGHT: f32 = 0.01;
+const DEFAULT_FOCUS_DISTANCE: f32 = 3.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// First person camera that flies around the scene, looking where the mouse points it.
+    #[default]
+    Fly,
+    /// Camera that orbits around a target point, for inspecting a single model.
+    Orbit,
+    /// Six degrees of freedom camera with a quaternion orientation. It can roll and loop without gimbal lock, for
+    /// flight and space scenes.
+    Free,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Perspective projection with a vertical field of view in degrees.
+    Perspective { fov_y: f32 },
+    /// Orthographic projection showing `height` world units vertically; the width follows the aspect ratio.
+    Orthographic { height: f32 },
+    /// Asymmetric perspective frustum given by its extents on the near plane, e.g. for rendering one tile of a larger
+    /// image. The aspect ratio is ignored.
+    OffAxis {
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+    },
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective {
+            fov_y: DEFAULT_FOV_Y,
+        }
+    }
+}
 
 pub struct CameraDescriptor {
+    pub mode: CameraMode,
     pub aspect_ratio: f32,
-    pub fov_y: f32,
+    pub projection: Projection,
+    pub depth_mode: DepthMode,
     pub z_near: f32,
     pub z_far: f32,
     pub position: Vec3,
@@ -13,13 +61,18 @@ pub struct CameraDescriptor {
     pub yaw: f32,
     pub pitch: f32,
     pub mouse_sensitivity: f32,
+    pub target: Vec3,
+    pub pan_sensitivity: f32,
+    pub min_distance: f32,
 }
 
 impl Default for CameraDescriptor {
     fn default() -> Self {
         Self {
+            mode: CameraMode::Fly,
             aspect_ratio: 16.0 / 9.0,
-            fov_y: 45.0,
+            projection: Projection::default(),
+            depth_mode: DepthMode::Standard,
             z_near: 0.1,
             z_far: 100.0,
             position: Vec3::new(0.0, 0.0, 3.0),
@@ -29,13 +82,19 @@ impl Default for CameraDescriptor {
             yaw: -90.0,
             pitch: 0.0,
             mouse_sensitivity: 0.1,
+            target: Vec3::ZERO,
+            pan_sensitivity: 0.002,
+            min_distance: 0.1,
         }
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct Camera {
+    mode: CameraMode,
     aspect_ratio: f32,
-    fov_y: f32,
+    projection: Projection,
+    depth_mode: DepthMode,
     z_near: f32,
     z_far: f32,
     position: Vec3,
@@ -46,13 +105,22 @@ pub struct Camera {
     pitch: f32,
     mouse_sensitivity: f32,
     has_mouse: bool,
+    target: Vec3,
+    distance: f32,
+    azimuth: f32,
+    elevation: f32,
+    pan_sensitivity: f32,
+    min_distance: f32,
+    orientation: Quat,
 }
 
 impl Camera {
     pub fn new(desc: &CameraDescriptor) -> Self {
-        Self {
+        let mut camera = Self {
+            mode: CameraMode::Fly,
             aspect_ratio: desc.aspect_ratio,
-            fov_y: desc.fov_y,
+            projection: desc.projection,
+            depth_mode: desc.depth_mode,
             z_near: desc.z_near,
             z_far: desc.z_far,
             position: desc.position,
@@ -63,9 +131,77 @@ impl Camera {
             pitch: desc.pitch,
             mouse_sensitivity: desc.mouse_sensitivity,
             has_mouse: false,
+            target: desc.target,
+            distance: 0.0,
+            azimuth: 0.0,
+            elevation: 0.0,
+            pan_sensitivity: desc.pan_sensitivity,
+            min_distance: desc.min_distance,
+            orientation: orientation_from_direction(desc.direction, desc.up),
+        };
+        if desc.mode != CameraMode::Fly {
+            camera.set_mode(desc.mode);
+        }
+
+        camera
+    }
+
+    pub fn get_mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    /// Switch between camera modes. The camera keeps its position when switching so that the view doesn't jump;
+    /// entering orbit mode turns the camera to face the target and leaving free mode levels out any roll.
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        match mode {
+            CameraMode::Fly => {
+                self.update_yaw_pitch();
+                self.update_direction();
+            }
+
+            CameraMode::Free => self.orientation = self.get_orientation(),
+
+            CameraMode::Orbit => {
+                let offset = self.position - self.target;
+                self.distance = offset.length().max(self.min_distance);
+                self.azimuth = offset.z.atan2(offset.x).to_degrees();
+                self.elevation = (offset.y / self.distance)
+                    .clamp(-1.0, 1.0)
+                    .asin()
+                    .to_degrees()
+                    .clamp(-89.0, 89.0);
+            }
+        }
+
+        self.mode = mode;
+        if self.mode == CameraMode::Orbit {
+            self.update_orbit();
+        }
+    }
+
+    pub fn toggle_mode(&mut self) {
+        match self.mode {
+            CameraMode::Fly => self.set_mode(CameraMode::Orbit),
+            CameraMode::Orbit => self.set_mode(CameraMode::Free),
+            CameraMode::Free => self.set_mode(CameraMode::Fly),
+        }
+    }
+
+    pub fn get_target(&self) -> Vec3 {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: Vec3) {
+        self.target = target;
+        if self.mode == CameraMode::Orbit {
+            self.set_mode(CameraMode::Orbit);
         }
     }
 
+    pub fn get_distance(&self) -> f32 {
+        self.distance
+    }
+
     pub fn set_has_mouse(&mut self, has_mouse: bool) {
         self.has_mouse = has_mouse;
     }
@@ -78,23 +214,314 @@ impl Camera {
         self.position
     }
 
+    /// Move the camera to `position`. In orbit mode the camera keeps looking at its target from the new position.
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+        if self.mode == CameraMode::Orbit {
+            self.set_mode(CameraMode::Orbit);
+        }
+    }
+
     pub fn get_direction(&self) -> Vec3 {
         self.direction
     }
 
+    pub fn get_right(&self) -> Vec3 {
+        self.direction.cross(self.get_up()).normalize()
+    }
+
+    /// The camera's up vector. This is the world up vector except in free mode, where it follows the camera's roll.
+    pub fn get_up(&self) -> Vec3 {
+        match self.mode {
+            CameraMode::Free => self.orientation * Vec3::Y,
+            _ => self.up,
+        }
+    }
+
+    /// The rotation from camera space, looking down -Z with +Y up, to world space.
+    pub fn get_orientation(&self) -> Quat {
+        match self.mode {
+            CameraMode::Free => self.orientation,
+            _ => orientation_from_direction(self.direction, self.up),
+        }
+    }
+
+    /// Point the camera using a camera space to world space rotation. Only free mode keeps any roll in `orientation`;
+    /// in orbit mode the camera moves around the target so that it looks along the new direction.
+    pub fn set_orientation(&mut self, orientation: Quat) {
+        let direction = orientation.normalize() * Vec3::NEG_Z;
+
+        match self.mode {
+            CameraMode::Fly => {
+                self.direction = direction;
+                self.update_yaw_pitch();
+                self.update_direction();
+            }
+
+            CameraMode::Orbit => {
+                self.position = self.target - direction * self.distance;
+                self.set_mode(CameraMode::Orbit);
+            }
+
+            CameraMode::Free => {
+                self.orientation = orientation.normalize();
+                self.direction = direction;
+            }
+        }
+    }
+
+    /// Spherically interpolate from the current orientation towards `orientation` by `t`, where 0 leaves the camera
+    /// as it is and 1 is the same as calling [`Camera::set_orientation`].
+    pub fn slerp_orientation(&mut self, orientation: Quat, t: f32) {
+        let from = self.get_orientation();
+        self.set_orientation(from.slerp(orientation.normalize(), t));
+    }
+
+    /// Turn the camera to face `target`. In orbit mode `target` becomes the new orbit target.
+    pub fn look_at(&mut self, target: Vec3) {
+        let direction = (target - self.position).normalize_or_zero();
+        if direction == Vec3::ZERO {
+            return;
+        }
+
+        match self.mode {
+            CameraMode::Fly => {
+                self.direction = direction;
+                self.update_yaw_pitch();
+                self.update_direction();
+            }
+
+            CameraMode::Orbit => self.set_target(target),
+
+            CameraMode::Free => {
+                let up = self.get_up();
+                let up = if direction.cross(up).length_squared() < 1e-6 {
+                    self.orientation * Vec3::Z
+                } else {
+                    up
+                };
+                self.orientation = orientation_from_direction(direction, up);
+                self.direction = direction;
+            }
+        }
+    }
+
+    pub fn get_speed(&self) -> f32 {
+        self.speed
+    }
+
     pub fn get_view_matrix(&self) -> Mat4 {
-        Mat4::look_to_rh(self.position, self.direction, self.up)
+        Mat4::look_to_rh(self.position, self.direction, self.get_up())
     }
 
+    pub fn get_projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    /// The vertical field of view in degrees, if the camera has a perspective projection.
+    pub fn get_fov_y(&self) -> Option<f32> {
+        match self.projection {
+            Projection::Perspective { fov_y } => Some(fov_y),
+            _ => None,
+        }
+    }
+
+    /// Set the vertical field of view in degrees. This does nothing unless the camera has a perspective projection.
+    pub fn set_fov_y(&mut self, fov_y: f32) {
+        if let Projection::Perspective { fov_y: current } = &mut self.projection {
+            *current = fov_y.clamp(MIN_FOV_Y, MAX_FOV_Y);
+        }
+    }
+
+    /// Switch between perspective and orthographic projection. The new projection is sized so that objects at the
+    /// focus distance, i.e. the target in orbit mode, appear the same size as before.
+    pub fn toggle_projection(&mut self) {
+        let distance = self.focus_distance();
+
+        self.projection = match self.projection {
+            Projection::Perspective { fov_y } => Projection::Orthographic {
+                height: 2.0 * distance * (fov_y.to_radians() * 0.5).tan(),
+            },
+            Projection::Orthographic { height } => Projection::Perspective {
+                fov_y: (2.0 * (height * 0.5 / distance).atan())
+                    .to_degrees()
+                    .clamp(MIN_FOV_Y, MAX_FOV_Y),
+            },
+            Projection::OffAxis { bottom, top, .. } => Projection::Perspective {
+                fov_y: (2.0 * ((top - bottom) * 0.5 / self.z_near).atan())
+                    .to_degrees()
+                    .clamp(MIN_FOV_Y, MAX_FOV_Y),
+            },
+        };
+    }
+
+    /// The off-axis projection showing one of `columns` by `rows` tiles of what the camera sees, counting from the
+    /// top left, so the tiles can be rendered separately and stitched into a larger image. `None` for orthographic
+    /// projections, which have no off-axis equivalent.
+    pub fn tile_projection(
+        &self,
+        column: u32,
+        row: u32,
+        columns: u32,
+        rows: u32,
+    ) -> Option<Projection> {
+        let (left, right, bottom, top) = match self.projection {
+            Projection::Perspective { fov_y } => {
+                let top = self.z_near * (fov_y.to_radians() * 0.5).tan();
+                let right = top * self.aspect_ratio;
+                (-right, right, -top, top)
+            }
+            Projection::OffAxis {
+                left,
+                right,
+                bottom,
+                top,
+            } => (left, right, bottom, top),
+            Projection::Orthographic { .. } => return None,
+        };
+
+        let tile_width = (right - left) / columns as f32;
+        let tile_height = (top - bottom) / rows as f32;
+        let tile_left = left + tile_width * column as f32;
+        let tile_top = top - tile_height * row as f32;
+
+        Some(Projection::OffAxis {
+            left: tile_left,
+            right: tile_left + tile_width,
+            bottom: tile_top - tile_height,
+            top: tile_top,
+        })
+    }
+
+    pub fn get_aspect_ratio(&self) -> f32 {
+        self.aspect_ratio
+    }
+
+    /// Ignores degenerate ratios, such as those of a minimized window, keeping the last usable one.
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        if aspect_ratio.is_finite() && aspect_ratio > 0.0 {
+            self.aspect_ratio = aspect_ratio;
+        }
+    }
+
+    /// Match the projection to the viewport the camera renders into. Call this whenever a viewport other than the
+    /// whole window is resized; empty viewports are ignored.
+    pub fn set_viewport(&mut self, viewport: &Viewport) {
+        if let Some(aspect_ratio) = viewport.aspect_ratio() {
+            self.set_aspect_ratio(aspect_ratio);
+        }
+    }
+
+    /// Keep the projection matched to a camera rendering to the whole window, by passing it every window event.
+    pub fn process_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::Resized(size) => {
+                self.set_viewport(&Viewport::from_size(size.width, size.height));
+            }
+
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                self.set_viewport(&Viewport::from_size(
+                    new_inner_size.width,
+                    new_inner_size.height,
+                ));
+            }
+
+            _ => (),
+        }
+    }
+
+    /// The depth mode the projection matrix is built for. Depth textures and pipelines must be created to match,
+    /// see [`DepthMode::depth_stencil_state`].
+    pub fn get_depth_mode(&self) -> DepthMode {
+        self.depth_mode
+    }
+
+    /// In [`DepthMode::ReverseInfinite`] the perspective projections have no far plane. An orthographic projection
+    /// can't be infinite so it keeps its far plane but still reverses depth.
     pub fn get_projection_matrix(&self) -> Mat4 {
-        Mat4::perspective_rh(
-            self.fov_y.to_radians(),
-            self.aspect_ratio,
-            self.z_near,
-            self.z_far,
+        let reverse = self.depth_mode == DepthMode::ReverseInfinite;
+
+        match self.projection {
+            Projection::Perspective { fov_y } if reverse => Mat4::perspective_infinite_reverse_rh(
+                fov_y.to_radians(),
+                self.aspect_ratio,
+                self.z_near,
+            ),
+
+            Projection::Perspective { fov_y } => Mat4::perspective_rh(
+                fov_y.to_radians(),
+                self.aspect_ratio,
+                self.z_near,
+                self.z_far,
+            ),
+
+            Projection::Orthographic { height } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * self.aspect_ratio;
+                let (z_near, z_far) = if reverse {
+                    (self.z_far, self.z_near)
+                } else {
+                    (self.z_near, self.z_far)
+                };
+                Mat4::orthographic_rh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    z_near,
+                    z_far,
+                )
+            }
+
+            Projection::OffAxis {
+                left,
+                right,
+                bottom,
+                top,
+            } if reverse => frustum_infinite_reverse_rh(left, right, bottom, top, self.z_near),
+
+            Projection::OffAxis {
+                left,
+                right,
+                bottom,
+                top,
+            } => frustum_rh(left, right, bottom, top, self.z_near, self.z_far),
+        }
+    }
+
+    /// The world space planes bounding everything the camera can see.
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_projection(
+            self.get_projection_matrix() * self.get_view_matrix(),
+            self.depth_mode,
         )
     }
 
+    /// The world space ray through `cursor_position`, in pixels from the top left of the window, for a camera
+    /// rendering into `viewport`. The ray starts on the near plane.
+    pub fn screen_to_ray(&self, cursor_position: Vec2, viewport: &Viewport) -> Ray {
+        let local = viewport.to_local(cursor_position) / viewport.size();
+        let ndc = Vec2::new(2.0 * local.x - 1.0, 1.0 - 2.0 * local.y);
+
+        // Unproject a point on the near plane and one part way into the scene; the far plane can't be used because
+        // it's at infinity with reverse-Z.
+        let (near_depth, inner_depth) = match self.depth_mode {
+            DepthMode::Standard => (0.0, 0.5),
+            DepthMode::ReverseInfinite => (1.0, 0.5),
+        };
+
+        let inverse_view_projection =
+            (self.get_projection_matrix() * self.get_view_matrix()).inverse();
+        let near = inverse_view_projection.project_point3(ndc.extend(near_depth));
+        let inner = inverse_view_projection.project_point3(ndc.extend(inner_depth));
+
+        Ray::new(near, inner - near)
+    }
+
     pub fn get_gpu_camera(&self) -> GpuCamera {
         GpuCamera {
             projection: self.get_projection_matrix(),
@@ -105,22 +532,38 @@ impl Camera {
     }
 
     pub fn move_forward(&mut self, dt: f32) {
-        self.position += self.speed * self.direction * dt;
+        self.translate(self.speed * self.direction * dt);
     }
 
     pub fn move_backward(&mut self, dt: f32) {
-        self.position -= self.speed * self.direction * dt;
+        self.translate(-self.speed * self.direction * dt);
     }
 
     pub fn skew_left(&mut self, dt: f32) {
-        self.position -= self.direction.cross(self.up).normalize() * self.speed * dt;
+        self.translate(-self.get_right() * self.speed * dt);
     }
 
     pub fn skew_right(&mut self, dt: f32) {
-        self.position += self.direction.cross(self.up).normalize() * self.speed * dt;
+        self.translate(self.get_right() * self.speed * dt);
+    }
+
+    /// Move the camera by `offset`. In orbit mode the target moves with the camera.
+    pub fn translate(&mut self, offset: Vec3) {
+        self.position += offset;
+        if self.mode == CameraMode::Orbit {
+            self.target += offset;
+        }
     }
 
     pub fn yaw_pitch(&mut self, yaw: f32, pitch: f32) {
+        if self.mode == CameraMode::Free {
+            // Turn about the camera's own axes rather than the world's, so there's no need to clamp the pitch.
+            let yaw = Quat::from_rotation_y(-(yaw * self.mouse_sensitivity).to_radians());
+            let pitch = Quat::from_rotation_x((pitch * self.mouse_sensitivity).to_radians());
+            self.set_orientation(self.orientation * yaw * pitch);
+            return;
+        }
+
         self.yaw += yaw * self.mouse_sensitivity;
         self.pitch += pitch * self.mouse_sensitivity;
 
@@ -131,6 +574,96 @@ impl Camera {
             self.pitch = -89.0;
         }
 
+        self.update_direction();
+    }
+
+    /// Roll the camera clockwise by `angle` degrees around its direction in free mode.
+    pub fn roll(&mut self, angle: f32) {
+        if self.mode != CameraMode::Free {
+            return;
+        }
+
+        let roll = Quat::from_rotation_z(-angle.to_radians());
+        self.set_orientation(self.orientation * roll);
+    }
+
+    /// Zoom in, or out for negative `delta`. Perspective projection narrows the field of view by `delta` degrees while
+    /// the other projections shrink the visible area by 10% per unit of `delta`.
+    pub fn zoom(&mut self, delta: f32) {
+        let scale = (1.0 - delta * 0.1).max(0.01);
+
+        match &mut self.projection {
+            Projection::Perspective { fov_y } => {
+                *fov_y = (*fov_y - delta).clamp(MIN_FOV_Y, MAX_FOV_Y);
+            }
+
+            Projection::Orthographic { height } => {
+                *height = (*height * scale).max(MIN_ORTHOGRAPHIC_HEIGHT);
+            }
+
+            Projection::OffAxis {
+                left,
+                right,
+                bottom,
+                top,
+            } => {
+                *left *= scale;
+                *right *= scale;
+                *bottom *= scale;
+                *top *= scale;
+            }
+        }
+    }
+
+    /// Rotate around the target in orbit mode, typically from a mouse drag.
+    pub fn orbit(&mut self, azimuth: f32, elevation: f32) {
+        if self.mode != CameraMode::Orbit {
+            return;
+        }
+
+        self.azimuth += azimuth * self.mouse_sensitivity;
+        self.elevation = (self.elevation + elevation * self.mouse_sensitivity).clamp(-89.0, 89.0);
+        self.update_orbit();
+    }
+
+    /// Move the target, and the camera with it, across the view plane in orbit mode. The distance moved scales with
+    /// the distance to the target so that panning feels the same at any zoom level.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        if self.mode != CameraMode::Orbit {
+            return;
+        }
+
+        let right = self.get_right();
+        let up = right.cross(self.direction).normalize();
+        let scale = self.distance * self.pan_sensitivity;
+        self.target += (-right * dx + up * dy) * scale;
+        self.update_orbit();
+    }
+
+    /// Move towards or away from the target in orbit mode. Positive `delta` moves closer.
+    pub fn dolly(&mut self, delta: f32) {
+        if self.mode != CameraMode::Orbit {
+            return;
+        }
+
+        self.distance = (self.distance * (1.0 - delta * 0.1)).max(self.min_distance);
+        self.update_orbit();
+    }
+
+    fn focus_distance(&self) -> f32 {
+        let distance = match self.mode {
+            CameraMode::Orbit => self.distance,
+            _ => (self.target - self.position).dot(self.direction),
+        };
+
+        if distance > self.z_near {
+            distance
+        } else {
+            DEFAULT_FOCUS_DISTANCE
+        }
+    }
+
+    fn update_direction(&mut self) {
         let direction = Vec3::new(
             self.yaw.to_radians().cos() * self.pitch.to_radians().cos(),
             self.pitch.to_radians().sin(),
@@ -139,18 +672,68 @@ impl Camera {
         self.direction = direction.normalize();
     }
 
-    pub fn zoom(&mut self, delta: f32) {
-        self.fov_y -= delta;
+    fn update_yaw_pitch(&mut self) {
+        self.yaw = self.direction.z.atan2(self.direction.x).to_degrees();
+        self.pitch = self
+            .direction
+            .y
+            .clamp(-1.0, 1.0)
+            .asin()
+            .to_degrees()
+            .clamp(-89.0, 89.0);
+    }
 
-        if self.fov_y < 1.0 {
-            self.fov_y = 1.0;
-        }
-        if self.fov_y > 45.0 {
-            self.fov_y = 45.0;
-        }
+    fn update_orbit(&mut self) {
+        let azimuth = self.azimuth.to_radians();
+        let elevation = self.elevation.to_radians();
+        let offset = Vec3::new(
+            azimuth.cos() * elevation.cos(),
+            elevation.sin(),
+            azimuth.sin() * elevation.cos(),
+        );
+
+        self.position = self.target + offset * self.distance;
+        self.direction = -offset;
     }
 }
 
+/// Right handed perspective projection for an arbitrary frustum, mapping depth to `[0, 1]` like
+/// [`Mat4::perspective_rh`].
+fn frustum_rh(left: f32, right: f32, bottom: f32, top: f32, z_near: f32, z_far: f32) -> Mat4 {
+    let width = right - left;
+    let height = top - bottom;
+    let r = z_far / (z_near - z_far);
+
+    Mat4::from_cols(
+        Vec4::new(2.0 * z_near / width, 0.0, 0.0, 0.0),
+        Vec4::new(0.0, 2.0 * z_near / height, 0.0, 0.0),
+        Vec4::new((right + left) / width, (top + bottom) / height, r, -1.0),
+        Vec4::new(0.0, 0.0, r * z_near, 0.0),
+    )
+}
+
+/// Like [`frustum_rh`] but with the far plane at infinity and depth reversed, mapping the near plane to 1 and infinity
+/// to 0 like [`Mat4::perspective_infinite_reverse_rh`].
+fn frustum_infinite_reverse_rh(left: f32, right: f32, bottom: f32, top: f32, z_near: f32) -> Mat4 {
+    let width = right - left;
+    let height = top - bottom;
+
+    Mat4::from_cols(
+        Vec4::new(2.0 * z_near / width, 0.0, 0.0, 0.0),
+        Vec4::new(0.0, 2.0 * z_near / height, 0.0, 0.0),
+        Vec4::new((right + left) / width, (top + bottom) / height, 0.0, -1.0),
+        Vec4::new(0.0, 0.0, z_near, 0.0),
+    )
+}
+
+fn orientation_from_direction(direction: Vec3, up: Vec3) -> Quat {
+    let forward = direction.normalize();
+    let right = forward.cross(up).normalize();
+    let up = right.cross(forward);
+
+    Quat::from_mat3(&Mat3::from_cols(right, up, -forward)).normalize()
+}
+
 #[derive(Debug, Default, Clone, Copy, Pod, Zeroable)]
 #[repr(C)]
 pub struct GpuCamera {
@@ -159,3 +742,292 @@ pub struct GpuCamera {
     position: Vec3,
     _pad: f32,
 }
+
+#[cfg(test)]
+mod tests {
+    use winit::dpi::PhysicalSize;
+
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    /// A camera in `mode` at (0, 0, 3) looking down -Z at the origin, with the default settings.
+    fn camera_in(mode: CameraMode) -> Camera {
+        Camera::new(&CameraDescriptor {
+            mode,
+            ..Default::default()
+        })
+    }
+
+    fn assert_near(actual: Vec3, expected: Vec3) {
+        assert!(
+            actual.abs_diff_eq(expected, EPSILON),
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn resizing_the_window_matches_the_aspect_ratio() {
+        let mut camera = Camera::new(&CameraDescriptor::default());
+
+        camera.process_window_event(&WindowEvent::Resized(PhysicalSize::new(1600, 800)));
+        assert_eq!(camera.get_aspect_ratio(), 2.0);
+    }
+
+    #[test]
+    fn minimizing_the_window_keeps_the_aspect_ratio() {
+        let mut camera = Camera::new(&CameraDescriptor::default());
+        camera.process_window_event(&WindowEvent::Resized(PhysicalSize::new(1600, 800)));
+
+        camera.process_window_event(&WindowEvent::Resized(PhysicalSize::new(0, 0)));
+        assert_eq!(camera.get_aspect_ratio(), 2.0);
+    }
+
+    #[test]
+    fn entering_orbit_mode_keeps_the_view() {
+        let camera = camera_in(CameraMode::Orbit);
+
+        assert_eq!(camera.get_distance(), 3.0);
+        assert_near(camera.get_position(), Vec3::new(0.0, 0.0, 3.0));
+        assert_near(camera.get_direction(), Vec3::NEG_Z);
+    }
+
+    #[test]
+    fn azimuth_and_elevation_place_the_camera_on_a_sphere() {
+        let mut camera = camera_in(CameraMode::Orbit);
+
+        // The default mouse sensitivity turns 10 units of drag into a degree, starting from an azimuth of 90 degrees.
+        camera.orbit(900.0, 0.0);
+        assert_near(camera.get_position(), Vec3::new(-3.0, 0.0, 0.0));
+        assert_near(camera.get_direction(), Vec3::X);
+
+        camera.orbit(-1800.0, 450.0);
+        let half = 3.0 * 45_f32.to_radians().cos();
+        assert_near(camera.get_position(), Vec3::new(half, half, 0.0));
+        assert_near(camera.get_direction(), -Vec3::new(half, half, 0.0) / 3.0);
+    }
+
+    #[test]
+    fn orbit_elevation_stops_short_of_the_poles() {
+        let mut camera = camera_in(CameraMode::Orbit);
+
+        camera.orbit(0.0, 10_000.0);
+        assert!((camera.get_position().y - 3.0 * 89_f32.to_radians().sin()).abs() < EPSILON);
+
+        camera.orbit(0.0, -20_000.0);
+        assert!((camera.get_position().y + 3.0 * 89_f32.to_radians().sin()).abs() < EPSILON);
+    }
+
+    #[test]
+    fn dolly_scales_the_distance_down_to_the_minimum() {
+        let mut camera = camera_in(CameraMode::Orbit);
+
+        camera.dolly(5.0);
+        assert!((camera.get_distance() - 1.5).abs() < EPSILON);
+        assert_near(camera.get_position(), Vec3::new(0.0, 0.0, 1.5));
+
+        camera.dolly(-10.0);
+        assert!((camera.get_distance() - 3.0).abs() < EPSILON);
+
+        camera.dolly(100.0);
+        assert_eq!(camera.get_distance(), 0.1);
+        assert_near(camera.get_position(), Vec3::new(0.0, 0.0, 0.1));
+    }
+
+    #[test]
+    fn pan_moves_the_target_and_camera_together() {
+        let mut camera = camera_in(CameraMode::Orbit);
+
+        // Scaled by the distance of 3 and the default pan sensitivity of 0.002.
+        camera.pan(100.0, 50.0);
+
+        assert_near(camera.get_target(), Vec3::new(-0.6, 0.3, 0.0));
+        assert_near(camera.get_position(), Vec3::new(-0.6, 0.3, 3.0));
+        assert_eq!(camera.get_distance(), 3.0);
+        assert_near(camera.get_direction(), Vec3::NEG_Z);
+    }
+
+    #[test]
+    fn orbit_controls_do_nothing_in_other_modes() {
+        for mode in [CameraMode::Fly, CameraMode::Free] {
+            let mut camera = camera_in(mode);
+
+            camera.orbit(900.0, 450.0);
+            camera.pan(100.0, 50.0);
+            camera.dolly(5.0);
+
+            assert_near(camera.get_position(), Vec3::new(0.0, 0.0, 3.0));
+            assert_near(camera.get_direction(), Vec3::NEG_Z);
+            assert_eq!(camera.get_target(), Vec3::ZERO);
+        }
+    }
+
+    #[test]
+    fn roll_turns_up_clockwise_in_free_mode_only() {
+        let mut camera = camera_in(CameraMode::Free);
+
+        camera.roll(90.0);
+        assert_near(camera.get_up(), Vec3::X);
+        assert_near(camera.get_direction(), Vec3::NEG_Z);
+
+        let mut camera = camera_in(CameraMode::Fly);
+        camera.roll(90.0);
+        assert_eq!(camera.get_up(), Vec3::Y);
+    }
+
+    #[test]
+    fn look_at_turns_towards_the_target_without_rolling() {
+        let mut camera = camera_in(CameraMode::Free);
+
+        camera.look_at(Vec3::new(3.0, 0.0, 3.0));
+        assert_near(camera.get_direction(), Vec3::X);
+        assert_near(camera.get_up(), Vec3::Y);
+
+        // Straight up is parallel to the old up vector, which mustn't leave the orientation degenerate.
+        camera.look_at(Vec3::new(0.0, 5.0, 3.0));
+        assert_near(camera.get_direction(), Vec3::Y);
+        assert!(camera.get_orientation().is_normalized());
+        assert!((camera.get_up().dot(Vec3::Y)).abs() < EPSILON);
+
+        // Looking at its own position leaves the camera as it was.
+        camera.look_at(camera.get_position());
+        assert_near(camera.get_direction(), Vec3::Y);
+    }
+
+    #[test]
+    fn look_at_in_orbit_mode_sets_the_target() {
+        let mut camera = camera_in(CameraMode::Orbit);
+
+        camera.look_at(Vec3::new(0.0, 0.0, 1.0));
+
+        assert_eq!(camera.get_target(), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(camera.get_distance(), 2.0);
+    }
+
+    #[test]
+    fn set_orientation_keeps_roll_only_in_free_mode() {
+        let rolled = Quat::from_rotation_z(0.5);
+
+        let mut camera = camera_in(CameraMode::Free);
+        camera.set_orientation(rolled);
+        assert!(camera.get_orientation().abs_diff_eq(rolled, EPSILON));
+        assert_near(camera.get_direction(), Vec3::NEG_Z);
+
+        let mut camera = camera_in(CameraMode::Fly);
+        camera.set_orientation(rolled);
+        assert_near(camera.get_direction(), Vec3::NEG_Z);
+        assert_eq!(camera.get_up(), Vec3::Y);
+    }
+
+    #[test]
+    fn slerp_orientation_reaches_both_endpoints() {
+        let turned = Quat::from_rotation_y(90_f32.to_radians());
+        let mut camera = camera_in(CameraMode::Free);
+        let start = camera.get_orientation();
+
+        camera.slerp_orientation(turned, 0.0);
+        assert!(camera.get_orientation().abs_diff_eq(start, EPSILON));
+
+        camera.slerp_orientation(turned, 0.5);
+        assert!(camera
+            .get_orientation()
+            .abs_diff_eq(Quat::from_rotation_y(45_f32.to_radians()), EPSILON));
+
+        camera.slerp_orientation(turned, 1.0);
+        assert!(camera.get_orientation().abs_diff_eq(turned, EPSILON));
+        assert_near(camera.get_direction(), Vec3::NEG_X);
+    }
+
+    #[test]
+    fn toggling_the_projection_keeps_the_focus_distance_the_same_size() {
+        let mut camera = camera_in(CameraMode::Fly);
+
+        camera.toggle_projection();
+        let Projection::Orthographic { height } = camera.get_projection() else {
+            panic!("expected an orthographic projection");
+        };
+        assert!((height - 6.0 * 22.5_f32.to_radians().tan()).abs() < EPSILON);
+
+        camera.toggle_projection();
+        assert!((camera.get_fov_y().unwrap() - DEFAULT_FOV_Y).abs() < EPSILON);
+    }
+
+    #[test]
+    fn toggling_an_off_axis_projection_returns_to_perspective() {
+        let mut camera = camera_in(CameraMode::Fly);
+        let half_height = 0.1 * 15_f32.to_radians().tan();
+        camera.set_projection(Projection::OffAxis {
+            left: 0.0,
+            right: 0.1,
+            bottom: -half_height,
+            top: half_height,
+        });
+
+        camera.toggle_projection();
+
+        assert!((camera.get_fov_y().unwrap() - 30.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn zoom_narrows_the_field_of_view_within_limits() {
+        let mut camera = camera_in(CameraMode::Fly);
+
+        camera.zoom(5.0);
+        assert_eq!(camera.get_fov_y(), Some(40.0));
+
+        camera.zoom(100.0);
+        assert_eq!(camera.get_fov_y(), Some(MIN_FOV_Y));
+
+        camera.zoom(-100.0);
+        assert_eq!(camera.get_fov_y(), Some(MAX_FOV_Y));
+    }
+
+    #[test]
+    fn zoom_shrinks_orthographic_projections_down_to_a_minimum() {
+        let mut camera = camera_in(CameraMode::Fly);
+        camera.set_projection(Projection::Orthographic { height: 4.0 });
+
+        camera.zoom(1.0);
+        assert_eq!(
+            camera.get_projection(),
+            Projection::Orthographic { height: 3.6 }
+        );
+
+        for _ in 0..10 {
+            camera.zoom(100.0);
+        }
+        assert_eq!(
+            camera.get_projection(),
+            Projection::Orthographic {
+                height: MIN_ORTHOGRAPHIC_HEIGHT
+            }
+        );
+    }
+
+    #[test]
+    fn zoom_scales_off_axis_projections() {
+        let mut camera = camera_in(CameraMode::Fly);
+        camera.set_projection(Projection::OffAxis {
+            left: -0.2,
+            right: 0.1,
+            bottom: -0.1,
+            top: 0.3,
+        });
+
+        camera.zoom(5.0);
+
+        let Projection::OffAxis {
+            left,
+            right,
+            bottom,
+            top,
+        } = camera.get_projection()
+        else {
+            panic!("expected an off-axis projection");
+        };
+        assert_near(Vec3::new(left, right, 0.0), Vec3::new(-0.1, 0.05, 0.0));
+        assert_near(Vec3::new(bottom, top, 0.0), Vec3::new(-0.05, 0.15, 0.0));
+    }
+}
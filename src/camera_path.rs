@@ -0,0 +1,464 @@
+use std::{fs, io, path::Path};
+
+use glam::{Quat, Vec3};
+
+use crate::{
+    assets,
+    camera::{Camera, CameraMode, DEFAULT_FOV_Y},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    /// Seconds since the start of the path.
+    pub time: f32,
+    pub position: Vec3,
+    pub orientation: Quat,
+    pub fov_y: f32,
+}
+
+impl Keyframe {
+    pub fn from_camera(camera: &Camera, time: f32) -> Self {
+        Self {
+            time,
+            position: camera.get_position(),
+            orientation: camera.get_orientation(),
+            fov_y: camera.get_fov_y().unwrap_or(DEFAULT_FOV_Y),
+        }
+    }
+
+    /// Move and point `camera` to match the keyframe. Orbit cameras are switched to free mode so that the orbit
+    /// target doesn't fight the path.
+    pub fn apply(&self, camera: &mut Camera) {
+        if camera.get_mode() == CameraMode::Orbit {
+            camera.set_mode(CameraMode::Free);
+        }
+
+        camera.set_position(self.position);
+        camera.set_orientation(self.orientation);
+        camera.set_fov_y(self.fov_y);
+    }
+}
+
+/// A sequence of camera keyframes, ordered by time, that can be sampled at any time in between. Positions follow a
+/// Catmull-Rom spline through the keyframes and orientations are slerped, so playback is smooth even when the
+/// keyframes are far apart.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+    }
+
+    /// Add a keyframe, keeping the keyframes ordered by time.
+    pub fn push(&mut self, keyframe: Keyframe) {
+        let index = self
+            .keyframes
+            .partition_point(|existing| existing.time <= keyframe.time);
+        self.keyframes.insert(index, keyframe);
+    }
+
+    /// Add a keyframe capturing `camera` at `time`.
+    pub fn record(&mut self, camera: &Camera, time: f32) {
+        self.push(Keyframe::from_camera(camera, time));
+    }
+
+    /// Time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// The interpolated keyframe at `time`, clamped to the start and end of the path. Returns `None` for an empty
+    /// path.
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+
+        if time <= first.time {
+            return Some(Keyframe { time, ..*first });
+        }
+        if time >= last.time {
+            return Some(Keyframe { time, ..*last });
+        }
+
+        let next = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+        let current = next - 1;
+        let k1 = &self.keyframes[current];
+        let k2 = &self.keyframes[next];
+        let k0 = &self.keyframes[current.saturating_sub(1)];
+        let k3 = &self.keyframes[(next + 1).min(self.keyframes.len() - 1)];
+
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 {
+            (time - k1.time) / span
+        } else {
+            0.0
+        };
+
+        Some(Keyframe {
+            time,
+            position: catmull_rom(k0.position, k1.position, k2.position, k3.position, t),
+            orientation: k1.orientation.slerp(k2.orientation, t).normalize(),
+            fov_y: k1.fov_y + (k2.fov_y - k1.fov_y) * t,
+        })
+    }
+
+    /// Write the path as text, one keyframe per line: `time px py pz qx qy qz qw fov_y`.
+    pub fn save<P>(&self, path: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let mut contents = String::from("# time px py pz qx qy qz qw fov_y\n");
+        for keyframe in &self.keyframes {
+            let p = keyframe.position;
+            let q = keyframe.orientation;
+            contents.push_str(&format!(
+                "{} {} {} {} {} {} {} {} {}\n",
+                keyframe.time, p.x, p.y, p.z, q.x, q.y, q.z, q.w, keyframe.fov_y
+            ));
+        }
+
+        fs::write(path, contents)
+    }
+
+    /// Read a path written by [`CameraPath::save`]. Blank lines and lines starting with `#` are ignored.
+    pub fn load<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let contents = fs::read_to_string(path)?;
+        let mut camera_path = Self::new();
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| assets::invalid_data(line_number, &e.to_string()))?;
+
+            let [time, px, py, pz, qx, qy, qz, qw, fov_y] = values[..] else {
+                return Err(assets::invalid_data(line_number, "expected 9 values"));
+            };
+
+            let orientation = Quat::from_xyzw(qx, qy, qz, qw);
+            if orientation.length_squared() == 0.0 {
+                return Err(assets::invalid_data(
+                    line_number,
+                    "orientation is a zero quaternion",
+                ));
+            }
+
+            camera_path.push(Keyframe {
+                time,
+                position: Vec3::new(px, py, pz),
+                orientation: orientation.normalize(),
+                fov_y,
+            });
+        }
+
+        Ok(camera_path)
+    }
+}
+
+/// Plays a [`CameraPath`] back onto a camera at a controllable speed.
+#[derive(Debug, Clone)]
+pub struct CameraPathPlayer {
+    path: CameraPath,
+    time: f32,
+    speed: f32,
+    looping: bool,
+}
+
+impl CameraPathPlayer {
+    pub fn new(path: CameraPath) -> Self {
+        Self {
+            path,
+            time: 0.0,
+            speed: 1.0,
+            looping: false,
+        }
+    }
+
+    pub fn path(&self) -> &CameraPath {
+        &self.path
+    }
+
+    pub fn get_time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time.clamp(0.0, self.path.duration());
+    }
+
+    pub fn get_speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Playback rate, where 1 is real time and 0 pauses.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.time >= self.path.duration()
+    }
+
+    /// Advance playback by `dt` seconds and move `camera` to the new point on the path.
+    pub fn update(&mut self, dt: f32, camera: &mut Camera) {
+        let duration = self.path.duration();
+        self.time += dt * self.speed;
+
+        if self.looping && duration > 0.0 {
+            self.time %= duration;
+        } else {
+            self.time = self.time.min(duration);
+        }
+
+        if let Some(keyframe) = self.path.sample(self.time) {
+            keyframe.apply(camera);
+        }
+    }
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    0.5 * ((2.0 * p1)
+        + (-p0 + p2) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (-p0 + 3.0 * p1 - 3.0 * p2 + p3) * t3)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io::ErrorKind, path::PathBuf, process};
+
+    use crate::camera::CameraDescriptor;
+
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    /// A keyframe at `time` with the camera at `x` along the X axis, turning about Y and widening as `x` grows.
+    fn keyframe(time: f32, x: f32) -> Keyframe {
+        Keyframe {
+            time,
+            position: Vec3::new(x, 0.0, 0.0),
+            orientation: Quat::from_rotation_y(x * 0.1),
+            fov_y: 30.0 + x,
+        }
+    }
+
+    /// A path with keyframes one second apart moving one unit along X per second.
+    fn straight_path(count: usize) -> CameraPath {
+        let mut path = CameraPath::new();
+        for i in 0..count {
+            path.push(keyframe(i as f32, i as f32));
+        }
+        path
+    }
+
+    /// A file in the temporary folder holding `contents`, named after `name` and this process so tests don't collide.
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("{}-{}.path", name, process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn load(name: &str, contents: &str) -> io::Result<CameraPath> {
+        let path = temp_file(name, contents);
+        let result = CameraPath::load(&path);
+        fs::remove_file(path).unwrap();
+        result
+    }
+
+    #[test]
+    fn an_empty_path_has_no_samples() {
+        assert_eq!(CameraPath::new().sample(0.0), None);
+    }
+
+    #[test]
+    fn sampling_at_a_keyframe_returns_it() {
+        let path = straight_path(4);
+
+        for expected in path.keyframes() {
+            let sample = path.sample(expected.time).unwrap();
+
+            assert!(sample.position.abs_diff_eq(expected.position, EPSILON));
+            assert!(sample
+                .orientation
+                .abs_diff_eq(expected.orientation, EPSILON));
+            assert!((sample.fov_y - expected.fov_y).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn sampling_between_keyframes_interpolates() {
+        let path = straight_path(4);
+        let sample = path.sample(1.5).unwrap();
+
+        assert_eq!(sample.time, 1.5);
+        assert!(sample
+            .position
+            .abs_diff_eq(Vec3::new(1.5, 0.0, 0.0), EPSILON));
+        assert!(sample
+            .orientation
+            .abs_diff_eq(Quat::from_rotation_y(0.15), EPSILON));
+        assert!((sample.fov_y - 31.5).abs() < EPSILON);
+    }
+
+    #[test]
+    fn sampling_past_either_end_clamps() {
+        let path = straight_path(3);
+
+        let before = path.sample(-2.0).unwrap();
+        assert_eq!(before.time, -2.0);
+        assert_eq!(before.position, path.keyframes()[0].position);
+
+        let after = path.sample(10.0).unwrap();
+        assert_eq!(after.time, 10.0);
+        assert_eq!(after.position, path.keyframes()[2].position);
+    }
+
+    #[test]
+    fn a_single_keyframe_is_held_forever() {
+        let mut path = CameraPath::new();
+        path.push(keyframe(2.0, 5.0));
+
+        assert_eq!(path.duration(), 2.0);
+        for time in [0.0, 2.0, 7.0] {
+            let sample = path.sample(time).unwrap();
+
+            assert_eq!(
+                sample,
+                Keyframe {
+                    time,
+                    ..keyframe(2.0, 5.0)
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn push_keeps_keyframes_ordered_with_later_duplicates_after() {
+        let mut path = CameraPath::new();
+        path.push(keyframe(2.0, 0.0));
+        path.push(keyframe(0.0, 1.0));
+        path.push(keyframe(2.0, 2.0));
+        path.push(keyframe(1.0, 3.0));
+
+        let order = path
+            .keyframes()
+            .iter()
+            .map(|keyframe| (keyframe.time, keyframe.position.x))
+            .collect::<Vec<_>>();
+        assert_eq!(order, [(0.0, 1.0), (1.0, 3.0), (2.0, 0.0), (2.0, 2.0)]);
+    }
+
+    #[test]
+    fn saved_paths_load_the_same() {
+        let path = straight_path(3);
+
+        let file = temp_file("round-trip", "");
+        path.save(&file).unwrap();
+        let loaded = CameraPath::load(&file);
+        fs::remove_file(file).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.keyframes().len(), 3);
+        for (loaded, saved) in loaded.keyframes().iter().zip(path.keyframes()) {
+            assert_eq!(loaded.time, saved.time);
+            assert_eq!(loaded.position, saved.position);
+            assert!(loaded.orientation.abs_diff_eq(saved.orientation, EPSILON));
+            assert_eq!(loaded.fov_y, saved.fov_y);
+        }
+    }
+
+    #[test]
+    fn lines_with_the_wrong_number_of_values_are_invalid() {
+        for line in ["0 0 0 0 0 0 0 1", "0 0 0 0 0 0 0 1 45 1"] {
+            let contents = format!("# comment\n{}\n", line);
+            let error = load("value-count", &contents).unwrap_err();
+
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert!(error.to_string().starts_with("line 2: "), "{}", error);
+        }
+    }
+
+    #[test]
+    fn zero_quaternions_are_invalid() {
+        let error = load("zero-quaternion", "0 0 0 0 0 0 0 0 45\n").unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn the_player_advances_at_its_speed() {
+        let mut camera = Camera::new(&CameraDescriptor::default());
+        let mut player = CameraPathPlayer::new(straight_path(4));
+        player.set_speed(2.0);
+
+        player.update(0.5, &mut camera);
+
+        assert_eq!(player.get_time(), 1.0);
+        assert!(camera
+            .get_position()
+            .abs_diff_eq(Vec3::new(1.0, 0.0, 0.0), EPSILON));
+        assert!(!player.is_finished());
+    }
+
+    #[test]
+    fn the_player_stops_at_the_end_unless_looping() {
+        let mut camera = Camera::new(&CameraDescriptor::default());
+        let mut player = CameraPathPlayer::new(straight_path(4));
+
+        player.update(4.0, &mut camera);
+        assert_eq!(player.get_time(), 3.0);
+        assert!(player.is_finished());
+
+        player.set_time(0.0);
+        player.set_looping(true);
+        player.update(4.0, &mut camera);
+        assert_eq!(player.get_time(), 1.0);
+        assert!(!player.is_finished());
+    }
+
+    #[test]
+    fn negative_speeds_pause_the_player() {
+        let mut camera = Camera::new(&CameraDescriptor::default());
+        let mut player = CameraPathPlayer::new(straight_path(4));
+        player.set_speed(-1.0);
+
+        player.update(1.0, &mut camera);
+
+        assert_eq!(player.get_speed(), 0.0);
+        assert_eq!(player.get_time(), 0.0);
+    }
+}
@@ -0,0 +1,543 @@
+use std::{
+    fmt, io,
+    path::PathBuf,
+    sync::mpsc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use image::{imageops, ImageError, RgbaImage};
+use wgpu::{
+    Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoder,
+    CommandEncoderDescriptor, Device, Extent3d, ImageCopyBuffer, ImageDataLayout, Maintain,
+    MapMode, Queue, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    TextureView, TextureViewDescriptor, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+use crate::{assets, camera::Camera};
+
+#[derive(Debug)]
+pub enum CaptureError {
+    /// Only 8 bit RGBA and BGRA textures can be saved.
+    UnsupportedFormat(TextureFormat),
+    /// The texture wasn't created with [`TextureUsages::COPY_SRC`].
+    NotCopyable,
+    /// Poster tiles need a perspective projection to be split into off-axis tiles.
+    UnsupportedProjection,
+    Map(BufferAsyncError),
+    /// Recorded frames all have to be the size the recording started at.
+    SizeChanged {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    Save(ImageError),
+    Io(io::Error),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::UnsupportedFormat(format) => {
+                write!(f, "textures in {:?} can't be captured", format)
+            }
+            CaptureError::NotCopyable => write!(f, "the texture can't be copied from"),
+            CaptureError::UnsupportedProjection => {
+                write!(f, "only perspective projections can be split into tiles")
+            }
+            CaptureError::Map(e) => write!(f, "failed to read the texture back: {}", e),
+            CaptureError::SizeChanged { expected, actual } => write!(
+                f,
+                "the frame changed size from {}x{} to {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            CaptureError::Save(e) => write!(f, "failed to save the image: {}", e),
+            CaptureError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+impl From<ImageError> for CaptureError {
+    fn from(e: ImageError) -> Self {
+        CaptureError::Save(e)
+    }
+}
+
+impl From<io::Error> for CaptureError {
+    fn from(e: io::Error) -> Self {
+        CaptureError::Io(e)
+    }
+}
+
+/// A copy of a texture in a buffer the CPU can read.
+pub struct TextureReadback {
+    buffer: Buffer,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    mapping: Option<mpsc::Receiver<Result<(), BufferAsyncError>>>,
+}
+
+impl TextureReadback {
+    /// Record a copy of `texture` into `encoder`. The copy can be read once the encoder has been submitted.
+    pub fn new(
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+    ) -> Result<Self, CaptureError> {
+        let format = texture.format();
+        if !is_capturable(format) {
+            return Err(CaptureError::UnsupportedFormat(format));
+        }
+        if !texture.usage().contains(TextureUsages::COPY_SRC) {
+            return Err(CaptureError::NotCopyable);
+        }
+
+        // Rows in the buffer have to start on a multiple of the copy alignment, so they're padded to it and the
+        // padding stripped again when reading.
+        let (width, height) = (texture.width(), texture.height());
+        let padded_bytes_per_row = padded_bytes_per_row(width);
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("buffer::readback"),
+            size: padded_bytes_per_row as u64 * height as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Ok(Self {
+            buffer,
+            format,
+            width,
+            height,
+            padded_bytes_per_row,
+            mapping: None,
+        })
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Start mapping the copy for reading without waiting for it. Call once the encoder has been submitted.
+    pub fn map_async(&mut self) {
+        if self.mapping.is_some() {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        self.buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.mapping = Some(receiver);
+    }
+
+    /// The image if mapping has finished, or `None` if it hasn't or was never started. Mapping only progresses while
+    /// the device is polled.
+    pub fn try_read(&mut self) -> Option<Result<RgbaImage, CaptureError>> {
+        let result = self.mapping.as_ref()?.try_recv().ok()?;
+        self.mapping = None;
+
+        Some(result.map_err(CaptureError::Map).map(|()| {
+            let image = self.to_image();
+            self.buffer.unmap();
+            image
+        }))
+    }
+
+    /// Wait for the copy to finish and read it into an image.
+    pub fn read(mut self, device: &Device) -> Result<RgbaImage, CaptureError> {
+        self.map_async();
+        device.poll(Maintain::Wait);
+
+        self.try_read()
+            .expect("the buffer is mapped once the device is idle")
+    }
+
+    /// The mapped buffer without its row padding, as RGBA.
+    fn to_image(&self) -> RgbaImage {
+        let data = self.buffer.slice(..).get_mapped_range();
+        let row_bytes = self.width as usize * 4;
+        let mut pixels = Vec::with_capacity(row_bytes * self.height as usize);
+        for row in data.chunks_exact(self.padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..row_bytes]);
+        }
+
+        if matches!(
+            self.format,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        RgbaImage::from_raw(self.width, self.height, pixels).expect("pixels match the image size")
+    }
+}
+
+/// Whether textures in `format` can be read back into an image.
+pub fn is_capturable(format: TextureFormat) -> bool {
+    matches!(
+        format,
+        TextureFormat::Rgba8Unorm
+            | TextureFormat::Rgba8UnormSrgb
+            | TextureFormat::Bgra8Unorm
+            | TextureFormat::Bgra8UnormSrgb
+    )
+}
+
+/// Bytes per row of an RGBA texture `width` pixels wide, rounded up to the alignment texture copies need.
+pub fn padded_bytes_per_row(width: u32) -> u32 {
+    let bytes_per_row = width * 4;
+    let alignment = COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    bytes_per_row.div_ceil(alignment) * alignment
+}
+
+/// A texture that can be drawn into like the surface and then read back, for surfaces that can't be copied from.
+pub struct CaptureTarget {
+    texture: Texture,
+    view: TextureView,
+}
+
+impl CaptureTarget {
+    pub fn new(device: &Device, format: TextureFormat, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("texture::capture"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+
+    pub fn get_texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    pub fn get_view(&self) -> &TextureView {
+        &self.view
+    }
+
+    /// Read back whatever was last drawn into the target.
+    pub fn read(&self, device: &Device, queue: &Queue) -> Result<RgbaImage, CaptureError> {
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("command_encoder::capture"),
+        });
+        let readback = TextureReadback::new(device, &mut encoder, &self.texture)?;
+        queue.submit(Some(encoder.finish()));
+
+        readback.read(device)
+    }
+}
+
+/// Capture the frame just drawn into `frame`, before it's presented.
+///
+/// The frame is copied straight from the surface texture when the surface allows copies. Otherwise `render` is called
+/// to draw the frame again into a texture that does.
+pub fn capture_frame<F>(
+    device: &Device,
+    queue: &Queue,
+    frame: &Texture,
+    render: F,
+) -> Result<RgbaImage, CaptureError>
+where
+    F: FnOnce(&TextureView),
+{
+    if !is_capturable(frame.format()) {
+        return Err(CaptureError::UnsupportedFormat(frame.format()));
+    }
+
+    if frame.usage().contains(TextureUsages::COPY_SRC) {
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("command_encoder::capture"),
+        });
+        let readback = TextureReadback::new(device, &mut encoder, frame)?;
+        queue.submit(Some(encoder.finish()));
+
+        return readback.read(device);
+    }
+
+    let target = CaptureTarget::new(device, frame.format(), frame.width(), frame.height());
+    render(target.get_view());
+
+    target.read(device, queue)
+}
+
+/// How a poster is split into tiles, each the size of the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PosterLayout {
+    pub columns: u32,
+    pub rows: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+}
+
+impl PosterLayout {
+    pub fn width(&self) -> u32 {
+        self.columns * self.tile_width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.rows * self.tile_height
+    }
+
+    /// Where the tile in `column` and `row` goes in the poster, from its top left.
+    pub fn tile_origin(&self, column: u32, row: u32) -> (u32, u32) {
+        (column * self.tile_width, row * self.tile_height)
+    }
+
+    /// Every tile's column and row, a row at a time from the top left.
+    pub fn tiles(&self) -> impl Iterator<Item = (u32, u32)> {
+        let columns = self.columns;
+        (0..self.rows).flat_map(move |row| (0..columns).map(move |column| (column, row)))
+    }
+}
+
+/// Capture an image of what `camera` sees that's `layout.columns` by `layout.rows` times the size of a frame.
+///
+/// Each tile is drawn by `render` with an off-axis copy of the camera into its own texture and read back, and the
+/// tiles are stitched together on the CPU, so the poster can be larger than the biggest texture the device supports.
+pub fn capture_poster<F>(
+    device: &Device,
+    queue: &Queue,
+    format: TextureFormat,
+    layout: PosterLayout,
+    camera: &Camera,
+    mut render: F,
+) -> Result<RgbaImage, CaptureError>
+where
+    F: FnMut(&Camera, &TextureView),
+{
+    if !is_capturable(format) {
+        return Err(CaptureError::UnsupportedFormat(format));
+    }
+
+    let target = CaptureTarget::new(device, format, layout.tile_width, layout.tile_height);
+    let mut poster = RgbaImage::new(layout.width(), layout.height());
+    let mut tile_camera = camera.clone();
+
+    for (column, row) in layout.tiles() {
+        let projection = camera
+            .tile_projection(column, row, layout.columns, layout.rows)
+            .ok_or(CaptureError::UnsupportedProjection)?;
+        tile_camera.set_projection(projection);
+
+        render(&tile_camera, target.get_view());
+        let tile = target.read(device, queue)?;
+
+        let (x, y) = layout.tile_origin(column, row);
+        imageops::replace(&mut poster, &tile, x as i64, y as i64);
+    }
+
+    Ok(poster)
+}
+
+/// A path in the output folder for a PNG named after `name` and the current time, e.g.
+/// `model-loading-20240131-235959-123.png`.
+pub fn screenshot_path(name: &str) -> io::Result<PathBuf> {
+    assets::output_path(format!("{}-{}.png", name, timestamp(SystemTime::now())))
+}
+
+/// Save a captured image to a timestamped PNG named after `name`, see [`screenshot_path`], reporting where it went or
+/// why it couldn't be saved. `kind` names the capture in the report, e.g. "screenshot".
+pub fn save_capture(kind: &str, name: &str, result: Result<RgbaImage, CaptureError>) {
+    let result = result.and_then(|image| {
+        let path = screenshot_path(name)?;
+        image.save(&path)?;
+        Ok(path)
+    });
+
+    match result {
+        Ok(path) => println!("saved {} to {}", kind, path.display()),
+        Err(e) => eprintln!("unable to save {}: {}", kind, e),
+    }
+}
+
+/// `time` in UTC as `YYYYMMDD-hhmmss-mmm`, which sorts in time order.
+pub fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let seconds_of_day = seconds % 86400;
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// The Gregorian calendar date `days` after 1970-01-01, using Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    (year, month as u32, day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::camera::{CameraDescriptor, Projection};
+
+    use super::*;
+
+    const EPSILON: f32 = 1e-6;
+
+    /// The near plane extents of an off-axis projection as `(left, right, bottom, top)`.
+    fn extents(projection: Projection) -> (f32, f32, f32, f32) {
+        match projection {
+            Projection::OffAxis {
+                left,
+                right,
+                bottom,
+                top,
+            } => (left, right, bottom, top),
+            projection => panic!("expected an off-axis projection, got {:?}", projection),
+        }
+    }
+
+    #[test]
+    fn rows_are_padded_to_the_copy_alignment() {
+        assert_eq!(padded_bytes_per_row(0), 0);
+        assert_eq!(padded_bytes_per_row(1), 256);
+        assert_eq!(padded_bytes_per_row(64), 256);
+        assert_eq!(padded_bytes_per_row(65), 512);
+        assert_eq!(padded_bytes_per_row(1280), 5120);
+    }
+
+    #[test]
+    fn poster_layout_places_tiles_row_by_row() {
+        let layout = PosterLayout {
+            columns: 3,
+            rows: 2,
+            tile_width: 100,
+            tile_height: 50,
+        };
+
+        assert_eq!(layout.width(), 300);
+        assert_eq!(layout.height(), 100);
+        assert_eq!(layout.tile_origin(0, 0), (0, 0));
+        assert_eq!(layout.tile_origin(2, 1), (200, 50));
+        assert_eq!(
+            layout.tiles().collect::<Vec<_>>(),
+            [(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn poster_tiles_exactly_cover_the_camera_frustum() {
+        let mut camera = Camera::new(&CameraDescriptor::default());
+        camera.set_aspect_ratio(16.0 / 9.0);
+        let layout = PosterLayout {
+            columns: 3,
+            rows: 2,
+            tile_width: 160,
+            tile_height: 90,
+        };
+        let tile = |column, row| {
+            extents(
+                camera
+                    .tile_projection(column, row, layout.columns, layout.rows)
+                    .unwrap(),
+            )
+        };
+
+        let (left, right, bottom, top) = extents(camera.tile_projection(0, 0, 1, 1).unwrap());
+        assert!((right / top - 16.0 / 9.0).abs() < EPSILON);
+        assert!((left + right).abs() < EPSILON);
+        assert!((bottom + top).abs() < EPSILON);
+
+        assert!((tile(0, 0).0 - left).abs() < EPSILON);
+        assert!((tile(0, 0).3 - top).abs() < EPSILON);
+        assert!((tile(2, 1).1 - right).abs() < EPSILON);
+        assert!((tile(2, 1).2 - bottom).abs() < EPSILON);
+        for (column, row) in layout.tiles() {
+            let (tile_left, tile_right, tile_bottom, tile_top) = tile(column, row);
+
+            assert!((tile_right - tile_left - (right - left) / 3.0).abs() < EPSILON);
+            assert!((tile_top - tile_bottom - (top - bottom) / 2.0).abs() < EPSILON);
+            if column + 1 < layout.columns {
+                assert_eq!(tile_right, tile(column + 1, row).0);
+            }
+            if row + 1 < layout.rows {
+                assert_eq!(tile_bottom, tile(column, row + 1).3);
+            }
+        }
+    }
+
+    #[test]
+    fn orthographic_cameras_have_no_tiles() {
+        let mut camera = Camera::new(&CameraDescriptor::default());
+        camera.set_projection(Projection::Orthographic { height: 4.0 });
+
+        assert_eq!(camera.tile_projection(0, 0, 2, 2), None);
+    }
+
+    #[test]
+    fn days_since_the_epoch_are_calendar_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(11017), (2000, 3, 1));
+        assert_eq!(civil_from_days(20088), (2024, 12, 31));
+        assert_eq!(civil_from_days(-25508), (1900, 3, 1));
+    }
+
+    #[test]
+    fn timestamps_sort_in_time_order() {
+        assert_eq!(timestamp(UNIX_EPOCH), "19700101-000000-000");
+
+        let leap_day = UNIX_EPOCH + Duration::from_millis((11016 * 86400 + 86399) * 1000 + 123);
+        assert_eq!(timestamp(leap_day), "20000229-235959-123");
+    }
+}
@@ -0,0 +1,510 @@
+use std::{env, fmt, process};
+
+use wgpu::{Backends, PowerPreference, PresentMode};
+use winit::{
+    dpi::LogicalSize,
+    window::{Fullscreen, WindowBuilder},
+};
+
+use crate::{
+    gpu::{AdapterCapabilities, AdapterSelector, GpuContextOptions},
+    msaa::{DEFAULT_SAMPLE_COUNT, SAMPLE_COUNTS},
+    recording::{RecordingFormat, DEFAULT_RECORDING_FPS},
+};
+
+/// Poster tiles used unless configured otherwise, which makes an 8K poster from a 1080p window.
+pub const DEFAULT_POSTER_TILES: (u32, u32) = (4, 4);
+
+/// Command line usage, shared by every sample.
+pub const USAGE: &str = "\
+options:
+  --backend <list>         comma separated backends to use: vulkan, metal, dx12, dx11, gl, webgpu, primary,
+                           secondary or all [env: WGPU_BACKEND]
+  --adapter <index|name>   the adapter to use, by its index in --list-adapters or part of its name
+                           [env: WGPU_ADAPTER_NAME]
+  --list-adapters          print the adapters on the selected backends and exit
+  --fallback               use a software adapter
+  --power <low|high>       prefer an integrated or a discrete GPU [env: WGPU_POWER_PREF]
+  --resolution <WxH>       the window size, e.g. 1920x1080
+  --fullscreen             open a borderless fullscreen window
+  --vsync, --no-vsync      wait for vertical blank when presenting, on by default
+  --present-mode <mode>    fifo, fifo-relaxed, mailbox or immediate, overriding --vsync if supported
+  --fps <n>                cap the frame rate on the CPU, or 0 for uncapped, the default
+  --msaa <n>               samples per pixel: 1, 2, 4, 8 or 16, lowered to what the adapter supports, 4 by default
+  --record-format <format> png for a numbered PNG sequence or y4m for an uncompressed video, png by default
+  --record-fps <n>         the frame rate recordings are simulated and played back at, 60 by default
+  --poster <CxR>           columns and rows of window sized tiles in a poster capture, 4x4 by default
+  -h, --help               print this and exit";
+
+/// Sample settings that apply to every sample, read from the command line and environment variables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub backends: Backends,
+    pub adapter: Option<AdapterSelector>,
+    pub power_preference: PowerPreference,
+    pub force_fallback_adapter: bool,
+    pub list_adapters: bool,
+    pub help: bool,
+    /// The window's logical size, or the sample's default size if `None`.
+    pub resolution: Option<(u32, u32)>,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    pub present_mode: Option<PresentMode>,
+    /// The frame rate to cap to, or `None` for uncapped.
+    pub target_fps: Option<f32>,
+    /// The requested MSAA sample count, see [`choose_sample_count`](crate::msaa::choose_sample_count).
+    pub sample_count: u32,
+    /// The columns and rows of tiles in a poster, see [`capture_poster`](crate::capture::capture_poster).
+    pub poster_tiles: (u32, u32),
+    pub recording_format: RecordingFormat,
+    /// The simulated frame rate of recordings, see [`Recorder`](crate::recording::Recorder).
+    pub recording_fps: f32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            backends: Backends::PRIMARY,
+            adapter: None,
+            power_preference: PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            list_adapters: false,
+            help: false,
+            resolution: None,
+            fullscreen: false,
+            vsync: true,
+            present_mode: None,
+            target_fps: None,
+            sample_count: DEFAULT_SAMPLE_COUNT,
+            poster_tiles: DEFAULT_POSTER_TILES,
+            recording_format: RecordingFormat::default(),
+            recording_fps: DEFAULT_RECORDING_FPS,
+        }
+    }
+}
+
+impl Config {
+    /// The config from the process's arguments and environment.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::parse(env::args().skip(1), |name| env::var(name).ok())
+    }
+
+    /// The config from the process's arguments and environment, for a sample to start with. Prints the usage or the
+    /// adapters and exits if either was asked for, and prints the error and the usage and exits if the config is
+    /// invalid.
+    pub fn load() -> Self {
+        let config = Self::from_env().unwrap_or_else(|e| {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        });
+
+        if config.help {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+
+        if config.list_adapters {
+            for (index, adapter) in AdapterCapabilities::list(config.backends)
+                .iter()
+                .enumerate()
+            {
+                println!("{}: {}", index, adapter);
+            }
+            process::exit(0);
+        }
+
+        config
+    }
+
+    /// Parse `args`, excluding the program name, on top of the environment variables returned by `var`. Flags take
+    /// precedence over environment variables, and values can be given as `--flag value` or `--flag=value`.
+    pub fn parse<I, F>(args: I, var: F) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = String>,
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut config = Self::default();
+
+        if let Some(value) = var("WGPU_BACKEND") {
+            config.backends = parse_backends(&value)
+                .ok_or_else(|| ConfigError::invalid_value("WGPU_BACKEND", &value))?;
+        }
+        if let Some(value) = var("WGPU_ADAPTER_NAME") {
+            config.adapter = Some(AdapterSelector::Name(value));
+        }
+        if let Some(value) = var("WGPU_POWER_PREF") {
+            config.power_preference = parse_power_preference(&value)
+                .ok_or_else(|| ConfigError::invalid_value("WGPU_POWER_PREF", &value))?;
+        }
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg, None),
+            };
+
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| ConfigError::MissingValue(flag.clone()))
+            };
+
+            match flag.as_str() {
+                "--backend" => {
+                    let value = value()?;
+                    config.backends = parse_backends(&value)
+                        .ok_or_else(|| ConfigError::invalid_value(&flag, &value))?;
+                }
+                "--adapter" => {
+                    let value = value()?;
+                    config.adapter = Some(match value.parse::<usize>() {
+                        Ok(index) => AdapterSelector::Index(index),
+                        Err(_) => AdapterSelector::Name(value),
+                    });
+                }
+                "--power" => {
+                    let value = value()?;
+                    config.power_preference = parse_power_preference(&value)
+                        .ok_or_else(|| ConfigError::invalid_value(&flag, &value))?;
+                }
+                "--resolution" => {
+                    let value = value()?;
+                    config.resolution = Some(
+                        parse_resolution(&value)
+                            .ok_or_else(|| ConfigError::invalid_value(&flag, &value))?,
+                    );
+                }
+                "--present-mode" => {
+                    let value = value()?;
+                    config.present_mode = Some(
+                        parse_present_mode(&value)
+                            .ok_or_else(|| ConfigError::invalid_value(&flag, &value))?,
+                    );
+                }
+                "--fps" => {
+                    let value = value()?;
+                    let fps = value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|fps| fps.is_finite() && *fps >= 0.0)
+                        .ok_or_else(|| ConfigError::invalid_value(&flag, &value))?;
+                    config.target_fps = (fps > 0.0).then_some(fps);
+                }
+                "--msaa" => {
+                    let value = value()?;
+                    config.sample_count = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|count| SAMPLE_COUNTS.contains(count))
+                        .ok_or_else(|| ConfigError::invalid_value(&flag, &value))?;
+                }
+                "--record-format" => {
+                    let value = value()?;
+                    config.recording_format = value
+                        .parse()
+                        .map_err(|_| ConfigError::invalid_value(&flag, &value))?;
+                }
+                "--record-fps" => {
+                    let value = value()?;
+                    config.recording_fps = value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|fps| fps.is_finite() && *fps > 0.0)
+                        .ok_or_else(|| ConfigError::invalid_value(&flag, &value))?;
+                }
+                "--poster" => {
+                    let value = value()?;
+                    config.poster_tiles = parse_resolution(&value)
+                        .ok_or_else(|| ConfigError::invalid_value(&flag, &value))?;
+                }
+                "--list-adapters" => config.list_adapters = true,
+                "--fallback" => config.force_fallback_adapter = true,
+                "--fullscreen" => config.fullscreen = true,
+                "--vsync" => config.vsync = true,
+                "--no-vsync" => config.vsync = false,
+                "-h" | "--help" => config.help = true,
+                _ => return Err(ConfigError::UnknownFlag(flag)),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Options for [`GpuContext::new`](crate::gpu::GpuContext::new) that use the configured adapter and present mode.
+    pub fn gpu_context_options(&self) -> GpuContextOptions {
+        GpuContextOptions {
+            backends: self.backends,
+            power_preference: self.power_preference,
+            force_fallback_adapter: self.force_fallback_adapter,
+            adapter: self.adapter.clone(),
+            preferred_present_modes: self
+                .present_mode
+                .into_iter()
+                .chain(if self.vsync {
+                    vec![PresentMode::Fifo]
+                } else {
+                    vec![PresentMode::Immediate, PresentMode::Mailbox]
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// A builder for a window with the configured size, or `default_width` by `default_height` if there isn't one.
+    pub fn window_builder(&self, default_width: u32, default_height: u32) -> WindowBuilder {
+        let (width, height) = self.resolution.unwrap_or((default_width, default_height));
+        let builder = WindowBuilder::new().with_inner_size(LogicalSize::new(width, height));
+
+        if self.fullscreen {
+            builder.with_fullscreen(Some(Fullscreen::Borderless(None)))
+        } else {
+            builder
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    UnknownFlag(String),
+    MissingValue(String),
+    InvalidValue { name: String, value: String },
+}
+
+impl ConfigError {
+    fn invalid_value(name: &str, value: &str) -> Self {
+        ConfigError::InvalidValue {
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnknownFlag(flag) => write!(f, "unknown option {}", flag),
+            ConfigError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+            ConfigError::InvalidValue { name, value } => {
+                write!(f, "invalid value \"{}\" for {}", value, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A comma separated list of backend names, or `None` if any of them is unknown.
+pub fn parse_backends(list: &str) -> Option<Backends> {
+    list.split(',')
+        .map(|name| match name.trim().to_lowercase().as_str() {
+            "vulkan" | "vk" => Some(Backends::VULKAN),
+            "metal" | "mtl" => Some(Backends::METAL),
+            "dx12" | "d3d12" => Some(Backends::DX12),
+            "dx11" | "d3d11" => Some(Backends::DX11),
+            "gl" | "gles" | "opengl" => Some(Backends::GL),
+            "webgpu" | "browser" => Some(Backends::BROWSER_WEBGPU),
+            "primary" => Some(Backends::PRIMARY),
+            "secondary" => Some(Backends::SECONDARY),
+            "all" => Some(Backends::all()),
+            _ => None,
+        })
+        .try_fold(Backends::empty(), |backends, backend| {
+            Some(backends | backend?)
+        })
+}
+
+fn parse_power_preference(value: &str) -> Option<PowerPreference> {
+    match value.to_lowercase().as_str() {
+        "low" => Some(PowerPreference::LowPower),
+        "high" => Some(PowerPreference::HighPerformance),
+        _ => None,
+    }
+}
+
+pub fn parse_present_mode(name: &str) -> Option<PresentMode> {
+    match name.to_lowercase().as_str() {
+        "fifo" => Some(PresentMode::Fifo),
+        "fifo-relaxed" => Some(PresentMode::FifoRelaxed),
+        "mailbox" => Some(PresentMode::Mailbox),
+        "immediate" => Some(PresentMode::Immediate),
+        _ => None,
+    }
+}
+
+/// A `WIDTHxHEIGHT` size with neither dimension zero.
+fn parse_resolution(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.split_once(['x', 'X'])?;
+    let width = width.trim().parse::<u32>().ok()?;
+    let height = height.trim().parse::<u32>().ok()?;
+
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str], vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        Config::parse(args.iter().map(|arg| arg.to_string()), |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn nothing_given_is_the_default() {
+        assert_eq!(parse(&[], &[]), Ok(Config::default()));
+    }
+
+    #[test]
+    fn flags_set_their_options() {
+        let config = parse(
+            &[
+                "--backend",
+                "vulkan,gl",
+                "--adapter",
+                "1",
+                "--power",
+                "low",
+                "--resolution",
+                "1920x1080",
+                "--present-mode",
+                "mailbox",
+                "--fps",
+                "30",
+                "--msaa",
+                "8",
+                "--record-format",
+                "y4m",
+                "--record-fps",
+                "24",
+                "--poster",
+                "2x3",
+                "--fallback",
+                "--fullscreen",
+                "--no-vsync",
+                "--list-adapters",
+            ],
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                backends: Backends::VULKAN | Backends::GL,
+                adapter: Some(AdapterSelector::Index(1)),
+                power_preference: PowerPreference::LowPower,
+                force_fallback_adapter: true,
+                list_adapters: true,
+                help: false,
+                resolution: Some((1920, 1080)),
+                fullscreen: true,
+                vsync: false,
+                present_mode: Some(PresentMode::Mailbox),
+                target_fps: Some(30.0),
+                sample_count: 8,
+                poster_tiles: (2, 3),
+                recording_format: RecordingFormat::Y4m,
+                recording_fps: 24.0,
+            }
+        );
+    }
+
+    #[test]
+    fn values_can_follow_an_equals_sign() {
+        let config = parse(&["--adapter=geforce", "--resolution=800X600"], &[]).unwrap();
+
+        assert_eq!(
+            config.adapter,
+            Some(AdapterSelector::Name("geforce".to_string()))
+        );
+        assert_eq!(config.resolution, Some((800, 600)));
+    }
+
+    #[test]
+    fn zero_fps_is_uncapped() {
+        let config = parse(&["--fps", "0"], &[]).unwrap();
+        assert_eq!(config.target_fps, None);
+    }
+
+    #[test]
+    fn environment_variables_are_used_without_flags() {
+        let vars = [
+            ("WGPU_BACKEND", "dx12"),
+            ("WGPU_ADAPTER_NAME", "radeon"),
+            ("WGPU_POWER_PREF", "low"),
+        ];
+        let config = parse(&[], &vars).unwrap();
+
+        assert_eq!(config.backends, Backends::DX12);
+        assert_eq!(
+            config.adapter,
+            Some(AdapterSelector::Name("radeon".to_string()))
+        );
+        assert_eq!(config.power_preference, PowerPreference::LowPower);
+    }
+
+    #[test]
+    fn flags_take_precedence_over_environment_variables() {
+        let vars = [("WGPU_BACKEND", "dx12"), ("WGPU_ADAPTER_NAME", "radeon")];
+        let config = parse(&["--backend", "metal", "--adapter", "0"], &vars).unwrap();
+
+        assert_eq!(config.backends, Backends::METAL);
+        assert_eq!(config.adapter, Some(AdapterSelector::Index(0)));
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        for (flag, value) in [
+            ("--backend", "glide"),
+            ("--power", "medium"),
+            ("--resolution", "0x600"),
+            ("--resolution", "800"),
+            ("--present-mode", "vsync"),
+            ("--fps", "-1"),
+            ("--msaa", "3"),
+            ("--record-format", "gif"),
+            ("--record-fps", "0"),
+            ("--poster", "4"),
+        ] {
+            assert_eq!(
+                parse(&[flag, value], &[]),
+                Err(ConfigError::invalid_value(flag, value))
+            );
+        }
+
+        assert_eq!(
+            parse(&[], &[("WGPU_POWER_PREF", "medium")]),
+            Err(ConfigError::invalid_value("WGPU_POWER_PREF", "medium"))
+        );
+    }
+
+    #[test]
+    fn flags_need_their_values() {
+        assert_eq!(
+            parse(&["--msaa"], &[]),
+            Err(ConfigError::MissingValue("--msaa".to_string()))
+        );
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        assert_eq!(
+            parse(&["--verbose"], &[]),
+            Err(ConfigError::UnknownFlag("--verbose".to_string()))
+        );
+    }
+
+    #[test]
+    fn help_is_asked_for_with_either_flag() {
+        assert!(parse(&["--help"], &[]).unwrap().help);
+        assert!(parse(&["-h"], &[]).unwrap().help);
+        assert!(!parse(&[], &[]).unwrap().help);
+    }
+}
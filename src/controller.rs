@@ -0,0 +1,692 @@
+use glam::{Vec2, Vec3};
+
+use crate::{
+    actions::{Action, ActionMap},
+    bounds::Aabb,
+    camera::{Camera, CameraMode},
+    input::InputState,
+};
+
+pub struct FlyControllerDescriptor {
+    /// How quickly, in units per second squared, the camera gets up to speed and comes to a stop.
+    pub acceleration: f32,
+    /// Multiplier applied to the camera speed while sprinting.
+    pub sprint_multiplier: f32,
+    /// How quickly, in degrees per second, the camera rolls in free mode.
+    pub roll_speed: f32,
+}
+
+impl Default for FlyControllerDescriptor {
+    fn default() -> Self {
+        Self {
+            acceleration: 40.0,
+            sprint_multiplier: 3.0,
+            roll_speed: 90.0,
+        }
+    }
+}
+
+/// Moves a [`Camera`] from the actions held in an [`InputState`] and the mouse movement.
+///
+/// Movement is integrated every frame rather than on key events, so it's independent of the key repeat rate and
+/// actions combine, e.g. moving forward and right together moves diagonally. The roll actions roll the camera in free
+/// mode. In orbit mode dragging with the orbit binding rotates around the target, dragging with the pan binding pans
+/// and zooming dollies.
+pub struct FlyController {
+    acceleration: f32,
+    sprint_multiplier: f32,
+    roll_speed: f32,
+    velocity: Vec3,
+}
+
+impl FlyController {
+    pub fn new(desc: &FlyControllerDescriptor) -> Self {
+        Self {
+            acceleration: desc.acceleration,
+            sprint_multiplier: desc.sprint_multiplier,
+            roll_speed: desc.roll_speed,
+            velocity: Vec3::ZERO,
+        }
+    }
+
+    pub fn get_velocity(&self) -> Vec3 {
+        self.velocity
+    }
+
+    /// Bring the camera to an immediate stop.
+    pub fn stop(&mut self) {
+        self.velocity = Vec3::ZERO;
+    }
+
+    pub fn update(
+        &mut self,
+        input: &InputState,
+        actions: &ActionMap,
+        camera: &mut Camera,
+        dt: f32,
+    ) {
+        self.look(input, actions, camera);
+
+        if camera.get_mode() == CameraMode::Free {
+            let roll = actions.axis(input, Action::RollRight, Action::RollLeft);
+            camera.roll(roll * self.roll_speed * dt);
+        }
+
+        let target_velocity =
+            self.wish_direction(input, actions, camera) * self.max_speed(input, actions, camera);
+        let change = (target_velocity - self.velocity).clamp_length_max(self.acceleration * dt);
+        self.velocity += change;
+
+        camera.translate(self.velocity * dt);
+    }
+
+    fn look(&self, input: &InputState, actions: &ActionMap, camera: &mut Camera) {
+        let mouse_delta = input.mouse_delta();
+        let zoom = actions.value(input, Action::Zoom);
+
+        match camera.get_mode() {
+            CameraMode::Fly | CameraMode::Free => {
+                if camera.has_mouse() && mouse_delta != Vec2::ZERO {
+                    camera.yaw_pitch(mouse_delta.x, -mouse_delta.y);
+                }
+                if zoom != 0.0 {
+                    camera.zoom(zoom);
+                }
+            }
+
+            CameraMode::Orbit => {
+                if actions.is_down(input, Action::Orbit) {
+                    camera.orbit(mouse_delta.x, mouse_delta.y);
+                } else if actions.is_down(input, Action::Pan) {
+                    camera.pan(mouse_delta.x, mouse_delta.y);
+                }
+                if zoom != 0.0 {
+                    camera.dolly(zoom);
+                }
+            }
+        }
+    }
+
+    fn wish_direction(&self, input: &InputState, actions: &ActionMap, camera: &Camera) -> Vec3 {
+        let forward = actions.axis(input, Action::MoveForward, Action::MoveBackward);
+        let right = actions.axis(input, Action::MoveRight, Action::MoveLeft);
+        let up = actions.axis(input, Action::MoveUp, Action::MoveDown);
+
+        (camera.get_direction() * forward + camera.get_right() * right + camera.get_up() * up)
+            .normalize_or_zero()
+    }
+
+    fn max_speed(&self, input: &InputState, actions: &ActionMap, camera: &Camera) -> f32 {
+        if actions.is_down(input, Action::Sprint) {
+            camera.get_speed() * self.sprint_multiplier
+        } else {
+            camera.get_speed()
+        }
+    }
+}
+
+/// Fixed timestep, in seconds, of the walk physics so that the same inputs always give the same motion regardless of
+/// the frame rate.
+pub const WALK_TIMESTEP: f32 = 1.0 / 120.0;
+
+/// Longest frame, in seconds, the walk physics catches up on. Longer frames, e.g. after a hitch or while the window
+/// is dragged, are cut short so that the next frame doesn't stall running hundreds of steps.
+pub const MAX_WALK_FRAME_TIME: f32 = 0.25;
+
+/// Gap left between the walker and boxes it's pushed out of.
+const SKIN: f32 = 1e-4;
+
+/// The static geometry a [`WalkController`] collides with: an infinite ground plane and a set of solid boxes.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CollisionWorld {
+    pub ground_height: f32,
+    pub boxes: Vec<Aabb>,
+}
+
+pub struct WalkControllerDescriptor {
+    /// Height of the camera above the walker's feet.
+    pub eye_height: f32,
+    /// Half the width of the walker's collision box.
+    pub radius: f32,
+    /// Walking speed in units per second.
+    pub speed: f32,
+    /// Multiplier applied to the walking speed while sprinting.
+    pub sprint_multiplier: f32,
+    /// How quickly, in units per second squared, the walker gets up to speed and comes to a stop on the ground.
+    pub acceleration: f32,
+    /// Fraction of the acceleration available while in the air.
+    pub air_control: f32,
+    /// Downwards acceleration in units per second squared.
+    pub gravity: f32,
+    /// Upwards speed, in units per second, at the start of a jump.
+    pub jump_speed: f32,
+}
+
+impl Default for WalkControllerDescriptor {
+    fn default() -> Self {
+        Self {
+            eye_height: 1.6,
+            radius: 0.3,
+            speed: 4.0,
+            sprint_multiplier: 2.0,
+            acceleration: 40.0,
+            air_control: 0.3,
+            gravity: 9.81,
+            jump_speed: 4.5,
+        }
+    }
+}
+
+/// One physics step's worth of intent, independent of where it came from.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct WalkInput {
+    /// Horizontal direction to walk in, with a length of at most 1.
+    pub direction: Vec3,
+    pub jump: bool,
+    pub sprint: bool,
+}
+
+/// Walks a [`Camera`] around a [`CollisionWorld`] with gravity and jumping.
+///
+/// The walker is an axis aligned box standing on its feet, `radius` wide on each side and a little taller than its
+/// eyes. Each step moves it along one axis at a time and pushes it back out of anything it ends up inside, so running
+/// into a wall at an angle slides along the wall instead of stopping dead. Physics runs in fixed [`WALK_TIMESTEP`]
+/// steps, see [`WalkController::step`], which is deterministic and doesn't need a camera or window to drive.
+pub struct WalkController {
+    eye_height: f32,
+    radius: f32,
+    speed: f32,
+    sprint_multiplier: f32,
+    acceleration: f32,
+    air_control: f32,
+    gravity: f32,
+    jump_speed: f32,
+    position: Vec3,
+    velocity: Vec3,
+    on_ground: bool,
+    jump_pending: bool,
+    accumulator: f32,
+}
+
+impl WalkController {
+    /// A walker standing with its feet at `position`.
+    pub fn new(desc: &WalkControllerDescriptor, position: Vec3) -> Self {
+        Self {
+            eye_height: desc.eye_height,
+            radius: desc.radius,
+            speed: desc.speed,
+            sprint_multiplier: desc.sprint_multiplier,
+            acceleration: desc.acceleration,
+            air_control: desc.air_control,
+            gravity: desc.gravity,
+            jump_speed: desc.jump_speed,
+            position,
+            velocity: Vec3::ZERO,
+            on_ground: false,
+            jump_pending: false,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Position of the walker's feet.
+    pub fn get_position(&self) -> Vec3 {
+        self.position
+    }
+
+    /// Move the walker's feet to `position`, stopping it.
+    pub fn set_position(&mut self, position: Vec3) {
+        self.position = position;
+        self.velocity = Vec3::ZERO;
+        self.on_ground = false;
+    }
+
+    pub fn get_eye_position(&self) -> Vec3 {
+        self.position + Vec3::Y * self.eye_height
+    }
+
+    pub fn get_velocity(&self) -> Vec3 {
+        self.velocity
+    }
+
+    pub fn is_on_ground(&self) -> bool {
+        self.on_ground
+    }
+
+    /// The walker's collision box.
+    pub fn bounds(&self) -> Aabb {
+        let half_width = Vec3::new(self.radius, 0.0, self.radius);
+        Aabb::new(
+            self.position - half_width,
+            self.position + half_width + Vec3::Y * self.height(),
+        )
+    }
+
+    /// Look around with the mouse, step the physics from the held actions and put the camera at the walker's eyes.
+    /// The camera is switched to fly mode, since walking has no use for roll or orbiting.
+    pub fn update(
+        &mut self,
+        input: &InputState,
+        actions: &ActionMap,
+        world: &CollisionWorld,
+        camera: &mut Camera,
+        dt: f32,
+    ) {
+        if camera.get_mode() != CameraMode::Fly {
+            camera.set_mode(CameraMode::Fly);
+        }
+
+        let mouse_delta = input.mouse_delta();
+        if camera.has_mouse() && mouse_delta != Vec2::ZERO {
+            camera.yaw_pitch(mouse_delta.x, -mouse_delta.y);
+        }
+
+        let zoom = actions.value(input, Action::Zoom);
+        if zoom != 0.0 {
+            camera.zoom(zoom);
+        }
+
+        let direction = camera.get_direction();
+        let forward = Vec3::new(direction.x, 0.0, direction.z).normalize_or_zero();
+        let right = forward.cross(Vec3::Y);
+        let walk_input = WalkInput {
+            direction: (forward * actions.axis(input, Action::MoveForward, Action::MoveBackward)
+                + right * actions.axis(input, Action::MoveRight, Action::MoveLeft))
+            .normalize_or_zero(),
+            jump: actions.was_pressed(input, Action::Jump),
+            sprint: actions.is_down(input, Action::Sprint),
+        };
+
+        self.advance(&walk_input, world, dt);
+        camera.set_position(self.get_eye_position());
+    }
+
+    /// Run as many fixed steps as fit into `dt`, at most [`MAX_WALK_FRAME_TIME`], carrying the remainder over to the
+    /// next call.
+    ///
+    /// A jump is held on to until the next step, which may be in a later call when frames are shorter than a step,
+    /// and only that step jumps so a press isn't repeated.
+    pub fn advance(&mut self, input: &WalkInput, world: &CollisionWorld, dt: f32) {
+        self.accumulator += dt.min(MAX_WALK_FRAME_TIME);
+        self.jump_pending |= input.jump;
+
+        while self.accumulator >= WALK_TIMESTEP {
+            let step_input = WalkInput {
+                jump: self.jump_pending,
+                ..*input
+            };
+            self.step(&step_input, world);
+            self.jump_pending = false;
+            self.accumulator -= WALK_TIMESTEP;
+        }
+    }
+
+    /// Advance the physics by a single [`WALK_TIMESTEP`].
+    pub fn step(&mut self, input: &WalkInput, world: &CollisionWorld) {
+        let dt = WALK_TIMESTEP;
+
+        let max_speed = if input.sprint {
+            self.speed * self.sprint_multiplier
+        } else {
+            self.speed
+        };
+        let acceleration = if self.on_ground {
+            self.acceleration
+        } else {
+            self.acceleration * self.air_control
+        };
+
+        let horizontal_velocity = Vec3::new(self.velocity.x, 0.0, self.velocity.z);
+        let target_velocity = input.direction.clamp_length_max(1.0) * max_speed;
+        let change = (target_velocity - horizontal_velocity).clamp_length_max(acceleration * dt);
+        self.velocity.x += change.x;
+        self.velocity.z += change.z;
+
+        if input.jump && self.on_ground {
+            self.velocity.y = self.jump_speed;
+        }
+        self.velocity.y -= self.gravity * dt;
+
+        self.on_ground = false;
+        for axis in [0, 2, 1] {
+            self.move_along(axis, self.velocity[axis] * dt, world);
+        }
+    }
+
+    fn height(&self) -> f32 {
+        // Leave a little room above the eyes so the near plane doesn't clip into ceilings.
+        self.eye_height + self.radius * 0.5
+    }
+
+    /// Move `distance` along `axis`, stopping against the first box in the way.
+    fn move_along(&mut self, axis: usize, distance: f32, world: &CollisionWorld) {
+        if distance == 0.0 {
+            return;
+        }
+
+        // How far the walker's box reaches either side of its position along the axis.
+        let (extent_below, extent_above) = if axis == 1 {
+            (0.0, self.height())
+        } else {
+            (self.radius, self.radius)
+        };
+
+        self.position[axis] += distance;
+
+        for aabb in &world.boxes {
+            // The walker may already have been pushed out of an earlier box, so check where it is now.
+            if !overlaps(&self.bounds(), aabb) {
+                continue;
+            }
+
+            // Push back out through the face that was crossed, which is the one facing the way we came from, leaving
+            // a sliver of space so rounding can't leave the boxes overlapping on this axis when moving along the next.
+            if distance > 0.0 {
+                self.position[axis] = self.position[axis].min(aabb.min[axis] - extent_above - SKIN);
+            } else {
+                self.position[axis] = self.position[axis].max(aabb.max[axis] + extent_below + SKIN);
+                if axis == 1 {
+                    self.on_ground = true;
+                }
+            }
+            self.velocity[axis] = 0.0;
+        }
+
+        if axis == 1 && self.position.y <= world.ground_height {
+            self.position.y = world.ground_height;
+            self.velocity.y = 0.0;
+            self.on_ground = true;
+        }
+    }
+}
+
+/// Whether two boxes overlap with some volume. Unlike [`Aabb::intersects`] boxes that only touch don't count, so the
+/// walker can stand on a box or slide along it without getting stuck.
+fn overlaps(a: &Aabb, b: &Aabb) -> bool {
+    a.min.cmplt(b.max).all() && a.max.cmpgt(b.min).all()
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::VirtualKeyCode;
+
+    use super::*;
+    use crate::camera::CameraDescriptor;
+
+    const EPSILON: f32 = 1e-5;
+
+    /// Fly for `frames` frames of `dt` seconds holding `keys`, with the default bindings.
+    fn fly(
+        controller: &mut FlyController,
+        camera: &mut Camera,
+        keys: &[VirtualKeyCode],
+        frames: u32,
+        dt: f32,
+    ) {
+        let mut input = InputState::new();
+        for key in keys {
+            input.press_key(*key);
+        }
+
+        for _ in 0..frames {
+            controller.update(&input, &ActionMap::default(), camera, dt);
+        }
+    }
+
+    /// A controller accelerating at 40 units per second squared and a camera looking down -Z at 10 units per second.
+    fn flyer() -> (FlyController, Camera) {
+        (
+            FlyController::new(&FlyControllerDescriptor::default()),
+            Camera::new(&CameraDescriptor::default()),
+        )
+    }
+
+    #[test]
+    fn flying_accelerates_up_to_the_camera_speed() {
+        let (mut controller, mut camera) = flyer();
+
+        fly(&mut controller, &mut camera, &[VirtualKeyCode::W], 1, 0.1);
+        assert!(controller
+            .get_velocity()
+            .abs_diff_eq(Vec3::new(0.0, 0.0, -4.0), EPSILON));
+        assert!(camera
+            .get_position()
+            .abs_diff_eq(Vec3::new(0.0, 0.0, 2.6), EPSILON));
+
+        fly(&mut controller, &mut camera, &[VirtualKeyCode::W], 10, 0.1);
+        assert!(controller
+            .get_velocity()
+            .abs_diff_eq(Vec3::new(0.0, 0.0, -10.0), EPSILON));
+    }
+
+    #[test]
+    fn flying_diagonally_is_no_faster() {
+        let (mut controller, mut camera) = flyer();
+
+        fly(
+            &mut controller,
+            &mut camera,
+            &[VirtualKeyCode::W, VirtualKeyCode::D],
+            10,
+            0.1,
+        );
+
+        let diagonal = 0.5_f32.sqrt() * 10.0;
+        assert!(controller
+            .get_velocity()
+            .abs_diff_eq(Vec3::new(diagonal, 0.0, -diagonal), EPSILON));
+    }
+
+    #[test]
+    fn sprinting_multiplies_the_speed() {
+        let (mut controller, mut camera) = flyer();
+
+        fly(
+            &mut controller,
+            &mut camera,
+            &[VirtualKeyCode::W, VirtualKeyCode::LShift],
+            10,
+            0.1,
+        );
+
+        assert!(controller
+            .get_velocity()
+            .abs_diff_eq(Vec3::new(0.0, 0.0, -30.0), EPSILON));
+    }
+
+    #[test]
+    fn letting_go_slows_to_a_stop() {
+        let (mut controller, mut camera) = flyer();
+        fly(&mut controller, &mut camera, &[VirtualKeyCode::W], 10, 0.1);
+
+        fly(&mut controller, &mut camera, &[], 1, 0.1);
+        assert!(controller
+            .get_velocity()
+            .abs_diff_eq(Vec3::new(0.0, 0.0, -6.0), EPSILON));
+
+        fly(&mut controller, &mut camera, &[], 2, 0.1);
+        assert_eq!(controller.get_velocity(), Vec3::ZERO);
+
+        let position = camera.get_position();
+        fly(&mut controller, &mut camera, &[], 1, 0.1);
+        assert_eq!(camera.get_position(), position);
+    }
+
+    #[test]
+    fn stop_is_immediate() {
+        let (mut controller, mut camera) = flyer();
+        fly(&mut controller, &mut camera, &[VirtualKeyCode::W], 10, 0.1);
+
+        controller.stop();
+        assert_eq!(controller.get_velocity(), Vec3::ZERO);
+    }
+
+    fn walker_at(position: Vec3) -> WalkController {
+        WalkController::new(&WalkControllerDescriptor::default(), position)
+    }
+
+    /// A walker that has landed on the ground at the origin.
+    fn grounded_walker(world: &CollisionWorld) -> WalkController {
+        let mut walker = walker_at(Vec3::ZERO);
+        walker.step(&WalkInput::default(), world);
+        assert!(walker.is_on_ground());
+        walker
+    }
+
+    fn wall(min: Vec3, max: Vec3) -> CollisionWorld {
+        CollisionWorld {
+            ground_height: 0.0,
+            boxes: vec![Aabb::new(min, max)],
+        }
+    }
+
+    fn walk_for(
+        walker: &mut WalkController,
+        input: &WalkInput,
+        world: &CollisionWorld,
+        steps: u32,
+    ) {
+        for _ in 0..steps {
+            walker.step(input, world);
+        }
+    }
+
+    #[test]
+    fn jump_in_a_frame_shorter_than_a_step_is_kept() {
+        let world = CollisionWorld::default();
+        let mut walker = grounded_walker(&world);
+        let jump = WalkInput {
+            jump: true,
+            ..Default::default()
+        };
+
+        walker.advance(&jump, &world, WALK_TIMESTEP / 2.0);
+        assert_eq!(walker.get_position().y, 0.0);
+
+        walker.advance(&WalkInput::default(), &world, WALK_TIMESTEP / 2.0);
+        walker.advance(&WalkInput::default(), &world, WALK_TIMESTEP);
+        assert!(walker.get_position().y > 0.0);
+        assert!(!walker.is_on_ground());
+    }
+
+    #[test]
+    fn jump_is_only_used_by_one_step() {
+        let world = CollisionWorld::default();
+        let desc = WalkControllerDescriptor::default();
+        let mut walker = grounded_walker(&world);
+        let jump = WalkInput {
+            jump: true,
+            ..Default::default()
+        };
+
+        walker.advance(&jump, &world, WALK_TIMESTEP * 3.0);
+
+        let expected = desc.jump_speed - desc.gravity * WALK_TIMESTEP * 3.0;
+        assert!((walker.get_velocity().y - expected).abs() < 1e-3);
+    }
+
+    #[test]
+    fn long_frames_are_cut_short() {
+        let world = CollisionWorld::default();
+        let input = WalkInput {
+            direction: Vec3::X,
+            ..Default::default()
+        };
+        let mut hitched = grounded_walker(&world);
+        let mut capped = grounded_walker(&world);
+
+        hitched.advance(&input, &world, 10.0);
+        capped.advance(&input, &world, MAX_WALK_FRAME_TIME);
+
+        assert_eq!(hitched.get_position(), capped.get_position());
+        assert_eq!(hitched.get_velocity(), capped.get_velocity());
+    }
+
+    #[test]
+    fn advancing_in_whole_steps_matches_stepping() {
+        let world = wall(Vec3::new(1.0, 0.0, -5.0), Vec3::new(2.0, 3.0, 5.0));
+        let input = WalkInput {
+            direction: Vec3::new(1.0, 0.0, 1.0).normalize(),
+            jump: true,
+            sprint: true,
+        };
+        let mut advanced = walker_at(Vec3::new(0.0, 0.5, 0.0));
+        let mut stepped = walker_at(Vec3::new(0.0, 0.5, 0.0));
+
+        for _ in 0..240 {
+            advanced.advance(&input, &world, WALK_TIMESTEP);
+            stepped.step(&input, &world);
+        }
+
+        assert_eq!(advanced.get_position(), stepped.get_position());
+        assert_eq!(advanced.get_velocity(), stepped.get_velocity());
+    }
+
+    #[test]
+    fn falls_onto_the_ground() {
+        let world = CollisionWorld {
+            ground_height: -1.0,
+            boxes: Vec::new(),
+        };
+        let mut walker = walker_at(Vec3::new(0.0, 2.0, 0.0));
+
+        walk_for(&mut walker, &WalkInput::default(), &world, 240);
+
+        assert_eq!(walker.get_position().y, -1.0);
+        assert_eq!(walker.get_velocity().y, 0.0);
+        assert!(walker.is_on_ground());
+    }
+
+    #[test]
+    fn stands_on_a_box() {
+        let world = wall(Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let mut walker = walker_at(Vec3::new(0.0, 2.0, 0.0));
+
+        walk_for(&mut walker, &WalkInput::default(), &world, 240);
+
+        assert!((walker.get_position().y - (1.0 + SKIN)).abs() < EPSILON);
+        assert!(walker.is_on_ground());
+    }
+
+    #[test]
+    fn slides_along_a_wall() {
+        let world = wall(Vec3::new(1.0, 0.0, -50.0), Vec3::new(2.0, 3.0, 50.0));
+        let mut walker = grounded_walker(&world);
+        let input = WalkInput {
+            direction: Vec3::new(1.0, 0.0, 1.0).normalize(),
+            ..Default::default()
+        };
+
+        walk_for(&mut walker, &input, &world, 240);
+
+        let radius = WalkControllerDescriptor::default().radius;
+        let position = walker.get_position();
+        assert!((position.x - (1.0 - radius - SKIN)).abs() < EPSILON);
+        assert!(position.z > 1.0);
+        assert_eq!(walker.get_velocity().x, 0.0);
+    }
+
+    #[test]
+    fn boxes_sharing_a_face_stop_like_one() {
+        let single = wall(Vec3::new(1.0, 0.0, -1.0), Vec3::new(2.0, 3.0, 1.0));
+        let split = CollisionWorld {
+            ground_height: 0.0,
+            boxes: vec![
+                Aabb::new(Vec3::new(1.0, 0.0, -1.0), Vec3::new(2.0, 3.0, 0.0)),
+                Aabb::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(2.0, 3.0, 1.0)),
+            ],
+        };
+        let input = WalkInput {
+            direction: Vec3::X,
+            ..Default::default()
+        };
+
+        let mut against_single = grounded_walker(&single);
+        let mut against_split = grounded_walker(&split);
+        walk_for(&mut against_single, &input, &single, 240);
+        walk_for(&mut against_split, &input, &split, 240);
+
+        let radius = WalkControllerDescriptor::default().radius;
+        assert!((against_single.get_position().x - (1.0 - radius - SKIN)).abs() < EPSILON);
+        assert_eq!(against_split.get_position(), against_single.get_position());
+    }
+}
@@ -0,0 +1,79 @@
+use wgpu::{
+    CompareFunction, DepthBiasState, DepthStencilState, Device, Extent3d, LoadOp, Operations,
+    StencilState, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    TextureView, TextureViewDescriptor,
+};
+
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// How depth values are distributed between the near and far planes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DepthMode {
+    /// Near plane at depth 0 and far plane at depth 1.
+    #[default]
+    Standard,
+    /// Near plane at depth 1 with the far plane at infinity, i.e. depth 0. Paired with a floating point depth buffer
+    /// this spreads precision far more evenly over the scene, so distant objects don't z-fight and are never clipped.
+    ReverseInfinite,
+}
+
+impl DepthMode {
+    pub fn compare_function(self) -> CompareFunction {
+        match self {
+            DepthMode::Standard => CompareFunction::Less,
+            DepthMode::ReverseInfinite => CompareFunction::Greater,
+        }
+    }
+
+    /// The depth value of a cleared depth buffer, i.e. the furthest possible depth.
+    pub fn clear_value(self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::ReverseInfinite => 0.0,
+        }
+    }
+
+    pub fn depth_ops(self) -> Operations<f32> {
+        Operations {
+            load: LoadOp::Clear(self.clear_value()),
+            store: true,
+        }
+    }
+
+    pub fn depth_stencil_state(self) -> DepthStencilState {
+        DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: self.compare_function(),
+            stencil: StencilState::default(),
+            bias: DepthBiasState::default(),
+        }
+    }
+}
+
+/// A depth buffer for a `width` by `height` target with `sample_count` samples per pixel.
+pub fn create_depth_texture(
+    device: &Device,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+) -> (Texture, TextureView) {
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("texture::depth"),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        usage: TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+
+    let texture_view = texture.create_view(&TextureViewDescriptor::default());
+
+    (texture, texture_view)
+}
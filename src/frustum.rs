@@ -0,0 +1,305 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::{
+    bounds::{Aabb, Sphere},
+    depth::DepthMode,
+};
+
+/// Plane with a unit `normal`, containing the points `p` where `normal.dot(p) + distance == 0`. Points on the side
+/// the normal points to have a positive signed distance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+impl Plane {
+    /// Build a plane from `(a, b, c, d)` coefficients, normalizing them so that `(a, b, c)` is a unit vector. A
+    /// plane with no normal, such as the far plane of an infinite projection, is left as is; its signed distance is
+    /// the same everywhere.
+    pub fn from_coefficients(coefficients: Vec4) -> Self {
+        let normal = coefficients.truncate();
+        let length = normal.length();
+
+        if length > f32::EPSILON {
+            Self {
+                normal: normal / length,
+                distance: coefficients.w / length,
+            }
+        } else {
+            Self {
+                normal: Vec3::ZERO,
+                distance: coefficients.w,
+            }
+        }
+    }
+
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+/// The six planes bounding everything a camera can see, with normals pointing into the frustum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    pub const LEFT: usize = 0;
+    pub const RIGHT: usize = 1;
+    pub const BOTTOM: usize = 2;
+    pub const TOP: usize = 3;
+    pub const NEAR: usize = 4;
+    pub const FAR: usize = 5;
+
+    /// Extract the frustum planes from a `projection * view` matrix with the Gribb-Hartmann method, using wgpu's
+    /// `[0, 1]` clip space depth range. The result is in world space; pass a projection matrix alone to get the planes
+    /// in view space.
+    pub fn from_view_projection(view_projection: Mat4, depth_mode: DepthMode) -> Self {
+        let row_0 = view_projection.row(0);
+        let row_1 = view_projection.row(1);
+        let row_2 = view_projection.row(2);
+        let row_3 = view_projection.row(3);
+
+        // Depth is 0 at the near plane normally but 0 at the far plane when it's reversed.
+        let (near, far) = match depth_mode {
+            DepthMode::Standard => (row_2, row_3 - row_2),
+            DepthMode::ReverseInfinite => (row_3 - row_2, row_2),
+        };
+
+        Self {
+            planes: [
+                Plane::from_coefficients(row_3 + row_0),
+                Plane::from_coefficients(row_3 - row_0),
+                Plane::from_coefficients(row_3 + row_1),
+                Plane::from_coefficients(row_3 - row_1),
+                Plane::from_coefficients(near),
+                Plane::from_coefficients(far),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    /// Whether any part of `sphere` might be inside the frustum. Like all plane based tests this is conservative:
+    /// spheres just outside a corner of the frustum are reported as intersecting.
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+
+    /// Whether any part of `aabb` might be inside the frustum. Each plane is tested against the corner of the box
+    /// furthest along its normal, so a box is only rejected when it's entirely behind one plane.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let furthest = Vec3::select(plane.normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            plane.signed_distance(furthest) >= 0.0
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CullStats {
+    pub visible: usize,
+    pub culled: usize,
+}
+
+/// Filter a draw list down to the items whose bounds intersect `frustum`, counting how many were kept and dropped.
+pub fn cull<T, I, F>(frustum: &Frustum, items: I, bounds: F) -> (Vec<T>, CullStats)
+where
+    I: IntoIterator<Item = T>,
+    F: Fn(&T) -> Aabb,
+{
+    let mut stats = CullStats::default();
+
+    let visible = items
+        .into_iter()
+        .filter(|item| {
+            let visible = frustum.intersects_aabb(&bounds(item));
+            if visible {
+                stats.visible += 1;
+            } else {
+                stats.culled += 1;
+            }
+            visible
+        })
+        .collect();
+
+    (visible, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    /// A camera at the origin looking down -Z with a 90 degree field of view, seeing from 1 to 100 units away.
+    fn frustum(depth_mode: DepthMode) -> Frustum {
+        let projection = match depth_mode {
+            DepthMode::Standard => Mat4::perspective_rh(90.0_f32.to_radians(), 1.0, 1.0, 100.0),
+            DepthMode::ReverseInfinite => {
+                Mat4::perspective_infinite_reverse_rh(90.0_f32.to_radians(), 1.0, 1.0)
+            }
+        };
+
+        Frustum::from_view_projection(projection, depth_mode)
+    }
+
+    /// Far planes are a long way off, so their distances are only compared relative to their size.
+    fn assert_plane_near(plane: &Plane, normal: Vec3, distance: f32) {
+        assert!(
+            plane.normal.abs_diff_eq(normal, EPSILON)
+                && (plane.distance - distance).abs() <= EPSILON * distance.abs().max(1.0),
+            "{:?} != {:?}, {}",
+            plane,
+            normal,
+            distance
+        );
+    }
+
+    #[test]
+    fn planes_are_normalized() {
+        for depth_mode in [DepthMode::Standard, DepthMode::ReverseInfinite] {
+            let frustum = frustum(depth_mode);
+            for (index, plane) in frustum.planes.iter().enumerate() {
+                if depth_mode == DepthMode::ReverseInfinite && index == Frustum::FAR {
+                    continue;
+                }
+                assert!(
+                    (plane.normal.length() - 1.0).abs() < EPSILON,
+                    "plane {} of {:?} isn't normalized: {:?}",
+                    index,
+                    depth_mode,
+                    plane
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn planes_face_into_the_frustum() {
+        let frustum = frustum(DepthMode::Standard);
+        let diagonal = 0.5_f32.sqrt();
+
+        assert_plane_near(
+            &frustum.planes[Frustum::LEFT],
+            Vec3::new(diagonal, 0.0, -diagonal),
+            0.0,
+        );
+        assert_plane_near(
+            &frustum.planes[Frustum::RIGHT],
+            Vec3::new(-diagonal, 0.0, -diagonal),
+            0.0,
+        );
+        assert_plane_near(
+            &frustum.planes[Frustum::BOTTOM],
+            Vec3::new(0.0, diagonal, -diagonal),
+            0.0,
+        );
+        assert_plane_near(
+            &frustum.planes[Frustum::TOP],
+            Vec3::new(0.0, -diagonal, -diagonal),
+            0.0,
+        );
+        assert_plane_near(&frustum.planes[Frustum::NEAR], Vec3::NEG_Z, -1.0);
+        assert_plane_near(&frustum.planes[Frustum::FAR], Vec3::Z, 100.0);
+    }
+
+    #[test]
+    fn reversed_depth_has_the_same_near_plane_and_no_far_plane() {
+        let frustum = frustum(DepthMode::ReverseInfinite);
+
+        assert_plane_near(&frustum.planes[Frustum::NEAR], Vec3::NEG_Z, -1.0);
+        assert_eq!(frustum.planes[Frustum::FAR].normal, Vec3::ZERO);
+        assert!(frustum.planes[Frustum::FAR].distance > 0.0);
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -1.0e6)));
+    }
+
+    #[test]
+    fn points_are_inside_between_the_near_and_far_planes() {
+        let frustum = frustum(DepthMode::Standard);
+
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -10.0)));
+        assert!(frustum.contains_point(Vec3::new(9.0, -9.0, -10.0)));
+        assert!(!frustum.contains_point(Vec3::new(11.0, 0.0, -10.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -0.5)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -101.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 10.0)));
+    }
+
+    #[test]
+    fn spheres_inside_outside_and_straddling() {
+        for depth_mode in [DepthMode::Standard, DepthMode::ReverseInfinite] {
+            let frustum = frustum(depth_mode);
+            let sphere = |center, radius| Sphere { center, radius };
+
+            assert!(frustum.intersects_sphere(&sphere(Vec3::new(0.0, 0.0, -10.0), 1.0)));
+            assert!(!frustum.intersects_sphere(&sphere(Vec3::new(0.0, 0.0, 10.0), 1.0)));
+            assert!(!frustum.intersects_sphere(&sphere(Vec3::new(20.0, 0.0, -10.0), 1.0)));
+            // Straddling the near plane and the right plane.
+            assert!(frustum.intersects_sphere(&sphere(Vec3::ZERO, 2.0)));
+            assert!(frustum.intersects_sphere(&sphere(Vec3::new(10.5, 0.0, -10.0), 1.0)));
+        }
+
+        let frustum = frustum(DepthMode::Standard);
+        assert!(!frustum.intersects_sphere(&Sphere {
+            center: Vec3::new(0.0, 0.0, -110.0),
+            radius: 5.0,
+        }));
+        assert!(frustum.intersects_sphere(&Sphere {
+            center: Vec3::new(0.0, 0.0, -102.0),
+            radius: 5.0,
+        }));
+    }
+
+    #[test]
+    fn boxes_inside_outside_and_straddling() {
+        for depth_mode in [DepthMode::Standard, DepthMode::ReverseInfinite] {
+            let frustum = frustum(depth_mode);
+            let unit_box = |center| Aabb::from_center_half_extents(center, Vec3::splat(0.5));
+
+            assert!(frustum.intersects_aabb(&unit_box(Vec3::new(0.0, 0.0, -10.0))));
+            assert!(!frustum.intersects_aabb(&unit_box(Vec3::new(0.0, 0.0, 10.0))));
+            assert!(!frustum.intersects_aabb(&unit_box(Vec3::new(0.0, -20.0, -10.0))));
+            // Straddling the near plane and the top plane.
+            assert!(frustum.intersects_aabb(&unit_box(Vec3::new(0.0, 0.0, -1.0))));
+            assert!(frustum.intersects_aabb(&unit_box(Vec3::new(0.0, 10.0, -10.0))));
+        }
+
+        let frustum = frustum(DepthMode::Standard);
+        let far_box = |z| Aabb::from_center_half_extents(Vec3::new(0.0, 0.0, z), Vec3::splat(1.0));
+        assert!(!frustum.intersects_aabb(&far_box(-102.0)));
+        assert!(frustum.intersects_aabb(&far_box(-100.5)));
+    }
+
+    #[test]
+    fn cull_keeps_the_visible_items_and_counts_both() {
+        let frustum = frustum(DepthMode::Standard);
+        let centers = [
+            Vec3::new(0.0, 0.0, -10.0),
+            Vec3::new(0.0, 0.0, 10.0),
+            Vec3::new(5.0, 0.0, -10.0),
+            Vec3::new(50.0, 0.0, -10.0),
+        ];
+
+        let (visible, stats) = cull(&frustum, centers, |center| {
+            Aabb::from_center_half_extents(*center, Vec3::splat(0.5))
+        });
+
+        assert_eq!(visible, [centers[0], centers[2]]);
+        assert_eq!(
+            stats,
+            CullStats {
+                visible: 2,
+                culled: 2
+            }
+        );
+    }
+}
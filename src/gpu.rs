@@ -0,0 +1,485 @@
+use std::fmt;
+
+use futures::executor::block_on;
+use wgpu::{
+    Adapter, AdapterInfo, Backend, Backends, CompositeAlphaMode, CreateSurfaceError, Device,
+    DeviceDescriptor, Features, Instance, InstanceDescriptor, Limits, PowerPreference, PresentMode,
+    Queue, RequestAdapterOptions, RequestDeviceError, Surface, SurfaceCapabilities,
+    SurfaceConfiguration, TextureFormat, TextureUsages,
+};
+use winit::window::Window;
+
+use crate::msaa;
+
+/// What [`GpuContext::new`] needs from the adapter and prefers for the surface.
+#[derive(Debug, Clone)]
+pub struct GpuContextOptions {
+    pub backends: Backends,
+    pub power_preference: PowerPreference,
+    /// Use a software adapter, e.g. for machines without a GPU.
+    pub force_fallback_adapter: bool,
+    /// A specific adapter to use instead of the one matching the power preference.
+    pub adapter: Option<AdapterSelector>,
+    /// Features the context can't be created without.
+    pub required_features: Features,
+    /// Features that are enabled when the adapter has them. Check [`Device::features`] before relying on them.
+    pub optional_features: Features,
+    /// Limits the adapter has to meet.
+    pub required_limits: Limits,
+    /// Request everything the adapter supports rather than just the required limits.
+    pub request_adapter_limits: bool,
+    /// Surface formats in order of preference. The surface's own preferred format is used if none are supported.
+    pub preferred_formats: Vec<TextureFormat>,
+    /// Present modes in order of preference, falling back to [`PresentMode::Fifo`], which is always supported.
+    pub preferred_present_modes: Vec<PresentMode>,
+    /// Alpha modes in order of preference, falling back to the first mode the surface supports.
+    pub preferred_alpha_modes: Vec<CompositeAlphaMode>,
+    /// Surface usages besides [`TextureUsages::RENDER_ATTACHMENT`] that are enabled where the backend's surfaces
+    /// support them, e.g. [`TextureUsages::COPY_SRC`] to read frames back. Check the configured usage before relying
+    /// on them.
+    pub optional_surface_usages: TextureUsages,
+}
+
+impl Default for GpuContextOptions {
+    fn default() -> Self {
+        Self {
+            backends: Backends::PRIMARY,
+            power_preference: PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            adapter: None,
+            required_features: Features::empty(),
+            optional_features: Features::empty(),
+            required_limits: Limits::downlevel_defaults(),
+            request_adapter_limits: true,
+            preferred_formats: vec![TextureFormat::Rgba8Unorm, TextureFormat::Bgra8Unorm],
+            preferred_present_modes: vec![PresentMode::Fifo],
+            preferred_alpha_modes: vec![CompositeAlphaMode::Opaque],
+            optional_surface_usages: TextureUsages::empty(),
+        }
+    }
+}
+
+/// Picks an adapter out of those on the requested backends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdapterSelector {
+    /// The adapter's position in [`AdapterCapabilities::list`].
+    Index(usize),
+    /// The first adapter whose name contains this, ignoring case.
+    Name(String),
+}
+
+impl AdapterSelector {
+    /// The index of the selected adapter in `adapters`.
+    pub fn find(&self, adapters: &[AdapterInfo]) -> Option<usize> {
+        match self {
+            AdapterSelector::Index(index) => (*index < adapters.len()).then_some(*index),
+            AdapterSelector::Name(name) => {
+                let name = name.to_lowercase();
+                adapters
+                    .iter()
+                    .position(|info| info.name.to_lowercase().contains(&name))
+            }
+        }
+    }
+}
+
+impl fmt::Display for AdapterSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdapterSelector::Index(index) => write!(f, "adapter {}", index),
+            AdapterSelector::Name(name) => write!(f, "adapter named \"{}\"", name),
+        }
+    }
+}
+
+/// The name, features and limits of an adapter, for reporting what it can do.
+#[derive(Debug, Clone)]
+pub struct AdapterCapabilities {
+    pub info: AdapterInfo,
+    pub features: Features,
+    pub limits: Limits,
+}
+
+impl AdapterCapabilities {
+    pub fn from_adapter(adapter: &Adapter) -> Self {
+        Self {
+            info: adapter.get_info(),
+            features: adapter.features(),
+            limits: adapter.limits(),
+        }
+    }
+
+    /// The capabilities of every adapter on `backends`, in the order [`AdapterSelector::Index`] uses.
+    pub fn list(backends: Backends) -> Vec<Self> {
+        let instance = Instance::new(InstanceDescriptor {
+            backends,
+            ..Default::default()
+        });
+
+        Self::enumerate(&instance, backends)
+    }
+
+    /// The capabilities of every adapter available on `backends`.
+    pub fn enumerate(instance: &Instance, backends: Backends) -> Vec<Self> {
+        instance
+            .enumerate_adapters(backends)
+            .map(|adapter| Self::from_adapter(&adapter))
+            .collect()
+    }
+}
+
+impl fmt::Display for AdapterCapabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let limits = &self.limits;
+
+        writeln!(
+            f,
+            "{} ({:?}, {:?})",
+            self.info.name, self.info.backend, self.info.device_type
+        )?;
+        writeln!(f, "  features: {:?}", self.features)?;
+        write!(
+            f,
+            "  limits: max_texture_dimension_2d {}, max_bind_groups {}, max_uniform_buffer_binding_size {}, \
+             max_storage_buffer_binding_size {}, min_uniform_buffer_offset_alignment {}",
+            limits.max_texture_dimension_2d,
+            limits.max_bind_groups,
+            limits.max_uniform_buffer_binding_size,
+            limits.max_storage_buffer_binding_size,
+            limits.min_uniform_buffer_offset_alignment
+        )
+    }
+}
+
+/// A limit the adapter doesn't meet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitFailure {
+    pub name: &'static str,
+    pub required: u64,
+    pub supported: u64,
+}
+
+/// Why a [`GpuContext`] couldn't be created.
+#[derive(Debug)]
+pub enum SetupError {
+    CreateSurface(CreateSurfaceError),
+    /// No adapter on the requested backends can draw to the surface. Lists every adapter that was found.
+    NoAdapter {
+        backends: Backends,
+        available: Vec<AdapterCapabilities>,
+    },
+    /// The selected adapter doesn't exist. Lists every adapter on the requested backends.
+    AdapterNotFound {
+        selector: AdapterSelector,
+        available: Vec<AdapterCapabilities>,
+    },
+    MissingFeatures {
+        missing: Features,
+        adapter: Box<AdapterCapabilities>,
+    },
+    InsufficientLimits {
+        failures: Vec<LimitFailure>,
+        adapter: Box<AdapterCapabilities>,
+    },
+    RequestDevice {
+        source: RequestDeviceError,
+        adapter: Box<AdapterCapabilities>,
+    },
+    /// The adapter can't present to the surface.
+    IncompatibleSurface {
+        adapter: Box<AdapterCapabilities>,
+    },
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetupError::CreateSurface(e) => write!(f, "failed to create a surface: {}", e),
+
+            SetupError::NoAdapter {
+                backends,
+                available,
+            } => {
+                write!(
+                    f,
+                    "no adapter on {:?} is compatible with the surface",
+                    backends
+                )?;
+                if available.is_empty() {
+                    write!(f, ", and no adapters were found at all")
+                } else {
+                    write!(f, "; available adapters:")?;
+                    for adapter in available {
+                        write!(f, "\n{}", adapter)?;
+                    }
+                    Ok(())
+                }
+            }
+
+            SetupError::AdapterNotFound {
+                selector,
+                available,
+            } => {
+                write!(f, "there is no {}; available adapters:", selector)?;
+                for (index, adapter) in available.iter().enumerate() {
+                    write!(f, "\n{}: {}", index, adapter)?;
+                }
+                Ok(())
+            }
+
+            SetupError::MissingFeatures { missing, adapter } => write!(
+                f,
+                "the adapter is missing required features {:?}; adapter:\n{}",
+                missing, adapter
+            ),
+
+            SetupError::InsufficientLimits { failures, adapter } => {
+                write!(f, "the adapter doesn't meet the required limits:")?;
+                for failure in failures {
+                    write!(
+                        f,
+                        "\n  {}: required {}, supported {}",
+                        failure.name, failure.required, failure.supported
+                    )?;
+                }
+                write!(f, "\nadapter:\n{}", adapter)
+            }
+
+            SetupError::RequestDevice { source, adapter } => write!(
+                f,
+                "failed to get a device: {}; adapter:\n{}",
+                source, adapter
+            ),
+
+            SetupError::IncompatibleSurface { adapter } => write!(
+                f,
+                "the adapter can't present to the surface; adapter:\n{}",
+                adapter
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SetupError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SetupError::CreateSurface(e) => Some(e),
+            SetupError::RequestDevice { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// A device and the window surface it draws to.
+pub struct GpuContext {
+    pub instance: Instance,
+    pub adapter: Adapter,
+    pub device: Device,
+    pub queue: Queue,
+    pub surface: Surface,
+    pub surface_config: SurfaceConfiguration,
+    pub surface_capabilities: SurfaceCapabilities,
+}
+
+impl GpuContext {
+    /// Create a device for `window` and configure its surface to the window's size.
+    pub fn new(window: &Window, options: &GpuContextOptions) -> Result<Self, SetupError> {
+        let instance = Instance::new(InstanceDescriptor {
+            backends: options.backends,
+            ..Default::default()
+        });
+
+        let surface =
+            unsafe { instance.create_surface(window) }.map_err(SetupError::CreateSurface)?;
+
+        let adapter = match &options.adapter {
+            Some(selector) => select_adapter(&instance, options.backends, selector)?,
+            None => block_on(instance.request_adapter(&RequestAdapterOptions {
+                power_preference: options.power_preference,
+                force_fallback_adapter: options.force_fallback_adapter,
+                compatible_surface: Some(&surface),
+            }))
+            .ok_or_else(|| SetupError::NoAdapter {
+                backends: options.backends,
+                available: AdapterCapabilities::enumerate(&instance, Backends::all()),
+            })?,
+        };
+        let capabilities = Box::new(AdapterCapabilities::from_adapter(&adapter));
+        if !adapter.is_surface_supported(&surface) {
+            return Err(SetupError::IncompatibleSurface {
+                adapter: capabilities,
+            });
+        }
+
+        let missing = options.required_features - capabilities.features;
+        if !missing.is_empty() {
+            return Err(SetupError::MissingFeatures {
+                missing,
+                adapter: capabilities,
+            });
+        }
+        let features =
+            options.required_features | (options.optional_features & capabilities.features);
+
+        let failures = limit_failures(&options.required_limits, &capabilities.limits);
+        if !failures.is_empty() {
+            return Err(SetupError::InsufficientLimits {
+                failures,
+                adapter: capabilities,
+            });
+        }
+        let limits = if options.request_adapter_limits {
+            capabilities.limits.clone()
+        } else {
+            options.required_limits.clone()
+        };
+
+        let (device, queue) = block_on(adapter.request_device(
+            &DeviceDescriptor {
+                label: Some("device"),
+                features,
+                limits,
+            },
+            None,
+        ))
+        .map_err(|source| SetupError::RequestDevice {
+            source,
+            adapter: capabilities.clone(),
+        })?;
+
+        let surface_capabilities = surface.get_capabilities(&adapter);
+        let format = choose_format(&surface_capabilities.formats, &options.preferred_formats)
+            .ok_or(SetupError::IncompatibleSurface {
+                adapter: capabilities,
+            })?;
+
+        // A minimized window has no size, but the surface needs one to be configured.
+        let size = window.inner_size();
+        let surface_config = SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT
+                | (options.optional_surface_usages
+                    & supported_surface_usages(adapter.get_info().backend)),
+            format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: choose_present_mode(
+                &surface_capabilities.present_modes,
+                &options.preferred_present_modes,
+            ),
+            alpha_mode: choose_alpha_mode(
+                &surface_capabilities.alpha_modes,
+                &options.preferred_alpha_modes,
+            ),
+            view_formats: Vec::new(),
+        };
+        surface.configure(&device, &surface_config);
+
+        Ok(Self {
+            instance,
+            adapter,
+            device,
+            queue,
+            surface,
+            surface_config,
+            surface_capabilities,
+        })
+    }
+
+    pub fn adapter_capabilities(&self) -> AdapterCapabilities {
+        AdapterCapabilities::from_adapter(&self.adapter)
+    }
+
+    /// The sample counts supported for drawing to the surface with an optional `depth_format` depth buffer.
+    pub fn supported_sample_counts(&self, depth_format: Option<TextureFormat>) -> Vec<u32> {
+        msaa::supported_sample_counts(
+            &self.adapter,
+            &self.device,
+            self.surface_config.format,
+            depth_format,
+        )
+    }
+}
+
+fn select_adapter(
+    instance: &Instance,
+    backends: Backends,
+    selector: &AdapterSelector,
+) -> Result<Adapter, SetupError> {
+    let mut adapters = instance.enumerate_adapters(backends).collect::<Vec<_>>();
+    let infos = adapters
+        .iter()
+        .map(|adapter| adapter.get_info())
+        .collect::<Vec<_>>();
+
+    match selector.find(&infos) {
+        Some(index) => Ok(adapters.swap_remove(index)),
+        None => Err(SetupError::AdapterNotFound {
+            selector: selector.clone(),
+            available: adapters
+                .iter()
+                .map(AdapterCapabilities::from_adapter)
+                .collect(),
+        }),
+    }
+}
+
+/// Every limit in `required` that `supported` doesn't meet.
+pub fn limit_failures(required: &Limits, supported: &Limits) -> Vec<LimitFailure> {
+    let mut failures = Vec::new();
+    required.check_limits_with_fail_fn(supported, false, |name, required, supported| {
+        failures.push(LimitFailure {
+            name,
+            required,
+            supported,
+        })
+    });
+
+    failures
+}
+
+/// The usages surfaces on `backend` support.
+///
+/// wgpu doesn't report which usages a surface supports and configuring one with an unsupported usage is fatal, so
+/// this goes by what each backend's surfaces are known to allow. Vulkan only guarantees render attachments, but every
+/// desktop driver allows copies too.
+pub fn supported_surface_usages(backend: Backend) -> TextureUsages {
+    match backend {
+        Backend::Vulkan | Backend::Dx12 => {
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC | TextureUsages::COPY_DST
+        }
+        Backend::Metal => TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_DST,
+        _ => TextureUsages::RENDER_ATTACHMENT,
+    }
+}
+
+/// The first of `preferred` in `supported`, or the surface's own preference, which it lists first. `None` if nothing
+/// is supported.
+pub fn choose_format(
+    supported: &[TextureFormat],
+    preferred: &[TextureFormat],
+) -> Option<TextureFormat> {
+    choose(supported, preferred).or_else(|| supported.first().copied())
+}
+
+pub fn choose_present_mode(supported: &[PresentMode], preferred: &[PresentMode]) -> PresentMode {
+    choose(supported, preferred).unwrap_or(PresentMode::Fifo)
+}
+
+pub fn choose_alpha_mode(
+    supported: &[CompositeAlphaMode],
+    preferred: &[CompositeAlphaMode],
+) -> CompositeAlphaMode {
+    choose(supported, preferred)
+        .or_else(|| supported.first().copied())
+        .unwrap_or(CompositeAlphaMode::Auto)
+}
+
+fn choose<T>(supported: &[T], preferred: &[T]) -> Option<T>
+where
+    T: Copy + PartialEq,
+{
+    preferred
+        .iter()
+        .copied()
+        .find(|value| supported.contains(value))
+}
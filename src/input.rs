@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+
+use glam::Vec2;
+use winit::event::{
+    DeviceEvent, ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+/// Lines scrolled per pixel for devices, such as touchpads, that report scrolling in pixels.
+const PIXELS_PER_LINE: f32 = 1.0 / 20.0;
+
+/// Tracks which keys and mouse buttons are currently held, along with the mouse movement and scrolling since the
+/// last frame. Feed it every window and device event, read it once per frame, then call [`InputState::end_frame`].
+#[derive(Debug, Default, Clone)]
+pub struct InputState {
+    keys: HashSet<VirtualKeyCode>,
+    pressed_keys: HashSet<VirtualKeyCode>,
+    mouse_buttons: HashSet<MouseButton>,
+    pressed_mouse_buttons: HashSet<MouseButton>,
+    mouse_delta: Vec2,
+    scroll_delta: f32,
+    cursor_position: Option<Vec2>,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn process_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(key) = input.virtual_keycode {
+                    match input.state {
+                        ElementState::Pressed => self.press_key(key),
+                        ElementState::Released => self.release_key(key),
+                    }
+                }
+            }
+
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => self.press_mouse_button(*button),
+                ElementState::Released => self.release_mouse_button(*button),
+            },
+
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(_, y) => self.scroll(*y),
+                MouseScrollDelta::PixelDelta(position) => {
+                    self.scroll(position.y as f32 * PIXELS_PER_LINE)
+                }
+            },
+
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = Some(Vec2::new(position.x as f32, position.y as f32));
+            }
+
+            WindowEvent::CursorLeft { .. } => self.cursor_position = None,
+
+            // Releases are never delivered to an unfocused window, so forget everything to avoid stuck keys.
+            WindowEvent::Focused(false) => self.clear(),
+
+            _ => (),
+        }
+    }
+
+    pub fn process_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.move_mouse(Vec2::new(delta.0 as f32, delta.1 as f32));
+        }
+    }
+
+    pub fn press_key(&mut self, key: VirtualKeyCode) {
+        if self.keys.insert(key) {
+            self.pressed_keys.insert(key);
+        }
+    }
+
+    pub fn release_key(&mut self, key: VirtualKeyCode) {
+        self.keys.remove(&key);
+    }
+
+    pub fn press_mouse_button(&mut self, button: MouseButton) {
+        if self.mouse_buttons.insert(button) {
+            self.pressed_mouse_buttons.insert(button);
+        }
+    }
+
+    pub fn release_mouse_button(&mut self, button: MouseButton) {
+        self.mouse_buttons.remove(&button);
+    }
+
+    pub fn move_mouse(&mut self, delta: Vec2) {
+        self.mouse_delta += delta;
+    }
+
+    pub fn scroll(&mut self, lines: f32) {
+        self.scroll_delta += lines;
+    }
+
+    /// Whether `key` is currently held down.
+    pub fn is_key_down(&self, key: VirtualKeyCode) -> bool {
+        self.keys.contains(&key)
+    }
+
+    /// Whether `key` went down since the last frame. Key repeats don't count.
+    pub fn was_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.pressed_keys.contains(&key)
+    }
+
+    pub fn is_mouse_button_down(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.contains(&button)
+    }
+
+    /// Whether `button` went down since the last frame.
+    pub fn was_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.pressed_mouse_buttons.contains(&button)
+    }
+
+    /// Raw mouse movement since the last frame.
+    pub fn mouse_delta(&self) -> Vec2 {
+        self.mouse_delta
+    }
+
+    /// Lines scrolled since the last frame, positive is away from the user.
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    /// Cursor position in physical pixels relative to the top left of the window, if the cursor is in the window.
+    pub fn cursor_position(&self) -> Option<Vec2> {
+        self.cursor_position
+    }
+
+    /// Reset the per-frame state, i.e. mouse movement, scrolling and keys and buttons pressed this frame.
+    pub fn end_frame(&mut self) {
+        self.pressed_keys.clear();
+        self.pressed_mouse_buttons.clear();
+        self.mouse_delta = Vec2::ZERO;
+        self.scroll_delta = 0.0;
+    }
+
+    /// Release every key and mouse button.
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.pressed_keys.clear();
+        self.mouse_buttons.clear();
+        self.pressed_mouse_buttons.clear();
+        self.mouse_delta = Vec2::ZERO;
+        self.scroll_delta = 0.0;
+    }
+}
@@ -1,2 +1,25 @@
+pub mod actions;
 pub mod assets;
+pub mod bookmarks;
+pub mod bounds;
 pub mod camera;
+pub mod camera_path;
+pub mod capture;
+pub mod config;
+pub mod controller;
+pub mod depth;
+pub mod frustum;
+pub mod gpu;
+pub mod input;
+pub mod msaa;
+pub mod pacing;
+pub mod picking;
+pub mod pipeline;
+pub mod procedural;
+pub mod profiler;
+pub mod recording;
+pub mod reflection;
+pub mod render_graph;
+pub mod surface;
+pub mod uniform_arena;
+pub mod viewport;
@@ -0,0 +1,171 @@
+use wgpu::{
+    Adapter, Color, Device, Extent3d, Features, MultisampleState, Operations,
+    RenderPassColorAttachment, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureFormatFeatureFlags, TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+/// Every sample count wgpu can support, in increasing order.
+pub const SAMPLE_COUNTS: [u32; 5] = [1, 2, 4, 8, 16];
+
+/// Sample count used unless configured otherwise. Every adapter supports 4x multisampling on the common formats.
+pub const DEFAULT_SAMPLE_COUNT: u32 = 4;
+
+/// The sample counts that a `color_format` target resolved into the frame, drawn with an optional `depth_format`
+/// depth buffer, supports on `device`.
+///
+/// Without [`Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`] the device is limited to the sample counts wgpu
+/// guarantees for each format, whatever else the adapter supports.
+pub fn supported_sample_counts(
+    adapter: &Adapter,
+    device: &Device,
+    color_format: TextureFormat,
+    depth_format: Option<TextureFormat>,
+) -> Vec<u32> {
+    let flags = |format: TextureFormat| {
+        if device
+            .features()
+            .contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        {
+            adapter.get_texture_format_features(format).flags
+        } else {
+            format.guaranteed_format_features(device.features()).flags
+        }
+    };
+    let color_flags = flags(color_format);
+    let depth_flags = depth_format.map(flags);
+
+    SAMPLE_COUNTS
+        .into_iter()
+        .filter(|count| {
+            *count == 1
+                || (color_flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE)
+                    && color_flags.sample_count_supported(*count)
+                    && depth_flags
+                        .iter()
+                        .all(|flags| flags.sample_count_supported(*count)))
+        })
+        .collect()
+}
+
+/// The highest of `supported` that's no more than `requested`, or 1, which is always supported.
+pub fn choose_sample_count(requested: u32, supported: &[u32]) -> u32 {
+    supported
+        .iter()
+        .copied()
+        .filter(|count| *count <= requested)
+        .max()
+        .unwrap_or(1)
+}
+
+/// A multisampled color target that resolves into the frame being drawn, or nothing but the frame itself when the
+/// sample count is 1.
+pub struct MultisampleTarget {
+    format: TextureFormat,
+    sample_count: u32,
+    texture: Option<(Texture, TextureView)>,
+}
+
+impl MultisampleTarget {
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> Self {
+        let mut target = Self {
+            format,
+            sample_count,
+            texture: None,
+        };
+        target.resize(device, width, height);
+
+        target
+    }
+
+    pub fn get_sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Recreate the target at a new size, e.g. after the surface is resized.
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.texture = (self.sample_count > 1).then(|| {
+            let texture = device.create_texture(&TextureDescriptor {
+                label: Some("texture::multisample"),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: self.sample_count,
+                dimension: TextureDimension::D2,
+                format: self.format,
+                usage: TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            });
+            let texture_view = texture.create_view(&TextureViewDescriptor::default());
+
+            (texture, texture_view)
+        });
+    }
+
+    /// The multisample state for pipelines drawing to this target.
+    pub fn multisample_state(&self) -> MultisampleState {
+        MultisampleState {
+            count: self.sample_count,
+            ..Default::default()
+        }
+    }
+
+    /// A color attachment that draws into the target and resolves into `output_view`, or draws straight into
+    /// `output_view` if it isn't multisampled. The samples themselves are discarded once resolved.
+    pub fn color_attachment<'a>(
+        &'a self,
+        output_view: &'a TextureView,
+        ops: Operations<Color>,
+    ) -> RenderPassColorAttachment<'a> {
+        match &self.texture {
+            Some((_, view)) => RenderPassColorAttachment {
+                view,
+                resolve_target: Some(output_view),
+                ops: Operations {
+                    store: false,
+                    ..ops
+                },
+            },
+            None => RenderPassColorAttachment {
+                view: output_view,
+                resolve_target: None,
+                ops,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_highest_supported_count_up_to_the_request_is_chosen() {
+        let supported = [1, 2, 4, 8];
+
+        assert_eq!(choose_sample_count(4, &supported), 4);
+        assert_eq!(choose_sample_count(16, &supported), 8);
+        assert_eq!(choose_sample_count(1, &supported), 1);
+    }
+
+    #[test]
+    fn requests_between_supported_counts_round_down() {
+        assert_eq!(choose_sample_count(8, &[1, 4, 16]), 4);
+        assert_eq!(choose_sample_count(3, &[1, 2, 4]), 2);
+    }
+
+    #[test]
+    fn one_sample_is_chosen_when_nothing_else_fits() {
+        assert_eq!(choose_sample_count(4, &[1]), 1);
+        assert_eq!(choose_sample_count(0, &[1, 4]), 1);
+        assert_eq!(choose_sample_count(4, &[]), 1);
+    }
+}
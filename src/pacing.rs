@@ -0,0 +1,252 @@
+use std::{
+    hint, thread,
+    time::{Duration, Instant},
+};
+
+use wgpu::PresentMode;
+
+/// The present modes cycled through by [`next_present_mode`], in order.
+pub const PRESENT_MODES: [PresentMode; 4] = [
+    PresentMode::Fifo,
+    PresentMode::FifoRelaxed,
+    PresentMode::Mailbox,
+    PresentMode::Immediate,
+];
+
+/// Frame rate the limiter is switched to when no target has been configured.
+pub const DEFAULT_TARGET_FPS: f32 = 60.0;
+
+/// How long before a frame is due the limiter stops sleeping and starts spinning. Sleeps can overshoot by a
+/// millisecond or more depending on the OS scheduler, so the last stretch is waited out by spinning.
+pub const DEFAULT_SPIN_THRESHOLD: Duration = Duration::from_millis(2);
+
+/// The present mode after `current` in [`PRESENT_MODES`] that's in `supported`, wrapping around, or `current` if
+/// no other mode is supported. A `current` mode outside the cycle is followed by the first supported mode in it.
+pub fn next_present_mode(current: PresentMode, supported: &[PresentMode]) -> PresentMode {
+    let start = PRESENT_MODES
+        .iter()
+        .position(|mode| *mode == current)
+        .unwrap_or(PRESENT_MODES.len() - 1);
+
+    (1..=PRESENT_MODES.len())
+        .map(|offset| PRESENT_MODES[(start + offset) % PRESENT_MODES.len()])
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(current)
+}
+
+/// Caps the frame rate on the CPU by waiting before each frame, independently of the present mode.
+#[derive(Debug, Clone)]
+pub struct FrameLimiter {
+    frame_time: Option<Duration>,
+    spin_threshold: Duration,
+    deadline: Option<Instant>,
+}
+
+impl FrameLimiter {
+    /// A limiter for `target_fps` frames per second, or one that never waits if that's `None`.
+    pub fn new(target_fps: Option<f32>) -> Self {
+        let mut limiter = Self {
+            frame_time: None,
+            spin_threshold: DEFAULT_SPIN_THRESHOLD,
+            deadline: None,
+        };
+        limiter.set_target_fps(target_fps);
+
+        limiter
+    }
+
+    pub fn get_target_fps(&self) -> Option<f32> {
+        self.frame_time
+            .map(|frame_time| 1.0 / frame_time.as_secs_f32())
+    }
+
+    /// Change the target frame rate, where `None`, zero or a negative rate means uncapped.
+    pub fn set_target_fps(&mut self, target_fps: Option<f32>) {
+        self.frame_time = target_fps
+            .filter(|fps| fps.is_finite() && *fps > 0.0)
+            .map(|fps| Duration::from_secs_f32(1.0 / fps));
+        self.deadline = None;
+    }
+
+    pub fn set_spin_threshold(&mut self, spin_threshold: Duration) {
+        self.spin_threshold = spin_threshold;
+    }
+
+    /// When the frame starting at `now` is due, or `None` if uncapped, and schedule the frame after it.
+    ///
+    /// Frames keep to a steady cadence rather than each waiting a whole frame time, so a frame that runs a little
+    /// long is made up for by a shorter wait next time. A frame more than a whole frame late starts the cadence afresh
+    /// instead of rushing through frames to catch up.
+    pub fn next_deadline(&mut self, now: Instant) -> Option<Instant> {
+        let frame_time = self.frame_time?;
+
+        let deadline = match self.deadline {
+            Some(deadline) if deadline + frame_time >= now => deadline,
+            _ => now,
+        };
+        self.deadline = Some(deadline + frame_time);
+
+        Some(deadline)
+    }
+
+    /// Wait until the next frame is due. Call once per frame, before starting on it.
+    pub fn wait(&mut self) {
+        if let Some(deadline) = self.next_deadline(Instant::now()) {
+            sleep_until(deadline, self.spin_threshold);
+        }
+    }
+}
+
+/// Sleep until `spin_threshold` before `deadline`, then spin until it.
+pub fn sleep_until(deadline: Instant, spin_threshold: Duration) {
+    let now = Instant::now();
+    if deadline > now + spin_threshold {
+        thread::sleep(deadline - now - spin_threshold);
+    }
+
+    while Instant::now() < deadline {
+        hint::spin_loop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frame time of a limiter at 4 fps, which is exact in both `f32` and [`Duration`].
+    const FRAME_TIME: Duration = Duration::from_millis(250);
+
+    fn limiter() -> FrameLimiter {
+        FrameLimiter::new(Some(4.0))
+    }
+
+    #[test]
+    fn the_first_frame_is_due_immediately() {
+        let start = Instant::now();
+
+        assert_eq!(limiter().next_deadline(start), Some(start));
+    }
+
+    #[test]
+    fn frames_on_time_keep_a_steady_cadence() {
+        let start = Instant::now();
+        let mut limiter = limiter();
+        limiter.next_deadline(start);
+
+        for frame in 1..5 {
+            let now = start + FRAME_TIME * frame - Duration::from_millis(100);
+
+            assert_eq!(limiter.next_deadline(now), Some(start + FRAME_TIME * frame));
+        }
+    }
+
+    #[test]
+    fn slightly_late_frames_are_caught_up() {
+        let start = Instant::now();
+        let mut limiter = limiter();
+        limiter.next_deadline(start);
+
+        let late = start + FRAME_TIME + Duration::from_millis(100);
+        assert_eq!(limiter.next_deadline(late), Some(start + FRAME_TIME));
+        assert_eq!(
+            limiter.next_deadline(late + Duration::from_millis(10)),
+            Some(start + FRAME_TIME * 2)
+        );
+    }
+
+    #[test]
+    fn frames_more_than_a_frame_late_start_afresh() {
+        let start = Instant::now();
+        let mut limiter = limiter();
+        limiter.next_deadline(start);
+
+        let late = start + FRAME_TIME * 2 + Duration::from_millis(100);
+        assert_eq!(limiter.next_deadline(late), Some(late));
+        assert_eq!(limiter.next_deadline(late), Some(late + FRAME_TIME));
+    }
+
+    #[test]
+    fn changing_the_target_restarts_the_cadence() {
+        let start = Instant::now();
+        let mut limiter = limiter();
+        limiter.next_deadline(start);
+
+        limiter.set_target_fps(Some(4.0));
+        let now = start + Duration::from_millis(100);
+        assert_eq!(limiter.next_deadline(now), Some(now));
+    }
+
+    #[test]
+    fn uncapped_limiters_never_wait() {
+        for target_fps in [
+            None,
+            Some(0.0),
+            Some(-30.0),
+            Some(f32::INFINITY),
+            Some(f32::NAN),
+        ] {
+            let mut limiter = FrameLimiter::new(target_fps);
+
+            assert_eq!(limiter.get_target_fps(), None);
+            assert_eq!(limiter.next_deadline(Instant::now()), None);
+        }
+    }
+
+    #[test]
+    fn present_modes_cycle_in_order_and_wrap() {
+        let all = PRESENT_MODES;
+
+        assert_eq!(
+            next_present_mode(PresentMode::Fifo, &all),
+            PresentMode::FifoRelaxed
+        );
+        assert_eq!(
+            next_present_mode(PresentMode::Immediate, &all),
+            PresentMode::Fifo
+        );
+    }
+
+    #[test]
+    fn unsupported_present_modes_are_skipped() {
+        let supported = [PresentMode::Fifo, PresentMode::Mailbox];
+
+        assert_eq!(
+            next_present_mode(PresentMode::Fifo, &supported),
+            PresentMode::Mailbox
+        );
+        assert_eq!(
+            next_present_mode(PresentMode::Mailbox, &supported),
+            PresentMode::Fifo
+        );
+        assert_eq!(
+            next_present_mode(PresentMode::Fifo, &[PresentMode::Fifo]),
+            PresentMode::Fifo
+        );
+    }
+
+    #[test]
+    fn modes_outside_the_cycle_move_onto_it() {
+        let supported = [
+            PresentMode::AutoVsync,
+            PresentMode::Fifo,
+            PresentMode::Mailbox,
+        ];
+
+        assert_eq!(
+            next_present_mode(PresentMode::AutoVsync, &supported),
+            PresentMode::Fifo
+        );
+        assert_eq!(
+            next_present_mode(PresentMode::AutoVsync, &supported[1..]),
+            PresentMode::Fifo
+        );
+        assert_eq!(
+            next_present_mode(PresentMode::AutoVsync, &[PresentMode::Immediate]),
+            PresentMode::Immediate
+        );
+        assert_eq!(
+            next_present_mode(PresentMode::AutoVsync, &[PresentMode::AutoVsync]),
+            PresentMode::AutoVsync
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use glam::{Mat4, Vec3};
+
+use crate::bounds::Aabb;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshHit {
+    /// Distance along the ray, in multiples of the ray direction.
+    pub distance: f32,
+    /// Index of the hit triangle, i.e. the triangle made from `indices[3 * triangle..3 * triangle + 3]`.
+    pub triangle: usize,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, distance: f32) -> Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    /// The ray in another space, e.g. pass the inverse model matrix to test against a mesh in model space. The
+    /// direction isn't renormalized so that hit distances stay comparable with the untransformed ray.
+    pub fn transform(&self, matrix: Mat4) -> Self {
+        Self {
+            origin: matrix.transform_point3(self.origin),
+            direction: matrix.transform_vector3(self.direction),
+        }
+    }
+
+    /// Distance to where the ray enters `aabb` using the slab method, or 0 if the ray starts inside it.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let inverse_direction = self.direction.recip();
+        let t1 = (aabb.min - self.origin) * inverse_direction;
+        let t2 = (aabb.max - self.origin) * inverse_direction;
+
+        // NaNs from 0 * infinity, when the origin lies on a slab boundary, are dropped by min/max.
+        let t_enter = t1.min(t2).max_element();
+        let t_exit = t1.max(t2).min_element();
+
+        if t_exit >= t_enter.max(0.0) {
+            Some(t_enter.max(0.0))
+        } else {
+            None
+        }
+    }
+
+    /// Distance to the triangle `a`, `b`, `c` using the Möller-Trumbore algorithm. Both sides of the triangle are hit.
+    pub fn intersect_triangle(&self, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+        let edge_1 = b - a;
+        let edge_2 = c - a;
+        let p = self.direction.cross(edge_2);
+        let determinant = edge_1.dot(p);
+
+        if determinant.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let inverse_determinant = 1.0 / determinant;
+        let s = self.origin - a;
+        let u = s.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(edge_1);
+        let v = self.direction.dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let distance = edge_2.dot(q) * inverse_determinant;
+        (distance >= 0.0).then_some(distance)
+    }
+
+    /// The nearest triangle of an indexed triangle list hit by the ray.
+    pub fn intersect_mesh(&self, positions: &[Vec3], indices: &[u32]) -> Option<MeshHit> {
+        indices
+            .chunks_exact(3)
+            .enumerate()
+            .filter_map(|(triangle, indices)| {
+                let a = positions[indices[0] as usize];
+                let b = positions[indices[1] as usize];
+                let c = positions[indices[2] as usize];
+                self.intersect_triangle(a, b, c)
+                    .map(|distance| MeshHit { distance, triangle })
+            })
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+    use crate::{
+        camera::{Camera, CameraDescriptor, DEFAULT_FOV_Y},
+        depth::DepthMode,
+        viewport::Viewport,
+    };
+
+    const EPSILON: f32 = 1e-4;
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Vec3::splat(-1.0), Vec3::splat(1.0))
+    }
+
+    fn triangle() -> [Vec3; 3] {
+        [
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ]
+    }
+
+    fn assert_near(actual: Option<f32>, expected: f32) {
+        let actual = actual.expect("expected a hit");
+        assert!(
+            (actual - expected).abs() < EPSILON,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    fn assert_vec_near(actual: Vec3, expected: Vec3) {
+        assert!(
+            actual.abs_diff_eq(expected, EPSILON),
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn ray_hits_box() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::Z);
+        assert_near(ray.intersect_aabb(&unit_box()), 4.0);
+
+        let diagonal = Ray::new(Vec3::splat(-5.0), Vec3::ONE);
+        assert_near(diagonal.intersect_aabb(&unit_box()), 4.0 * 3.0_f32.sqrt());
+    }
+
+    #[test]
+    fn ray_misses_box() {
+        let beside = Ray::new(Vec3::new(3.0, 0.0, -5.0), Vec3::Z);
+        assert_eq!(beside.intersect_aabb(&unit_box()), None);
+
+        let away = Ray::new(Vec3::new(0.0, 0.0, -5.0), -Vec3::Z);
+        assert_eq!(away.intersect_aabb(&unit_box()), None);
+
+        let past_corner = Ray::new(Vec3::new(-5.0, 0.0, -2.0), Vec3::new(1.0, 0.0, 1.0));
+        assert_eq!(past_corner.intersect_aabb(&unit_box()), None);
+    }
+
+    #[test]
+    fn ray_starting_inside_box_hits_at_zero() {
+        let ray = Ray::new(Vec3::new(0.5, -0.5, 0.0), Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(ray.intersect_aabb(&unit_box()), Some(0.0));
+    }
+
+    #[test]
+    fn axis_parallel_rays() {
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            let inside_slabs = Ray::new(-5.0 * axis + Vec3::splat(0.5) * (Vec3::ONE - axis), axis);
+            assert_near(inside_slabs.intersect_aabb(&unit_box()), 4.0);
+
+            let outside_slabs = Ray::new(-5.0 * axis + 2.0 * (Vec3::ONE - axis), axis);
+            assert_eq!(outside_slabs.intersect_aabb(&unit_box()), None);
+        }
+    }
+
+    #[test]
+    fn ray_hits_triangle_from_either_side() {
+        let [a, b, c] = triangle();
+
+        let front = Ray::new(Vec3::new(0.0, 0.0, 5.0), -Vec3::Z);
+        assert_near(front.intersect_triangle(a, b, c), 5.0);
+
+        let back = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::Z);
+        assert_near(back.intersect_triangle(a, b, c), 5.0);
+    }
+
+    #[test]
+    fn ray_hits_triangle_edges_and_corners() {
+        let [a, b, c] = triangle();
+
+        let edge = Ray::new(Vec3::new(0.0, -1.0, 5.0), -Vec3::Z);
+        assert_near(edge.intersect_triangle(a, b, c), 5.0);
+
+        let corner = Ray::new(c + Vec3::Z * 5.0, -Vec3::Z);
+        assert_near(corner.intersect_triangle(a, b, c), 5.0);
+
+        let outside_edge = Ray::new(Vec3::new(0.0, -1.01, 5.0), -Vec3::Z);
+        assert_eq!(outside_edge.intersect_triangle(a, b, c), None);
+    }
+
+    #[test]
+    fn ray_misses_triangle() {
+        let [a, b, c] = triangle();
+
+        let beside = Ray::new(Vec3::new(2.0, 2.0, 5.0), -Vec3::Z);
+        assert_eq!(beside.intersect_triangle(a, b, c), None);
+
+        let behind = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::Z);
+        assert_eq!(behind.intersect_triangle(a, b, c), None);
+
+        let parallel = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::X);
+        assert_eq!(parallel.intersect_triangle(a, b, c), None);
+    }
+
+    #[test]
+    fn mesh_hit_is_nearest_triangle() {
+        let [a, b, c] = triangle();
+        let offset = Vec3::new(0.0, 0.0, 2.0);
+        let positions = [a, b, c, a + offset, b + offset, c + offset];
+        let indices = [0, 1, 2, 3, 4, 5];
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), -Vec3::Z);
+        let hit = ray.intersect_mesh(&positions, &indices).unwrap();
+        assert_eq!(hit.triangle, 1);
+        assert_near(Some(hit.distance), 3.0);
+
+        let reversed = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::Z);
+        assert_eq!(
+            reversed
+                .intersect_mesh(&positions, &indices)
+                .unwrap()
+                .triangle,
+            0
+        );
+
+        let miss = Ray::new(Vec3::new(5.0, 0.0, 5.0), -Vec3::Z);
+        assert_eq!(miss.intersect_mesh(&positions, &indices), None);
+    }
+
+    #[test]
+    fn transformed_ray_keeps_world_distances() {
+        let [a, b, c] = triangle();
+        let model = Mat4::from_translation(Vec3::new(1.0, 0.0, 0.0))
+            * Mat4::from_scale(Vec3::new(2.0, 3.0, 2.0))
+            * Mat4::from_translation(Vec3::new(0.0, 0.0, 1.0));
+
+        let ray = Ray::new(Vec3::new(1.0, 0.0, 10.0), -Vec3::Z);
+        let local = ray.transform(model.inverse());
+        assert_vec_near(local.origin, Vec3::new(0.0, 0.0, 4.0));
+        assert_vec_near(local.direction, Vec3::new(0.0, 0.0, -0.5));
+
+        // The triangle is at z = 0 in model space and z = 2 in world space.
+        let distance = local.intersect_triangle(a, b, c);
+        assert_near(distance, 8.0);
+        assert_vec_near(ray.at(distance.unwrap()), Vec3::new(1.0, 0.0, 2.0));
+
+        // The box's front face is at z = 1 in model space and z = 4 in world space.
+        assert_near(local.intersect_aabb(&unit_box()), 6.0);
+    }
+
+    #[test]
+    fn screen_rays_through_centre_and_corner() {
+        let viewport = Viewport::from_size(1600, 900);
+        let tan_half_fov = (DEFAULT_FOV_Y.to_radians() * 0.5).tan();
+        let aspect_ratio = 1600.0 / 900.0;
+
+        for depth_mode in [DepthMode::Standard, DepthMode::ReverseInfinite] {
+            let desc = CameraDescriptor {
+                depth_mode,
+                ..Default::default()
+            };
+            let mut camera = Camera::new(&desc);
+            camera.set_viewport(&viewport);
+
+            let centre = camera.screen_to_ray(Vec2::new(800.0, 450.0), &viewport);
+            assert_vec_near(centre.origin, Vec3::new(0.0, 0.0, 3.0 - desc.z_near));
+            assert_vec_near(centre.direction, -Vec3::Z);
+
+            let corner = camera.screen_to_ray(Vec2::ZERO, &viewport);
+            let expected = Vec3::new(-tan_half_fov * aspect_ratio, tan_half_fov, -1.0);
+            assert_vec_near(corner.direction, expected.normalize());
+            assert_vec_near(corner.origin, desc.position + expected * desc.z_near);
+        }
+    }
+
+    #[test]
+    fn screen_rays_in_sub_viewport() {
+        let viewport = Viewport::from_size(1600, 900).sub_viewport(0.5, 0.0, 0.5, 1.0);
+        let mut camera = Camera::new(&CameraDescriptor::default());
+        camera.set_viewport(&viewport);
+
+        let centre = camera.screen_to_ray(Vec2::new(1200.0, 450.0), &viewport);
+        assert_vec_near(centre.direction, -Vec3::Z);
+    }
+}
@@ -0,0 +1,474 @@
+use std::{collections::HashMap, fmt, hash::Hash, sync::Arc};
+
+use wgpu::{
+    BlendState, ColorTargetState, ColorWrites, DepthStencilState, Device, Face, Features,
+    FragmentState, FrontFace, MultisampleState, PipelineLayout, PolygonMode, PrimitiveState,
+    PrimitiveTopology, RenderPipeline, RenderPipelineDescriptor, ShaderModule, StencilState,
+    TextureFormat, VertexBufferLayout, VertexState,
+};
+
+use crate::depth::DepthMode;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipelineError {
+    /// The device doesn't have features the pipeline needs, e.g. [`Features::POLYGON_MODE_LINE`] for wireframes.
+    MissingFeatures(Features),
+    /// The color format can't be multisampled with this many samples.
+    UnsupportedSampleCount { format: TextureFormat, count: u32 },
+    /// Stencil testing needs a depth format with a stencil aspect.
+    NoStencilAspect(TextureFormat),
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::MissingFeatures(features) => {
+                write!(f, "the device is missing features {:?}", features)
+            }
+            PipelineError::UnsupportedSampleCount { format, count } => {
+                write!(
+                    f,
+                    "{:?} doesn't support {} samples per pixel",
+                    format, count
+                )
+            }
+            PipelineError::NoStencilAspect(format) => {
+                write!(f, "{:?} has no stencil to test against", format)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+/// Everything about a pipeline except its shader and layout, which is what tells cached pipelines apart.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PipelineState {
+    vertex_entry_point: String,
+    fragment_entry_point: String,
+    color_format: TextureFormat,
+    blend: Option<BlendState>,
+    write_mask: ColorWrites,
+    primitive: PrimitiveState,
+    depth_stencil: Option<DepthStencilState>,
+    sample_count: u32,
+}
+
+impl PipelineState {
+    fn new(color_format: TextureFormat) -> Self {
+        Self {
+            vertex_entry_point: String::from("vs_main"),
+            fragment_entry_point: String::from("fs_main"),
+            color_format,
+            blend: None,
+            write_mask: ColorWrites::ALL,
+            primitive: PrimitiveState {
+                front_face: FrontFace::Ccw,
+                cull_mode: Some(Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(DepthMode::Standard.depth_stencil_state()),
+            sample_count: 1,
+        }
+    }
+
+    /// Check the pipeline can be built on a device with `features`.
+    fn validate(&self, features: Features) -> Result<(), PipelineError> {
+        let mut required_features = self.color_format.required_features();
+        match self.primitive.polygon_mode {
+            PolygonMode::Fill => (),
+            PolygonMode::Line => required_features |= Features::POLYGON_MODE_LINE,
+            PolygonMode::Point => required_features |= Features::POLYGON_MODE_POINT,
+        }
+        if let Some(depth_stencil) = &self.depth_stencil {
+            required_features |= depth_stencil.format.required_features();
+        }
+        let missing_features = required_features - features;
+        if !missing_features.is_empty() {
+            return Err(PipelineError::MissingFeatures(missing_features));
+        }
+
+        // With adapter specific format features the device may support more than is guaranteed, but there's no way to
+        // ask it without the adapter, so wgpu is left to check.
+        let guaranteed_flags = self.color_format.guaranteed_format_features(features).flags;
+        if !features.contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+            && !guaranteed_flags.sample_count_supported(self.sample_count)
+        {
+            return Err(PipelineError::UnsupportedSampleCount {
+                format: self.color_format,
+                count: self.sample_count,
+            });
+        }
+
+        match &self.depth_stencil {
+            Some(depth_stencil)
+                if depth_stencil.stencil.is_enabled()
+                    && !depth_stencil.format.has_stencil_aspect() =>
+            {
+                Err(PipelineError::NoStencilAspect(depth_stencil.format))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Builds render pipelines with the settings the samples share, so each pipeline only spells out what's different
+/// about it.
+///
+/// By default pipelines draw triangle lists from the shader's `vs_main` and `fs_main` into a single color target
+/// without blending, cull back faces, test depth in [`DEPTH_FORMAT`](crate::depth::DEPTH_FORMAT) with
+/// [`DepthMode::Standard`] and aren't multisampled.
+#[derive(Debug, Clone)]
+pub struct PipelineBuilder<'a> {
+    label: Option<&'a str>,
+    layout: Option<&'a PipelineLayout>,
+    shader: &'a ShaderModule,
+    vertex_buffers: Vec<VertexBufferLayout<'a>>,
+    state: PipelineState,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    /// A pipeline drawing with `shader` into `color_format` targets, usually the surface format.
+    pub fn new(shader: &'a ShaderModule, color_format: TextureFormat) -> Self {
+        Self {
+            label: None,
+            layout: None,
+            shader,
+            vertex_buffers: Vec::new(),
+            state: PipelineState::new(color_format),
+        }
+    }
+
+    pub fn label(mut self, label: &'a str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    /// The pipeline layout, or wgpu derives one from the shader if there isn't one.
+    pub fn layout(mut self, layout: &'a PipelineLayout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// Add a vertex buffer, bound to the next slot.
+    pub fn vertex_buffer(mut self, layout: VertexBufferLayout<'a>) -> Self {
+        self.vertex_buffers.push(layout);
+        self
+    }
+
+    pub fn vertex_entry_point(mut self, entry_point: &str) -> Self {
+        self.state.vertex_entry_point = entry_point.to_string();
+        self
+    }
+
+    pub fn fragment_entry_point(mut self, entry_point: &str) -> Self {
+        self.state.fragment_entry_point = entry_point.to_string();
+        self
+    }
+
+    pub fn blend(mut self, blend: BlendState) -> Self {
+        self.state.blend = Some(blend);
+        self
+    }
+
+    pub fn write_mask(mut self, write_mask: ColorWrites) -> Self {
+        self.state.write_mask = write_mask;
+        self
+    }
+
+    pub fn topology(mut self, topology: PrimitiveTopology) -> Self {
+        self.state.primitive.topology = topology;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: FrontFace) -> Self {
+        self.state.primitive.front_face = front_face;
+        self
+    }
+
+    /// The faces to cull, or `None` to draw both sides.
+    pub fn cull_mode(mut self, cull_mode: Option<Face>) -> Self {
+        self.state.primitive.cull_mode = cull_mode;
+        self
+    }
+
+    /// Lines and points need [`Features::POLYGON_MODE_LINE`] and [`Features::POLYGON_MODE_POINT`].
+    pub fn polygon_mode(mut self, polygon_mode: PolygonMode) -> Self {
+        self.state.primitive.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// Test depth the way `depth_mode` needs, keeping any stencil state.
+    pub fn depth_mode(mut self, depth_mode: DepthMode) -> Self {
+        let stencil = self.get_stencil();
+        self.state.depth_stencil = Some(DepthStencilState {
+            stencil,
+            ..depth_mode.depth_stencil_state()
+        });
+        self
+    }
+
+    /// Replace the depth and stencil state, or remove it with `None` for pipelines drawn without a depth buffer.
+    pub fn depth_stencil(mut self, depth_stencil: Option<DepthStencilState>) -> Self {
+        self.state.depth_stencil = depth_stencil;
+        self
+    }
+
+    /// Whether depth is written as well as tested. Does nothing without a depth buffer.
+    pub fn depth_write_enabled(mut self, enabled: bool) -> Self {
+        if let Some(depth_stencil) = &mut self.state.depth_stencil {
+            depth_stencil.depth_write_enabled = enabled;
+        }
+        self
+    }
+
+    /// The stencil test, which needs a depth format with a stencil aspect. Does nothing without a depth buffer.
+    pub fn stencil(mut self, stencil: StencilState) -> Self {
+        if let Some(depth_stencil) = &mut self.state.depth_stencil {
+            depth_stencil.stencil = stencil;
+        }
+        self
+    }
+
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.state.sample_count = sample_count;
+        self
+    }
+
+    fn get_stencil(&self) -> StencilState {
+        self.state
+            .depth_stencil
+            .as_ref()
+            .map(|depth_stencil| depth_stencil.stencil.clone())
+            .unwrap_or_default()
+    }
+
+    /// Check the pipeline can be built on `device`, which would otherwise panic in wgpu.
+    pub fn validate(&self, device: &Device) -> Result<(), PipelineError> {
+        self.state.validate(device.features())
+    }
+
+    pub fn build(&self, device: &Device) -> Result<RenderPipeline, PipelineError> {
+        self.validate(device)?;
+
+        let state = &self.state;
+        Ok(device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: self.label,
+            layout: self.layout,
+            vertex: VertexState {
+                module: self.shader,
+                entry_point: &state.vertex_entry_point,
+                buffers: &self.vertex_buffers,
+            },
+            primitive: state.primitive,
+            depth_stencil: state.depth_stencil.clone(),
+            multisample: MultisampleState {
+                count: state.sample_count,
+                ..Default::default()
+            },
+            fragment: Some(FragmentState {
+                module: self.shader,
+                entry_point: &state.fragment_entry_point,
+                targets: &[Some(ColorTargetState {
+                    format: state.color_format,
+                    blend: state.blend,
+                    write_mask: state.write_mask,
+                })],
+            }),
+            multiview: None,
+        }))
+    }
+}
+
+/// Pipelines built on one device, so asking for the same pipeline again doesn't build it again.
+///
+/// The cache doesn't know which device its pipelines belong to and hands them out whatever device it's given, so it
+/// must only ever be used with one device and be dropped, or [cleared](PipelineCache::clear), along with it. The
+/// simplest way is to keep it next to everything else made with the device, e.g. in the renderer that's recreated when
+/// the device is lost.
+///
+/// Pipelines are looked up by a key for their shader and layout, e.g. an enum of a sample's pipelines, together with
+/// the rest of their settings, so a pipeline asked for with a different sample count or format is built afresh.
+#[derive(Debug)]
+pub struct PipelineCache<K> {
+    pipelines: HashMap<(K, PipelineState), Arc<RenderPipeline>>,
+}
+
+impl<K> Default for PipelineCache<K> {
+    fn default() -> Self {
+        Self {
+            pipelines: HashMap::new(),
+        }
+    }
+}
+
+impl<K> PipelineCache<K>
+where
+    K: Hash + Eq,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The pipeline `builder` describes for `key`, built the first time it's asked for.
+    pub fn get_or_build(
+        &mut self,
+        device: &Device,
+        key: K,
+        builder: &PipelineBuilder,
+    ) -> Result<Arc<RenderPipeline>, PipelineError> {
+        let key = (key, builder.state.clone());
+        if let Some(pipeline) = self.pipelines.get(&key) {
+            return Ok(Arc::clone(pipeline));
+        }
+
+        let pipeline = Arc::new(builder.build(device)?);
+        self.pipelines.insert(key, Arc::clone(&pipeline));
+
+        Ok(pipeline)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.pipelines.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use wgpu::{CompareFunction, StencilFaceState};
+
+    use super::*;
+
+    const COLOR_FORMAT: TextureFormat = TextureFormat::Bgra8UnormSrgb;
+
+    /// A stencil test that passes where the stencil buffer equals the reference.
+    fn stencil_test() -> StencilState {
+        let face = StencilFaceState {
+            compare: CompareFunction::Equal,
+            ..Default::default()
+        };
+
+        StencilState {
+            front: face,
+            back: face,
+            read_mask: 0xff,
+            write_mask: 0,
+        }
+    }
+
+    #[test]
+    fn the_default_state_needs_no_features() {
+        assert_eq!(
+            PipelineState::new(COLOR_FORMAT).validate(Features::empty()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn wireframes_need_the_line_polygon_mode() {
+        let mut state = PipelineState::new(COLOR_FORMAT);
+        state.primitive.polygon_mode = PolygonMode::Line;
+
+        assert_eq!(
+            state.validate(Features::empty()),
+            Err(PipelineError::MissingFeatures(Features::POLYGON_MODE_LINE))
+        );
+        assert_eq!(state.validate(Features::POLYGON_MODE_LINE), Ok(()));
+    }
+
+    #[test]
+    fn points_need_the_point_polygon_mode() {
+        let mut state = PipelineState::new(COLOR_FORMAT);
+        state.primitive.polygon_mode = PolygonMode::Point;
+
+        assert_eq!(
+            state.validate(Features::POLYGON_MODE_LINE),
+            Err(PipelineError::MissingFeatures(Features::POLYGON_MODE_POINT))
+        );
+    }
+
+    #[test]
+    fn stencil_tests_need_a_stencil_aspect() {
+        let mut state = PipelineState::new(COLOR_FORMAT);
+        if let Some(depth_stencil) = &mut state.depth_stencil {
+            depth_stencil.stencil = stencil_test();
+        }
+
+        assert_eq!(
+            state.validate(Features::empty()),
+            Err(PipelineError::NoStencilAspect(TextureFormat::Depth32Float))
+        );
+
+        if let Some(depth_stencil) = &mut state.depth_stencil {
+            depth_stencil.format = TextureFormat::Depth24PlusStencil8;
+        }
+        assert_eq!(state.validate(Features::empty()), Ok(()));
+    }
+
+    #[test]
+    fn sample_counts_are_checked_against_the_guaranteed_ones() {
+        let mut state = PipelineState::new(COLOR_FORMAT);
+
+        state.sample_count = 4;
+        assert_eq!(state.validate(Features::empty()), Ok(()));
+
+        state.sample_count = 3;
+        assert_eq!(
+            state.validate(Features::empty()),
+            Err(PipelineError::UnsupportedSampleCount {
+                format: COLOR_FORMAT,
+                count: 3
+            })
+        );
+        assert_eq!(
+            state.validate(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn cache_keys_tell_apart_every_setting() {
+        let state = PipelineState::new(COLOR_FORMAT);
+        let multisampled = PipelineState {
+            sample_count: 4,
+            ..state.clone()
+        };
+        let other_format = PipelineState::new(TextureFormat::Rgba8UnormSrgb);
+        let wireframe = PipelineState {
+            primitive: PrimitiveState {
+                polygon_mode: PolygonMode::Line,
+                ..state.primitive
+            },
+            ..state.clone()
+        };
+
+        let keys = HashSet::from([
+            ("model", state.clone()),
+            ("model", multisampled),
+            ("model", other_format),
+            ("model", wireframe),
+            ("light", state.clone()),
+        ]);
+        assert_eq!(keys.len(), 5);
+        assert!(keys.contains(&("model", PipelineState::new(COLOR_FORMAT))));
+    }
+
+    #[test]
+    fn new_caches_are_empty() {
+        let mut cache = PipelineCache::<&str>::new();
+        assert!(cache.is_empty());
+
+        cache.clear();
+        assert_eq!(cache.len(), 0);
+    }
+}
@@ -0,0 +1,374 @@
+use image::{Rgba, RgbaImage};
+
+pub const MAGENTA: Rgba<u8> = Rgba([255, 0, 255, 255]);
+pub const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+pub const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+const MISSING_TEXTURE_SIZE: u32 = 64;
+const MISSING_TEXTURE_CELL_SIZE: u32 = 8;
+
+/// The texture used in place of an image that couldn't be found or decoded.
+/// It's a magenta and black checkerboard so that it stands out in any scene.
+pub fn missing_texture() -> RgbaImage {
+    checkerboard(
+        MISSING_TEXTURE_SIZE,
+        MISSING_TEXTURE_SIZE,
+        MISSING_TEXTURE_CELL_SIZE,
+        MAGENTA,
+        BLACK,
+    )
+}
+
+/// A checkerboard of `cell_size` pixel squares, starting with `a` in the top left corner.
+pub fn checkerboard(
+    width: u32,
+    height: u32,
+    cell_size: u32,
+    a: Rgba<u8>,
+    b: Rgba<u8>,
+) -> RgbaImage {
+    let cell_size = cell_size.max(1);
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        if (x / cell_size + y / cell_size) & 1 == 0 {
+            a
+        } else {
+            b
+        }
+    })
+}
+
+/// A grid of `cells` x `cells` squares with the u coordinate in the red channel and the v coordinate in the green
+/// channel. Each cell is outlined and labelled with its column letter and row number, e.g. `A0` in the top left and
+/// `J9` in the bottom right of a 10 x 10 grid, which makes it easy to spot flipped or stretched texture coordinates.
+pub fn uv_grid(width: u32, height: u32, cells: u32) -> RgbaImage {
+    let cells = cells.clamp(1, 26);
+    let cell_width = (width / cells).max(1);
+    let cell_height = (height / cells).max(1);
+
+    let mut image = RgbaImage::from_fn(width, height, |x, y| {
+        let u = x as f32 / width.saturating_sub(1).max(1) as f32;
+        let v = y as f32 / height.saturating_sub(1).max(1) as f32;
+
+        if x % cell_width == 0 || y % cell_height == 0 {
+            WHITE
+        } else {
+            Rgba([(u * 255.0) as u8, (v * 255.0) as u8, 64, 255])
+        }
+    });
+
+    let scale = (cell_width.min(cell_height) / 16).max(1);
+    for row in 0..cells {
+        for column in 0..cells {
+            let label = format!("{}{}", (b'A' + column as u8) as char, row);
+            let x = column * cell_width + 2 * scale;
+            let y = row * cell_height + 2 * scale;
+            draw_text(&mut image, x, y, scale, &label, WHITE);
+        }
+    }
+
+    image
+}
+
+/// Grayscale value noise, i.e. bilinearly interpolated random values on a lattice with one point every `scale`
+/// pixels. The same `seed` always produces the same image.
+pub fn value_noise(width: u32, height: u32, scale: f32, seed: u32) -> RgbaImage {
+    let scale = scale.max(1.0);
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let value = sample_value_noise(x as f32 / scale, y as f32 / scale, seed);
+        gray(value)
+    })
+}
+
+/// Grayscale Perlin (gradient) noise with one lattice cell every `scale` pixels. The same `seed` always produces the
+/// same image.
+pub fn perlin_noise(width: u32, height: u32, scale: f32, seed: u32) -> RgbaImage {
+    let scale = scale.max(1.0);
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let value = sample_perlin_noise(x as f32 / scale, y as f32 / scale, seed);
+        gray(value * 0.5 + 0.5)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GradientDirection {
+    Horizontal,
+    Vertical,
+    Diagonal,
+}
+
+/// A linear gradient from `from` to `to` in the given `direction`.
+pub fn linear_gradient(
+    width: u32,
+    height: u32,
+    from: Rgba<u8>,
+    to: Rgba<u8>,
+    direction: GradientDirection,
+) -> RgbaImage {
+    let max_x = width.saturating_sub(1).max(1) as f32;
+    let max_y = height.saturating_sub(1).max(1) as f32;
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let t = match direction {
+            GradientDirection::Horizontal => x as f32 / max_x,
+            GradientDirection::Vertical => y as f32 / max_y,
+            GradientDirection::Diagonal => (x as f32 / max_x + y as f32 / max_y) * 0.5,
+        };
+
+        lerp_color(from, to, t)
+    })
+}
+
+/// A tangent space normal map where every normal points straight out of the surface, i.e. `(0, 0, 1)`.
+pub fn flat_normal_map(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_pixel(width, height, Rgba([128, 128, 255, 255]))
+}
+
+/// Sample value noise in the range `[0, 1]` at the given lattice coordinates.
+pub fn sample_value_noise(x: f32, y: f32, seed: u32) -> f32 {
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let tx = smoothstep(x - x0);
+    let ty = smoothstep(y - y0);
+    let (xi, yi) = (x0 as i32, y0 as i32);
+
+    let v00 = lattice_value(xi, yi, seed);
+    let v10 = lattice_value(xi + 1, yi, seed);
+    let v01 = lattice_value(xi, yi + 1, seed);
+    let v11 = lattice_value(xi + 1, yi + 1, seed);
+
+    lerp(lerp(v00, v10, tx), lerp(v01, v11, tx), ty)
+}
+
+/// Sample Perlin noise in the range `[-1, 1]` at the given lattice coordinates.
+pub fn sample_perlin_noise(x: f32, y: f32, seed: u32) -> f32 {
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let fx = x - x0;
+    let fy = y - y0;
+    let tx = fade(fx);
+    let ty = fade(fy);
+    let (xi, yi) = (x0 as i32, y0 as i32);
+
+    let g00 = lattice_gradient(xi, yi, seed, fx, fy);
+    let g10 = lattice_gradient(xi + 1, yi, seed, fx - 1.0, fy);
+    let g01 = lattice_gradient(xi, yi + 1, seed, fx, fy - 1.0);
+    let g11 = lattice_gradient(xi + 1, yi + 1, seed, fx - 1.0, fy - 1.0);
+
+    lerp(lerp(g00, g10, tx), lerp(g01, g11, tx), ty)
+}
+
+fn hash(x: i32, y: i32, seed: u32) -> u32 {
+    let mut h = seed
+        .wrapping_mul(0x9E37_79B9)
+        .wrapping_add((x as u32).wrapping_mul(0x85EB_CA6B))
+        .wrapping_add((y as u32).wrapping_mul(0xC2B2_AE35));
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7FEB_352D);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846C_A68B);
+    h ^= h >> 16;
+    h
+}
+
+fn lattice_value(x: i32, y: i32, seed: u32) -> f32 {
+    hash(x, y, seed) as f32 / u32::MAX as f32
+}
+
+fn lattice_gradient(x: i32, y: i32, seed: u32, dx: f32, dy: f32) -> f32 {
+    match hash(x, y, seed) & 7 {
+        0 => dx + dy,
+        1 => dx - dy,
+        2 => -dx + dy,
+        3 => -dx - dy,
+        4 => dx,
+        5 => -dx,
+        6 => dy,
+        _ => -dy,
+    }
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp_color(from: Rgba<u8>, to: Rgba<u8>, t: f32) -> Rgba<u8> {
+    let t = t.clamp(0.0, 1.0);
+    let mut color = [0; 4];
+    for (i, channel) in color.iter_mut().enumerate() {
+        *channel = lerp(from.0[i] as f32, to.0[i] as f32, t).round() as u8;
+    }
+
+    Rgba(color)
+}
+
+fn gray(value: f32) -> Rgba<u8> {
+    let value = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Rgba([value, value, value, 255])
+}
+
+const GLYPH_WIDTH: u32 = 3;
+
+/// 3x5 glyphs for digits and upper case letters, one row per byte with the leftmost pixel in bit 2.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        _ => [0; 5],
+    }
+}
+
+fn draw_text(image: &mut RgbaImage, x: u32, y: u32, scale: u32, text: &str, color: Rgba<u8>) {
+    for (i, c) in text.chars().enumerate() {
+        let origin_x = x + i as u32 * (GLYPH_WIDTH + 1) * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                    continue;
+                }
+
+                for sy in 0..scale {
+                    for sx in 0..scale {
+                        let px = origin_x + column * scale + sx;
+                        let py = y + row as u32 * scale + sy;
+                        if px < image.width() && py < image.height() {
+                            image.put_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples on a grid covering a few lattice cells, including points on the lattice itself.
+    fn sample_points() -> impl Iterator<Item = (f32, f32)> {
+        (0..64).flat_map(|y| (0..64).map(move |x| (x as f32 * 0.13 - 4.0, y as f32 * 0.17 - 4.0)))
+    }
+
+    #[test]
+    fn missing_texture_is_a_magenta_and_black_checkerboard() {
+        let image = missing_texture();
+
+        assert_eq!(
+            image.dimensions(),
+            (MISSING_TEXTURE_SIZE, MISSING_TEXTURE_SIZE)
+        );
+        assert_eq!(*image.get_pixel(0, 0), MAGENTA);
+        assert_eq!(*image.get_pixel(MISSING_TEXTURE_CELL_SIZE - 1, 0), MAGENTA);
+        assert_eq!(*image.get_pixel(MISSING_TEXTURE_CELL_SIZE, 0), BLACK);
+        assert_eq!(*image.get_pixel(0, MISSING_TEXTURE_CELL_SIZE), BLACK);
+        assert_eq!(
+            *image.get_pixel(MISSING_TEXTURE_CELL_SIZE, MISSING_TEXTURE_CELL_SIZE),
+            MAGENTA
+        );
+    }
+
+    #[test]
+    fn checkerboard_is_deterministic() {
+        assert_eq!(
+            checkerboard(32, 16, 4, WHITE, BLACK),
+            checkerboard(32, 16, 4, WHITE, BLACK)
+        );
+    }
+
+    #[test]
+    fn checkerboard_with_zero_cell_size_alternates_every_pixel() {
+        let image = checkerboard(2, 2, 0, WHITE, BLACK);
+
+        assert_eq!(*image.get_pixel(0, 0), WHITE);
+        assert_eq!(*image.get_pixel(1, 0), BLACK);
+        assert_eq!(*image.get_pixel(1, 1), WHITE);
+    }
+
+    #[test]
+    fn uv_grid_is_deterministic() {
+        assert_eq!(uv_grid(128, 128, 12), uv_grid(128, 128, 12));
+    }
+
+    #[test]
+    fn uv_grid_outlines_cells_and_encodes_uvs() {
+        let image = uv_grid(101, 101, 10);
+
+        assert_eq!(*image.get_pixel(0, 57), WHITE);
+        assert_eq!(*image.get_pixel(57, 10), WHITE);
+        assert_eq!(*image.get_pixel(99, 99), Rgba([252, 252, 64, 255]));
+    }
+
+    #[test]
+    fn noise_is_deterministic_for_the_same_seed() {
+        assert_eq!(value_noise(32, 32, 8.0, 7), value_noise(32, 32, 8.0, 7));
+        assert_eq!(perlin_noise(32, 32, 8.0, 7), perlin_noise(32, 32, 8.0, 7));
+        assert_ne!(value_noise(32, 32, 8.0, 7), value_noise(32, 32, 8.0, 8));
+        assert_ne!(perlin_noise(32, 32, 8.0, 7), perlin_noise(32, 32, 8.0, 8));
+    }
+
+    #[test]
+    fn value_noise_stays_in_range() {
+        for (x, y) in sample_points() {
+            let value = sample_value_noise(x, y, 3);
+            assert!((0.0..=1.0).contains(&value), "{} at ({}, {})", value, x, y);
+        }
+    }
+
+    #[test]
+    fn perlin_noise_stays_in_range() {
+        for (x, y) in sample_points() {
+            let value = sample_perlin_noise(x, y, 3);
+            assert!((-1.0..=1.0).contains(&value), "{} at ({}, {})", value, x, y);
+        }
+    }
+
+    #[test]
+    fn perlin_noise_is_zero_on_the_lattice() {
+        assert_eq!(sample_perlin_noise(2.0, -3.0, 3), 0.0);
+    }
+}
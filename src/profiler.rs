@@ -0,0 +1,562 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Device, Features, Maintain, MapMode,
+    QuerySet, QuerySetDescriptor, QueryType, Queue, QUERY_SIZE,
+};
+
+/// Most scopes that are timed per frame. Scopes beyond this are ignored.
+pub const MAX_SCOPES: u32 = 32;
+
+/// Frames that can be waiting for their timestamps at once. Timestamps are read back a few frames after they're
+/// written so that reading them never stalls the GPU; frames begun while every slot is still waiting aren't timed.
+pub const FRAMES_IN_FLIGHT: usize = 3;
+
+/// Number of samples the rolling averages are taken over.
+pub const DEFAULT_WINDOW: usize = 120;
+
+/// Timings of a scope over the last few frames and over the whole run, in milliseconds.
+#[derive(Debug, Clone)]
+pub struct RollingAverage {
+    recent: VecDeque<f64>,
+    window: usize,
+    count: usize,
+    total: f64,
+    min: f64,
+    max: f64,
+}
+
+impl RollingAverage {
+    pub fn new(window: usize) -> Self {
+        Self {
+            recent: VecDeque::with_capacity(window),
+            window: window.max(1),
+            count: 0,
+            total: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn push(&mut self, ms: f64) {
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(ms);
+
+        self.count += 1;
+        self.total += ms;
+        self.min = self.min.min(ms);
+        self.max = self.max.max(ms);
+    }
+
+    /// The average of the last `window` samples, or `None` if there aren't any.
+    pub fn recent_average(&self) -> Option<f64> {
+        (!self.recent.is_empty())
+            .then(|| self.recent.iter().sum::<f64>() / self.recent.len() as f64)
+    }
+
+    /// The average of every sample.
+    pub fn average(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.total / self.count as f64)
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+/// Rolling timings per scope, in the order scopes were first seen.
+#[derive(Debug, Clone)]
+pub struct ProfileStats {
+    scopes: Vec<(String, RollingAverage)>,
+    window: usize,
+}
+
+impl ProfileStats {
+    pub fn new(window: usize) -> Self {
+        Self {
+            scopes: Vec::new(),
+            window,
+        }
+    }
+
+    pub fn record(&mut self, scope: &str, ms: f64) {
+        let index = match self.scopes.iter().position(|(name, _)| name == scope) {
+            Some(index) => index,
+            None => {
+                self.scopes
+                    .push((scope.to_string(), RollingAverage::new(self.window)));
+                self.scopes.len() - 1
+            }
+        };
+
+        self.scopes[index].1.push(ms);
+    }
+
+    pub fn get(&self, scope: &str) -> Option<&RollingAverage> {
+        self.scopes
+            .iter()
+            .find(|(name, _)| name == scope)
+            .map(|(_, timings)| timings)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &RollingAverage)> {
+        self.scopes
+            .iter()
+            .map(|(name, timings)| (name.as_str(), timings))
+    }
+
+    /// A one line summary of the recent averages for a HUD or window title, e.g. `shadow 0.41ms, scene 1.20ms`.
+    pub fn summary(&self) -> String {
+        self.iter()
+            .filter_map(|(name, timings)| {
+                Some(format!("{} {:.2}ms", name, timings.recent_average()?))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Every scope as CSV with a header row, times in milliseconds.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("scope,samples,average_ms,min_ms,max_ms,recent_average_ms\n");
+        for (name, timings) in self.iter() {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{}",
+                name,
+                timings.count(),
+                timings.average().unwrap_or_default(),
+                timings.min().unwrap_or_default(),
+                timings.max().unwrap_or_default(),
+                timings.recent_average().unwrap_or_default()
+            );
+        }
+
+        csv
+    }
+
+    /// Every scope as a JSON array of objects with the same fields as [`ProfileStats::to_csv`].
+    pub fn to_json(&self) -> String {
+        let scopes = self
+            .iter()
+            .map(|(name, timings)| {
+                format!(
+                    "  {{\"scope\": \"{}\", \"samples\": {}, \"average_ms\": {}, \"min_ms\": {}, \"max_ms\": {}, \
+                     \"recent_average_ms\": {}}}",
+                    name.replace('\\', "\\\\").replace('"', "\\\""),
+                    timings.count(),
+                    timings.average().unwrap_or_default(),
+                    timings.min().unwrap_or_default(),
+                    timings.max().unwrap_or_default(),
+                    timings.recent_average().unwrap_or_default()
+                )
+            })
+            .collect::<Vec<_>>();
+
+        format!("[\n{}\n]\n", scopes.join(",\n"))
+    }
+
+    pub fn save_csv<P>(&self, path: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        fs::write(path, self.to_csv())
+    }
+
+    pub fn save_json<P>(&self, path: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        fs::write(path, self.to_json())
+    }
+}
+
+/// A scope begun with [`Profiler::begin_scope`], to be passed to [`Profiler::end_scope`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[must_use]
+pub struct ProfileScope(Option<usize>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameState {
+    Free,
+    Recording,
+    Resolved,
+    Mapping,
+}
+
+struct ScopeRecord {
+    name: &'static str,
+    /// The first of the scope's two timestamp queries.
+    query: u32,
+    cpu_start: Instant,
+    cpu_end: Option<Instant>,
+}
+
+struct FrameSlot {
+    /// Query set, resolve buffer and readback buffer when timestamps are supported.
+    queries: Option<(QuerySet, Buffer, Buffer)>,
+    scopes: Vec<ScopeRecord>,
+    /// Queries written this frame, including those of scopes that were never ended.
+    query_count: u32,
+    state: FrameState,
+    /// One of the `MAP_*` states, set by the readback buffer's map callback.
+    map_state: Arc<AtomicU8>,
+}
+
+const MAP_PENDING: u8 = 0;
+const MAP_DONE: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+/// Times scopes of command encoding on the GPU with timestamp queries, or on the CPU if the device doesn't have
+/// [`Features::TIMESTAMP_QUERY`].
+///
+/// CPU timings only measure how long it takes to record the commands, not to execute them, but still show where the
+/// CPU side of a frame goes.
+///
+/// Every frame is wrapped in [`Profiler::begin_frame`] and [`Profiler::end_frame`], the latter before the encoder is
+/// finished, and followed by [`Profiler::frame_submitted`] once the commands are submitted.
+pub struct Profiler {
+    frames: Vec<FrameSlot>,
+    current: Option<usize>,
+    next: usize,
+    timestamp_period: f64,
+    stats: ProfileStats,
+}
+
+impl Profiler {
+    pub fn new(device: &Device, queue: &Queue) -> Self {
+        let timestamps = device.features().contains(Features::TIMESTAMP_QUERY);
+        let buffer_size = (MAX_SCOPES * 2) as u64 * QUERY_SIZE as u64;
+
+        let frames = (0..FRAMES_IN_FLIGHT)
+            .map(|_| FrameSlot {
+                queries: timestamps.then(|| {
+                    let query_set = device.create_query_set(&QuerySetDescriptor {
+                        label: Some("query_set::profiler"),
+                        ty: QueryType::Timestamp,
+                        count: MAX_SCOPES * 2,
+                    });
+                    let resolve_buffer = device.create_buffer(&BufferDescriptor {
+                        label: Some("buffer::profiler_resolve"),
+                        size: buffer_size,
+                        usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                        mapped_at_creation: false,
+                    });
+                    let readback_buffer = device.create_buffer(&BufferDescriptor {
+                        label: Some("buffer::profiler_readback"),
+                        size: buffer_size,
+                        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                        mapped_at_creation: false,
+                    });
+
+                    (query_set, resolve_buffer, readback_buffer)
+                }),
+                scopes: Vec::new(),
+                query_count: 0,
+                state: FrameState::Free,
+                map_state: Arc::new(AtomicU8::new(MAP_PENDING)),
+            })
+            .collect();
+
+        Self {
+            frames,
+            current: None,
+            next: 0,
+            timestamp_period: queue.get_timestamp_period() as f64,
+            stats: ProfileStats::new(DEFAULT_WINDOW),
+        }
+    }
+
+    /// Whether scopes are timed on the GPU rather than the CPU.
+    pub fn has_timestamps(&self) -> bool {
+        self.frames[0].queries.is_some()
+    }
+
+    pub fn stats(&self) -> &ProfileStats {
+        &self.stats
+    }
+
+    pub fn begin_frame(&mut self) {
+        let frame = &mut self.frames[self.next];
+        if frame.state != FrameState::Free {
+            self.current = None;
+            return;
+        }
+
+        frame.scopes.clear();
+        frame.query_count = 0;
+        frame.state = FrameState::Recording;
+        self.current = Some(self.next);
+        self.next = (self.next + 1) % self.frames.len();
+    }
+
+    pub fn begin_scope(
+        &mut self,
+        name: &'static str,
+        encoder: &mut CommandEncoder,
+    ) -> ProfileScope {
+        let Some(frame) = self.current.map(|current| &mut self.frames[current]) else {
+            return ProfileScope(None);
+        };
+        if frame.scopes.len() >= MAX_SCOPES as usize {
+            return ProfileScope(None);
+        }
+
+        let index = frame.scopes.len();
+        let query = index as u32 * 2;
+        if let Some((query_set, _, _)) = &frame.queries {
+            encoder.write_timestamp(query_set, query);
+        }
+        frame.query_count = query + 2;
+        frame.scopes.push(ScopeRecord {
+            name,
+            query,
+            cpu_start: Instant::now(),
+            cpu_end: None,
+        });
+
+        ProfileScope(Some(index))
+    }
+
+    pub fn end_scope(&mut self, scope: ProfileScope, encoder: &mut CommandEncoder) {
+        let (Some(index), Some(current)) = (scope.0, self.current) else {
+            return;
+        };
+        let frame = &mut self.frames[current];
+
+        if let Some((query_set, _, _)) = &frame.queries {
+            encoder.write_timestamp(query_set, frame.scopes[index].query + 1);
+        }
+        frame.scopes[index].cpu_end = Some(Instant::now());
+    }
+
+    /// Resolve the frame's timestamps into a buffer that can be read back. Call before finishing `encoder`.
+    pub fn end_frame(&mut self, encoder: &mut CommandEncoder) {
+        let Some(current) = self.current else {
+            return;
+        };
+        let frame = &mut self.frames[current];
+
+        // Scopes that were never ended have no end timestamp to read. Their queries are still resolved, so that every
+        // other scope's timestamps stay where they were written.
+        frame.scopes.retain(|scope| scope.cpu_end.is_some());
+
+        match &frame.queries {
+            Some((query_set, resolve_buffer, readback_buffer)) if !frame.scopes.is_empty() => {
+                let query_count = frame.query_count;
+                encoder.resolve_query_set(query_set, 0..query_count, resolve_buffer, 0);
+                encoder.copy_buffer_to_buffer(
+                    resolve_buffer,
+                    0,
+                    readback_buffer,
+                    0,
+                    query_count as u64 * QUERY_SIZE as u64,
+                );
+                frame.state = FrameState::Resolved;
+            }
+
+            Some(_) => frame.state = FrameState::Free,
+
+            None => {
+                for scope in &frame.scopes {
+                    let end = scope.cpu_end.expect("unended scopes are removed");
+                    self.stats
+                        .record(scope.name, (end - scope.cpu_start).as_secs_f64() * 1000.0);
+                }
+                frame.state = FrameState::Free;
+            }
+        }
+    }
+
+    /// Start reading back the timestamps of the frame just submitted, and collect any from earlier frames that have
+    /// arrived. Never blocks.
+    pub fn frame_submitted(&mut self, device: &Device) {
+        if let Some(current) = self.current.take() {
+            let frame = &mut self.frames[current];
+            if let (FrameState::Resolved, Some((_, _, readback_buffer))) =
+                (frame.state, &frame.queries)
+            {
+                let map_state = Arc::clone(&frame.map_state);
+                let size = frame.query_count as u64 * QUERY_SIZE as u64;
+                readback_buffer
+                    .slice(..size)
+                    .map_async(MapMode::Read, move |result| {
+                        let state = if result.is_ok() { MAP_DONE } else { MAP_FAILED };
+                        map_state.store(state, Ordering::Release);
+                    });
+                frame.state = FrameState::Mapping;
+            }
+        }
+
+        device.poll(Maintain::Poll);
+
+        for frame in &mut self.frames {
+            if frame.state != FrameState::Mapping {
+                continue;
+            }
+            let Some((_, _, readback_buffer)) = &frame.queries else {
+                continue;
+            };
+
+            match frame.map_state.swap(MAP_PENDING, Ordering::Acquire) {
+                MAP_DONE => (),
+                // The frame's timings are lost, but its slot can still be used again.
+                MAP_FAILED => {
+                    frame.state = FrameState::Free;
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let size = frame.query_count as u64 * QUERY_SIZE as u64;
+            {
+                let data = readback_buffer.slice(..size).get_mapped_range();
+                let timestamps = bytemuck::cast_slice::<u8, u64>(&data);
+                for scope in &frame.scopes {
+                    let start = timestamps[scope.query as usize];
+                    let end = timestamps[scope.query as usize + 1];
+                    let ticks = end.saturating_sub(start);
+                    self.stats.record(
+                        scope.name,
+                        ticks as f64 * self.timestamp_period / 1_000_000.0,
+                    );
+                }
+            }
+            readback_buffer.unmap();
+            frame.state = FrameState::Free;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1e-9;
+
+    fn assert_near(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("expected a value");
+        assert!(
+            (actual - expected).abs() < EPSILON,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    /// Stats with `scope` timed at each of `samples`.
+    fn stats_with(scope: &str, samples: &[f64]) -> ProfileStats {
+        let mut stats = ProfileStats::new(DEFAULT_WINDOW);
+        for ms in samples {
+            stats.record(scope, *ms);
+        }
+        stats
+    }
+
+    #[test]
+    fn empty_rolling_average_has_no_values() {
+        let timings = RollingAverage::new(4);
+
+        assert_eq!(timings.recent_average(), None);
+        assert_eq!(timings.average(), None);
+        assert_eq!(timings.min(), None);
+        assert_eq!(timings.max(), None);
+        assert_eq!(timings.count(), 0);
+    }
+
+    #[test]
+    fn recent_average_only_covers_the_window() {
+        let mut timings = RollingAverage::new(2);
+        for ms in [1.0, 2.0, 6.0] {
+            timings.push(ms);
+        }
+
+        assert_near(timings.recent_average(), 4.0);
+        assert_near(timings.average(), 3.0);
+        assert_near(timings.min(), 1.0);
+        assert_near(timings.max(), 6.0);
+        assert_eq!(timings.count(), 3);
+    }
+
+    #[test]
+    fn zero_window_keeps_the_latest_sample() {
+        let mut timings = RollingAverage::new(0);
+        timings.push(1.0);
+        timings.push(3.0);
+
+        assert_near(timings.recent_average(), 3.0);
+    }
+
+    #[test]
+    fn scopes_are_kept_in_the_order_first_seen() {
+        let mut stats = ProfileStats::new(DEFAULT_WINDOW);
+        stats.record("shadow", 1.0);
+        stats.record("scene", 2.0);
+        stats.record("shadow", 3.0);
+
+        let names = stats.iter().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(names, ["shadow", "scene"]);
+        assert_eq!(stats.get("shadow").map(RollingAverage::count), Some(2));
+        assert!(stats.get("bloom").is_none());
+    }
+
+    #[test]
+    fn summary_lists_recent_averages() {
+        let mut stats = stats_with("shadow", &[0.4, 0.42]);
+        stats.record("scene", 1.2);
+
+        assert_eq!(stats.summary(), "shadow 0.41ms, scene 1.20ms");
+        assert_eq!(ProfileStats::new(DEFAULT_WINDOW).summary(), "");
+    }
+
+    #[test]
+    fn csv_has_a_header_and_a_row_per_scope() {
+        let stats = stats_with("scene", &[1.0, 3.0]);
+
+        assert_eq!(
+            stats.to_csv(),
+            "scope,samples,average_ms,min_ms,max_ms,recent_average_ms\nscene,2,2,1,3,2\n"
+        );
+    }
+
+    #[test]
+    fn json_has_an_object_per_scope() {
+        let mut stats = stats_with("scene", &[1.0, 3.0]);
+        stats.record("ui", 0.5);
+
+        assert_eq!(
+            stats.to_json(),
+            "[\n  {\"scope\": \"scene\", \"samples\": 2, \"average_ms\": 2, \"min_ms\": 1, \"max_ms\": 3, \
+             \"recent_average_ms\": 2},\n  {\"scope\": \"ui\", \"samples\": 1, \"average_ms\": 0.5, \"min_ms\": 0.5, \
+             \"max_ms\": 0.5, \"recent_average_ms\": 0.5}\n]\n"
+        );
+    }
+
+    #[test]
+    fn json_escapes_scope_names() {
+        let stats = stats_with(r#"a "quoted" \ scope"#, &[1.0]);
+
+        assert!(stats
+            .to_json()
+            .contains(r#""scope": "a \"quoted\" \\ scope""#));
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    collections::VecDeque,
+    fmt, fs,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{self, SyncSender},
+    thread::{self, JoinHandle},
+    time::SystemTime,
+};
+
+use image::RgbaImage;
+use wgpu::{
+    CommandEncoderDescriptor, Device, Maintain, Queue, Texture, TextureUsages, TextureView,
+};
+
+use crate::{
+    assets,
+    capture::{timestamp, CaptureError, CaptureTarget, TextureReadback},
+    config::Config,
+};
+
+/// Simulated frame rate used unless configured otherwise.
+pub const DEFAULT_RECORDING_FPS: f32 = 60.0;
+
+/// Frames that can be waiting to be read back. Recording only waits on the GPU once this many are queued up, so
+/// reading frames back overlaps with drawing the next ones.
+pub const MAX_PENDING_FRAMES: usize = 3;
+
+/// Frames that can be waiting to be encoded and written.
+const MAX_UNWRITTEN_FRAMES: usize = 8;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// A folder of PNGs numbered from 0.
+    #[default]
+    PngSequence,
+    /// An uncompressed YUV 4:4:4 video stream.
+    Y4m,
+}
+
+impl fmt::Display for RecordingFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingFormat::PngSequence => write!(f, "png"),
+            RecordingFormat::Y4m => write!(f, "y4m"),
+        }
+    }
+}
+
+impl FromStr for RecordingFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "png" => Ok(RecordingFormat::PngSequence),
+            "y4m" => Ok(RecordingFormat::Y4m),
+            _ => Err(format!("unknown recording format `{}`", s)),
+        }
+    }
+}
+
+/// Records every frame at a fixed simulated timestep, so the recording plays back at its frame rate however long each
+/// frame took to draw.
+///
+/// While recording, samples advance their simulation by [`Recorder::get_timestep`] each frame instead of the real
+/// time since the last frame, and call [`Recorder::capture`] before presenting. Frames are read back asynchronously
+/// and encoded on another thread.
+pub struct Recorder {
+    path: PathBuf,
+    size: (u32, u32),
+    timestep: f32,
+    frame_count: u64,
+    pending: VecDeque<TextureReadback>,
+    target: Option<CaptureTarget>,
+    sender: Option<SyncSender<RgbaImage>>,
+    writer: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Recorder {
+    /// Start recording `width` by `height` frames at `fps` into a new file or folder in the output folder, named after
+    /// `name` and the current time.
+    pub fn start(
+        name: &str,
+        format: RecordingFormat,
+        fps: f32,
+        width: u32,
+        height: u32,
+    ) -> io::Result<Self> {
+        let name = format!("{}-{}", name, timestamp(SystemTime::now()));
+        let (path, mut frame_writer): (PathBuf, Box<dyn FrameWriter + Send>) = match format {
+            RecordingFormat::PngSequence => {
+                let path = assets::output_path(name)?;
+                fs::create_dir_all(&path)?;
+                (path.clone(), Box::new(PngSequenceWriter { path, next: 0 }))
+            }
+            RecordingFormat::Y4m => {
+                let path = assets::output_path(format!("{}.y4m", name))?;
+                let writer =
+                    Y4mWriter::new(BufWriter::new(File::create(&path)?), width, height, fps)?;
+                (path, Box::new(writer))
+            }
+        };
+
+        let (sender, receiver) = mpsc::sync_channel::<RgbaImage>(MAX_UNWRITTEN_FRAMES);
+        let writer = thread::spawn(move || {
+            for image in receiver {
+                frame_writer.write_frame(&image)?;
+            }
+            frame_writer.finish()
+        });
+
+        Ok(Self {
+            path,
+            size: (width, height),
+            timestep: 1.0 / fps,
+            frame_count: 0,
+            pending: VecDeque::new(),
+            target: None,
+            sender: Some(sender),
+            writer: Some(writer),
+        })
+    }
+
+    /// The file or folder being recorded into.
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Seconds of simulated time per frame.
+    pub fn get_timestep(&self) -> f32 {
+        self.timestep
+    }
+
+    /// Frames captured so far.
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Capture the frame just drawn into `frame`, before it's presented.
+    ///
+    /// Like [`capture_frame`](crate::capture::capture_frame), the frame is copied from the surface texture where the
+    /// surface allows it, or drawn again by `render` into a texture that does.
+    pub fn capture<F>(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        frame: &Texture,
+        render: F,
+    ) -> Result<(), CaptureError>
+    where
+        F: FnOnce(&TextureView),
+    {
+        let size = (frame.width(), frame.height());
+        if size != self.size {
+            return Err(CaptureError::SizeChanged {
+                expected: self.size,
+                actual: size,
+            });
+        }
+
+        let source = if frame.usage().contains(TextureUsages::COPY_SRC) {
+            frame
+        } else {
+            let target = self
+                .target
+                .get_or_insert_with(|| CaptureTarget::new(device, frame.format(), size.0, size.1));
+            render(target.get_view());
+            target.get_texture()
+        };
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("command_encoder::recording"),
+        });
+        let mut readback = TextureReadback::new(device, &mut encoder, source)?;
+        queue.submit(Some(encoder.finish()));
+        readback.map_async();
+
+        self.pending.push_back(readback);
+        self.frame_count += 1;
+
+        // Only wait for the GPU once too many frames are queued up.
+        let maintain = if self.pending.len() > MAX_PENDING_FRAMES {
+            Maintain::Wait
+        } else {
+            Maintain::Poll
+        };
+        device.poll(maintain);
+
+        self.write_ready_frames()
+    }
+
+    /// Wait for every captured frame to be written and close the recording, returning how many frames it has.
+    pub fn finish(mut self, device: &Device) -> Result<u64, CaptureError> {
+        if !self.pending.is_empty() {
+            device.poll(Maintain::Wait);
+        }
+        let result = self.write_ready_frames();
+
+        // Dropping the sender ends the writer thread once it has written everything sent so far.
+        self.sender = None;
+        let written = self.join_writer();
+
+        result.and(written).map(|()| self.frame_count)
+    }
+
+    /// Send frames that have been read back to the writer, in the order they were captured.
+    fn write_ready_frames(&mut self) -> Result<(), CaptureError> {
+        while let Some(result) = self.pending.front_mut().and_then(TextureReadback::try_read) {
+            self.pending.pop_front();
+
+            let image = result?;
+            let sent = self
+                .sender
+                .as_ref()
+                .is_some_and(|sender| sender.send(image).is_ok());
+            if !sent {
+                // The writer only stops early if it failed, and its error says why.
+                self.sender = None;
+                return self.join_writer();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn join_writer(&mut self) -> Result<(), CaptureError> {
+        match self.writer.take() {
+            Some(writer) => Ok(writer.join().expect("the recording writer panicked")?),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Start recording `width` by `height` frames named after `name` with the configured format and frame rate, reporting
+/// where they go, or `None` if the recording can't be created.
+pub fn start_recording(name: &str, config: &Config, width: u32, height: u32) -> Option<Recorder> {
+    let result = Recorder::start(
+        name,
+        config.recording_format,
+        config.recording_fps,
+        width,
+        height,
+    );
+
+    match result {
+        Ok(recorder) => {
+            println!("recording to {}", recorder.get_path().display());
+            Some(recorder)
+        }
+        Err(e) => {
+            eprintln!("unable to start recording: {}", e);
+            None
+        }
+    }
+}
+
+/// Stop the recording, if there is one, once every frame has been written, reporting how many were saved.
+pub fn stop_recording(recorder: &mut Option<Recorder>, device: &Device) {
+    let Some(recorder) = recorder.take() else {
+        return;
+    };
+    let path = recorder.get_path().to_path_buf();
+
+    match recorder.finish(device) {
+        Ok(frame_count) => println!("saved {} frames to {}", frame_count, path.display()),
+        Err(e) => eprintln!("unable to save the recording: {}", e),
+    }
+}
+
+trait FrameWriter {
+    fn write_frame(&mut self, image: &RgbaImage) -> io::Result<()>;
+
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+struct PngSequenceWriter {
+    path: PathBuf,
+    next: u64,
+}
+
+impl FrameWriter for PngSequenceWriter {
+    fn write_frame(&mut self, image: &RgbaImage) -> io::Result<()> {
+        let path = self.path.join(format!("{:06}.png", self.next));
+        image.save(path).map_err(io::Error::other)?;
+        self.next += 1;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes a YUV4MPEG2 stream, the uncompressed format most video tools read, in 4:4:4 so that no chroma is lost.
+pub struct Y4mWriter<W> {
+    writer: W,
+    size: (u32, u32),
+    planes: Vec<u8>,
+}
+
+impl<W> Y4mWriter<W>
+where
+    W: Write,
+{
+    /// Write the stream header for `width` by `height` frames at `fps`.
+    pub fn new(mut writer: W, width: u32, height: u32, fps: f32) -> io::Result<Self> {
+        let (numerator, denominator) = frame_rate_ratio(fps);
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+            width, height, numerator, denominator
+        )?;
+
+        Ok(Self {
+            writer,
+            size: (width, height),
+            planes: Vec::new(),
+        })
+    }
+
+    pub fn write_frame(&mut self, image: &RgbaImage) -> io::Result<()> {
+        if image.dimensions() != self.size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frames must all be the size given in the header",
+            ));
+        }
+
+        rgba_to_yuv444(image, &mut self.planes);
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&self.planes)
+    }
+
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W> FrameWriter for Y4mWriter<W>
+where
+    W: Write,
+{
+    fn write_frame(&mut self, image: &RgbaImage) -> io::Result<()> {
+        Y4mWriter::write_frame(self, image)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// `fps` as a ratio of whole numbers, exact to a thousandth of a frame.
+pub fn frame_rate_ratio(fps: f32) -> (u32, u32) {
+    if fps.fract() == 0.0 {
+        (fps as u32, 1)
+    } else {
+        ((fps * 1000.0).round() as u32, 1000)
+    }
+}
+
+/// Convert `image` into consecutive Y, Cb and Cr planes in `planes`, using limited range BT.601 like video players
+/// expect of Y4M streams. Alpha is dropped.
+pub fn rgba_to_yuv444(image: &RgbaImage, planes: &mut Vec<u8>) {
+    let pixel_count = image.width() as usize * image.height() as usize;
+    planes.clear();
+    planes.resize(pixel_count * 3, 0);
+    let (y_plane, chroma) = planes.split_at_mut(pixel_count);
+    let (cb_plane, cr_plane) = chroma.split_at_mut(pixel_count);
+
+    for (i, pixel) in image.pixels().enumerate() {
+        let [r, g, b, _] = pixel.0.map(|channel| channel as f32 / 255.0);
+
+        y_plane[i] = (16.0 + 65.481 * r + 128.553 * g + 24.966 * b).round() as u8;
+        cb_plane[i] = (128.0 - 37.797 * r - 74.203 * g + 112.0 * b).round() as u8;
+        cr_plane[i] = (128.0 + 112.0 * r - 93.786 * g - 18.214 * b).round() as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    /// The Y, Cb and Cr of a single `rgba` pixel.
+    fn yuv(rgba: [u8; 4]) -> [u8; 3] {
+        let mut planes = Vec::new();
+        rgba_to_yuv444(&RgbaImage::from_pixel(1, 1, Rgba(rgba)), &mut planes);
+        [planes[0], planes[1], planes[2]]
+    }
+
+    #[test]
+    fn whole_frame_rates_have_a_denominator_of_one() {
+        assert_eq!(frame_rate_ratio(60.0), (60, 1));
+        assert_eq!(frame_rate_ratio(24.0), (24, 1));
+    }
+
+    #[test]
+    fn fractional_frame_rates_are_exact_to_a_thousandth() {
+        assert_eq!(frame_rate_ratio(29.97), (29970, 1000));
+        assert_eq!(frame_rate_ratio(23.976), (23976, 1000));
+    }
+
+    #[test]
+    fn black_and_white_are_limited_range() {
+        assert_eq!(yuv([0, 0, 0, 255]), [16, 128, 128]);
+        assert_eq!(yuv([255, 255, 255, 255]), [235, 128, 128]);
+    }
+
+    #[test]
+    fn primaries_match_bt601() {
+        assert_eq!(yuv([255, 0, 0, 255]), [81, 90, 240]);
+        assert_eq!(yuv([0, 255, 0, 255]), [145, 54, 34]);
+        assert_eq!(yuv([0, 0, 255, 255]), [41, 240, 110]);
+    }
+
+    #[test]
+    fn alpha_is_dropped() {
+        assert_eq!(yuv([255, 0, 0, 0]), yuv([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn planes_are_written_one_after_another() {
+        let mut image = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 255]));
+        image.put_pixel(1, 0, Rgba([255, 255, 255, 255]));
+        let mut planes = vec![7; 100];
+
+        rgba_to_yuv444(&image, &mut planes);
+
+        assert_eq!(planes, [16, 235, 128, 128, 128, 128]);
+    }
+
+    #[test]
+    fn y4m_streams_have_a_header_and_marked_frames() {
+        let mut writer = Y4mWriter::new(Vec::new(), 2, 1, 29.97).unwrap();
+        let image = RgbaImage::from_pixel(2, 1, Rgba([255, 255, 255, 255]));
+        writer.write_frame(&image).unwrap();
+        writer.write_frame(&image).unwrap();
+
+        let frame = b"FRAME\n\xeb\xeb\x80\x80\x80\x80";
+        let mut expected = b"YUV4MPEG2 W2 H1 F29970:1000 Ip A1:1 C444\n".to_vec();
+        expected.extend_from_slice(frame);
+        expected.extend_from_slice(frame);
+        assert_eq!(writer.into_inner().unwrap(), expected);
+    }
+
+    #[test]
+    fn y4m_frames_must_match_the_header_size() {
+        let mut writer = Y4mWriter::new(Vec::new(), 2, 2, 60.0).unwrap();
+
+        let error = writer.write_frame(&RgbaImage::new(2, 1)).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            writer.into_inner().unwrap(),
+            b"YUV4MPEG2 W2 H2 F60:1 Ip A1:1 C444\n"
+        );
+    }
+}
@@ -0,0 +1,498 @@
+use std::{collections::BTreeMap, fmt};
+
+use naga::{
+    valid::{Capabilities, ModuleInfo, ValidationFlags, Validator},
+    AddressSpace, ImageClass, ImageDimension, Module, ScalarKind, ShaderStage, StorageAccess,
+    StorageFormat, TypeInner,
+};
+use wgpu::{
+    BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType,
+    BufferBindingType, BufferSize, Device, SamplerBindingType, ShaderStages, StorageTextureAccess,
+    TextureFormat, TextureSampleType, TextureViewDimension,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReflectionError {
+    /// The WGSL didn't parse, with naga's report of where and why.
+    Parse(String),
+    /// The module parsed but isn't valid, so which entry points use what can't be worked out.
+    Validation(String),
+    /// The binding has a type with no bind group layout entry, e.g. a binding array.
+    UnsupportedBinding { group: u32, binding: u32 },
+    /// Shaders merged together declare the same binding with different types.
+    Mismatch { group: u32, binding: u32 },
+}
+
+impl fmt::Display for ReflectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReflectionError::Parse(report) => write!(f, "unable to parse shader:\n{}", report),
+            ReflectionError::Validation(report) => write!(f, "invalid shader:\n{}", report),
+            ReflectionError::UnsupportedBinding { group, binding } => write!(
+                f,
+                "@group({}) @binding({}) can't be reflected into a layout",
+                group, binding
+            ),
+            ReflectionError::Mismatch { group, binding } => write!(
+                f,
+                "@group({}) @binding({}) is declared with different types",
+                group, binding
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReflectionError {}
+
+/// The bind group layouts a shader declares, read from its `@group` and `@binding` attributes so they can't drift out
+/// of sync with hand written layouts.
+///
+/// Each binding is visible to the stages of the entry points that use it, and buffers need at least the size of their
+/// type. WGSL doesn't say how textures are sampled, so float textures are taken to be filterable unless they're
+/// multisampled and samplers to be filtering. Buffers never have dynamic offsets.
+#[derive(Debug, Clone, Default)]
+pub struct ShaderBindings {
+    groups: BTreeMap<u32, Vec<BindGroupLayoutEntry>>,
+}
+
+impl ShaderBindings {
+    pub fn from_wgsl(source: &str) -> Result<Self, ReflectionError> {
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|e| ReflectionError::Parse(e.emit_to_string(source)))?;
+        let info = validator()
+            .validate(&module)
+            .map_err(|e| ReflectionError::Validation(e.emit_to_string(source)))?;
+
+        Self::reflect(&module, &info)
+    }
+
+    pub fn from_module(module: &Module) -> Result<Self, ReflectionError> {
+        let info = validator()
+            .validate(module)
+            .map_err(|e| ReflectionError::Validation(e.to_string()))?;
+
+        Self::reflect(module, &info)
+    }
+
+    fn reflect(module: &Module, info: &ModuleInfo) -> Result<Self, ReflectionError> {
+        let mut groups = BTreeMap::<u32, Vec<BindGroupLayoutEntry>>::new();
+        for (handle, global) in module.global_variables.iter() {
+            let Some(resource_binding) = &global.binding else {
+                continue;
+            };
+            let (group, binding) = (resource_binding.group, resource_binding.binding);
+
+            // Bindings no entry point uses keep an empty visibility, so bind groups still match the declarations.
+            let mut visibility = ShaderStages::NONE;
+            for (index, entry_point) in module.entry_points.iter().enumerate() {
+                if !info.get_entry_point(index)[handle].is_empty() {
+                    visibility |= shader_stages(entry_point.stage);
+                }
+            }
+
+            let ty = binding_type(module, global.space, &module.types[global.ty].inner)
+                .ok_or(ReflectionError::UnsupportedBinding { group, binding })?;
+
+            groups.entry(group).or_default().push(BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty,
+                count: None,
+            });
+        }
+
+        for entries in groups.values_mut() {
+            entries.sort_by_key(|entry| entry.binding);
+        }
+
+        Ok(Self { groups })
+    }
+
+    /// Add the bindings of another shader used in the same pipeline, e.g. one with a different fragment stage. Bindings
+    /// both shaders declare are visible to the stages of either.
+    pub fn merge(&mut self, other: &ShaderBindings) -> Result<(), ReflectionError> {
+        for (&group, other_entries) in &other.groups {
+            let entries = self.groups.entry(group).or_default();
+            for other_entry in other_entries {
+                match entries
+                    .iter_mut()
+                    .find(|entry| entry.binding == other_entry.binding)
+                {
+                    Some(entry) if entry.ty != other_entry.ty => {
+                        return Err(ReflectionError::Mismatch {
+                            group,
+                            binding: entry.binding,
+                        });
+                    }
+                    Some(entry) => entry.visibility |= other_entry.visibility,
+                    None => entries.push(*other_entry),
+                }
+            }
+            entries.sort_by_key(|entry| entry.binding);
+        }
+
+        Ok(())
+    }
+
+    /// How many bind groups a pipeline layout needs, i.e. one more than the highest group declared.
+    pub fn get_group_count(&self) -> u32 {
+        self.groups.keys().next_back().map_or(0, |group| group + 1)
+    }
+
+    /// The entries of `group` in binding order, which is empty for groups the shader doesn't declare.
+    pub fn get_entries(&self, group: u32) -> &[BindGroupLayoutEntry] {
+        self.groups.get(&group).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn descriptor<'a>(
+        &'a self,
+        label: Option<&'a str>,
+        group: u32,
+    ) -> BindGroupLayoutDescriptor<'a> {
+        BindGroupLayoutDescriptor {
+            label,
+            entries: self.get_entries(group),
+        }
+    }
+
+    pub fn create_bind_group_layout(
+        &self,
+        device: &Device,
+        label: &str,
+        group: u32,
+    ) -> BindGroupLayout {
+        device.create_bind_group_layout(&self.descriptor(Some(label), group))
+    }
+}
+
+fn validator() -> Validator {
+    Validator::new(ValidationFlags::all(), Capabilities::all())
+}
+
+fn shader_stages(stage: ShaderStage) -> ShaderStages {
+    match stage {
+        ShaderStage::Vertex => ShaderStages::VERTEX,
+        ShaderStage::Fragment => ShaderStages::FRAGMENT,
+        ShaderStage::Compute => ShaderStages::COMPUTE,
+    }
+}
+
+/// The layout entry type of a global in `space` with type `inner`, or `None` if there's no equivalent.
+fn binding_type(module: &Module, space: AddressSpace, inner: &TypeInner) -> Option<BindingType> {
+    let buffer = |ty| BindingType::Buffer {
+        ty,
+        has_dynamic_offset: false,
+        // Runtime sized arrays count as a single element, the least a buffer can hold.
+        min_binding_size: BufferSize::new(inner.size(&module.constants) as u64),
+    };
+
+    match (space, inner) {
+        (AddressSpace::Uniform, _) => Some(buffer(BufferBindingType::Uniform)),
+        (AddressSpace::Storage { access }, _) => Some(buffer(BufferBindingType::Storage {
+            read_only: !access.contains(StorageAccess::STORE),
+        })),
+        (AddressSpace::Handle, TypeInner::Sampler { comparison }) => {
+            Some(BindingType::Sampler(if *comparison {
+                SamplerBindingType::Comparison
+            } else {
+                SamplerBindingType::Filtering
+            }))
+        }
+        (
+            AddressSpace::Handle,
+            TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            },
+        ) => {
+            let view_dimension = view_dimension(*dim, *arrayed)?;
+            match *class {
+                ImageClass::Sampled { kind, multi } => Some(BindingType::Texture {
+                    sample_type: match kind {
+                        ScalarKind::Float => TextureSampleType::Float { filterable: !multi },
+                        ScalarKind::Sint => TextureSampleType::Sint,
+                        ScalarKind::Uint => TextureSampleType::Uint,
+                        ScalarKind::Bool => return None,
+                    },
+                    view_dimension,
+                    multisampled: multi,
+                }),
+                ImageClass::Depth { multi } => Some(BindingType::Texture {
+                    sample_type: TextureSampleType::Depth,
+                    view_dimension,
+                    multisampled: multi,
+                }),
+                ImageClass::Storage { format, access } => Some(BindingType::StorageTexture {
+                    access: match (
+                        access.contains(StorageAccess::LOAD),
+                        access.contains(StorageAccess::STORE),
+                    ) {
+                        (true, true) => StorageTextureAccess::ReadWrite,
+                        (true, false) => StorageTextureAccess::ReadOnly,
+                        _ => StorageTextureAccess::WriteOnly,
+                    },
+                    format: storage_format(format),
+                    view_dimension,
+                }),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn view_dimension(dim: ImageDimension, arrayed: bool) -> Option<TextureViewDimension> {
+    match (dim, arrayed) {
+        (ImageDimension::D1, false) => Some(TextureViewDimension::D1),
+        (ImageDimension::D2, false) => Some(TextureViewDimension::D2),
+        (ImageDimension::D2, true) => Some(TextureViewDimension::D2Array),
+        (ImageDimension::D3, false) => Some(TextureViewDimension::D3),
+        (ImageDimension::Cube, false) => Some(TextureViewDimension::Cube),
+        (ImageDimension::Cube, true) => Some(TextureViewDimension::CubeArray),
+        _ => None,
+    }
+}
+
+fn storage_format(format: StorageFormat) -> TextureFormat {
+    match format {
+        StorageFormat::R8Unorm => TextureFormat::R8Unorm,
+        StorageFormat::R8Snorm => TextureFormat::R8Snorm,
+        StorageFormat::R8Uint => TextureFormat::R8Uint,
+        StorageFormat::R8Sint => TextureFormat::R8Sint,
+        StorageFormat::R16Uint => TextureFormat::R16Uint,
+        StorageFormat::R16Sint => TextureFormat::R16Sint,
+        StorageFormat::R16Float => TextureFormat::R16Float,
+        StorageFormat::Rg8Unorm => TextureFormat::Rg8Unorm,
+        StorageFormat::Rg8Snorm => TextureFormat::Rg8Snorm,
+        StorageFormat::Rg8Uint => TextureFormat::Rg8Uint,
+        StorageFormat::Rg8Sint => TextureFormat::Rg8Sint,
+        StorageFormat::R32Uint => TextureFormat::R32Uint,
+        StorageFormat::R32Sint => TextureFormat::R32Sint,
+        StorageFormat::R32Float => TextureFormat::R32Float,
+        StorageFormat::Rg16Uint => TextureFormat::Rg16Uint,
+        StorageFormat::Rg16Sint => TextureFormat::Rg16Sint,
+        StorageFormat::Rg16Float => TextureFormat::Rg16Float,
+        StorageFormat::Rgba8Unorm => TextureFormat::Rgba8Unorm,
+        StorageFormat::Rgba8Snorm => TextureFormat::Rgba8Snorm,
+        StorageFormat::Rgba8Uint => TextureFormat::Rgba8Uint,
+        StorageFormat::Rgba8Sint => TextureFormat::Rgba8Sint,
+        StorageFormat::Rgb10a2Unorm => TextureFormat::Rgb10a2Unorm,
+        StorageFormat::Rg11b10Float => TextureFormat::Rg11b10Float,
+        StorageFormat::Rg32Uint => TextureFormat::Rg32Uint,
+        StorageFormat::Rg32Sint => TextureFormat::Rg32Sint,
+        StorageFormat::Rg32Float => TextureFormat::Rg32Float,
+        StorageFormat::Rgba16Uint => TextureFormat::Rgba16Uint,
+        StorageFormat::Rgba16Sint => TextureFormat::Rgba16Sint,
+        StorageFormat::Rgba16Float => TextureFormat::Rgba16Float,
+        StorageFormat::Rgba32Uint => TextureFormat::Rgba32Uint,
+        StorageFormat::Rgba32Sint => TextureFormat::Rgba32Sint,
+        StorageFormat::Rgba32Float => TextureFormat::Rgba32Float,
+        StorageFormat::R16Unorm => TextureFormat::R16Unorm,
+        StorageFormat::R16Snorm => TextureFormat::R16Snorm,
+        StorageFormat::Rg16Unorm => TextureFormat::Rg16Unorm,
+        StorageFormat::Rg16Snorm => TextureFormat::Rg16Snorm,
+        StorageFormat::Rgba16Unorm => TextureFormat::Rgba16Unorm,
+        StorageFormat::Rgba16Snorm => TextureFormat::Rgba16Snorm,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENE: &str = r#"
+struct Camera {
+    view_proj: mat4x4<f32>,
+    position: vec3<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(0) @binding(1) var<uniform> tint: vec4<f32>;
+@group(0) @binding(2) var<uniform> unused: vec4<f32>;
+@group(1) @binding(0) var diffuse_sampler: sampler;
+@group(1) @binding(1) var diffuse_texture: texture_2d<f32>;
+@group(1) @binding(2) var samples: texture_multisampled_2d<f32>;
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+    return camera.view_proj * vec4<f32>(position, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = textureSample(diffuse_texture, diffuse_sampler, position.xy / 512.0);
+    let sample = textureLoad(samples, vec2<i32>(position.xy), 0);
+    return color * tint + sample + vec4<f32>(camera.position, 0.0);
+}
+"#;
+
+    const COMPUTE: &str = r#"
+@group(0) @binding(0) var<storage, read> input: array<f32>;
+@group(0) @binding(1) var<storage, read_write> output: array<f32>;
+@group(0) @binding(2) var image: texture_storage_2d<rgba8unorm, write>;
+
+@compute @workgroup_size(1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    output[id.x] = input[id.x];
+    textureStore(image, vec2<i32>(id.xy), vec4<f32>(1.0));
+}
+"#;
+
+    fn entry(bindings: &ShaderBindings, group: u32, binding: u32) -> BindGroupLayoutEntry {
+        *bindings
+            .get_entries(group)
+            .iter()
+            .find(|entry| entry.binding == binding)
+            .expect("binding not reflected")
+    }
+
+    fn uniform(size: u64) -> BindingType {
+        BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: BufferSize::new(size),
+        }
+    }
+
+    #[test]
+    fn groups_are_counted_up_to_the_highest() {
+        let bindings = ShaderBindings::from_wgsl(SCENE).unwrap();
+
+        assert_eq!(bindings.get_group_count(), 2);
+        assert_eq!(bindings.get_entries(0).len(), 3);
+        assert!(bindings.get_entries(2).is_empty());
+    }
+
+    #[test]
+    fn uniforms_need_the_size_of_their_struct() {
+        let bindings = ShaderBindings::from_wgsl(SCENE).unwrap();
+
+        // A mat4x4 and a vec3, padded to the struct's 16 byte alignment.
+        assert_eq!(entry(&bindings, 0, 0).ty, uniform(80));
+        assert_eq!(entry(&bindings, 0, 1).ty, uniform(16));
+    }
+
+    #[test]
+    fn bindings_are_visible_to_the_stages_that_use_them() {
+        let bindings = ShaderBindings::from_wgsl(SCENE).unwrap();
+
+        assert_eq!(
+            entry(&bindings, 0, 0).visibility,
+            ShaderStages::VERTEX_FRAGMENT
+        );
+        assert_eq!(entry(&bindings, 0, 1).visibility, ShaderStages::FRAGMENT);
+        assert_eq!(entry(&bindings, 1, 1).visibility, ShaderStages::FRAGMENT);
+    }
+
+    #[test]
+    fn unused_bindings_are_visible_to_no_stage() {
+        let bindings = ShaderBindings::from_wgsl(SCENE).unwrap();
+
+        assert_eq!(entry(&bindings, 0, 2).visibility, ShaderStages::NONE);
+    }
+
+    #[test]
+    fn float_textures_are_filterable_unless_multisampled() {
+        let bindings = ShaderBindings::from_wgsl(SCENE).unwrap();
+
+        assert_eq!(
+            entry(&bindings, 1, 0).ty,
+            BindingType::Sampler(SamplerBindingType::Filtering)
+        );
+        assert_eq!(
+            entry(&bindings, 1, 1).ty,
+            BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            }
+        );
+        assert_eq!(
+            entry(&bindings, 1, 2).ty,
+            BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: false },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: true,
+            }
+        );
+    }
+
+    #[test]
+    fn storage_bindings_keep_their_access() {
+        let bindings = ShaderBindings::from_wgsl(COMPUTE).unwrap();
+
+        assert_eq!(
+            entry(&bindings, 0, 0).ty,
+            BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(4),
+            }
+        );
+        assert_eq!(
+            entry(&bindings, 0, 1).ty,
+            BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(4),
+            }
+        );
+        assert_eq!(
+            entry(&bindings, 0, 2).ty,
+            BindingType::StorageTexture {
+                access: StorageTextureAccess::WriteOnly,
+                format: TextureFormat::Rgba8Unorm,
+                view_dimension: TextureViewDimension::D2,
+            }
+        );
+        assert_eq!(entry(&bindings, 0, 1).visibility, ShaderStages::COMPUTE);
+    }
+
+    #[test]
+    fn merging_combines_visibility() {
+        let mut bindings = ShaderBindings::from_wgsl(COMPUTE).unwrap();
+        let other = ShaderBindings::from_wgsl(
+            r#"
+@group(0) @binding(0) var<storage, read> input: array<f32>;
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(input[0]);
+}
+"#,
+        )
+        .unwrap();
+
+        bindings.merge(&other).unwrap();
+
+        assert_eq!(
+            entry(&bindings, 0, 0).visibility,
+            ShaderStages::COMPUTE | ShaderStages::FRAGMENT
+        );
+        assert_eq!(bindings.get_entries(0).len(), 3);
+    }
+
+    #[test]
+    fn merging_different_types_is_a_mismatch() {
+        let mut bindings = ShaderBindings::from_wgsl(SCENE).unwrap();
+        let other =
+            ShaderBindings::from_wgsl("@group(1) @binding(1) var<uniform> tint: vec4<f32>;")
+                .unwrap();
+
+        assert_eq!(
+            bindings.merge(&other),
+            Err(ReflectionError::Mismatch {
+                group: 1,
+                binding: 1
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_wgsl_is_a_parse_error() {
+        assert!(matches!(
+            ShaderBindings::from_wgsl("@group(0) var<uniform> x f32;"),
+            Err(ReflectionError::Parse(_))
+        ));
+    }
+}
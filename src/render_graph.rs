@@ -0,0 +1,674 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+};
+
+use wgpu::{
+    CommandEncoder, Device, Extent3d, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+/// A texture in a [`RenderGraph`], either the surface or a transient texture the graph creates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ResourceId(usize);
+
+/// The size of a transient texture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureSize {
+    /// A fraction of the surface size, e.g. 0.5 for a half resolution target.
+    Relative(f32),
+    Absolute {
+        width: u32,
+        height: u32,
+    },
+}
+
+impl TextureSize {
+    /// The size in pixels for a `surface_width` by `surface_height` surface, at least 1 by 1.
+    pub fn resolve(self, surface_width: u32, surface_height: u32) -> (u32, u32) {
+        let (width, height) = match self {
+            TextureSize::Relative(scale) => (
+                (surface_width as f32 * scale).round() as u32,
+                (surface_height as f32 * scale).round() as u32,
+            ),
+            TextureSize::Absolute { width, height } => (width, height),
+        };
+
+        (width.max(1), height.max(1))
+    }
+}
+
+/// A texture that only exists while the graph executes. Transient textures with the same format, size and sample
+/// count whose uses don't overlap share the same memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransientDescriptor {
+    pub label: &'static str,
+    pub format: TextureFormat,
+    pub size: TextureSize,
+    pub sample_count: u32,
+}
+
+impl TransientDescriptor {
+    fn can_alias(&self, other: &Self) -> bool {
+        self.format == other.format
+            && self.size == other.size
+            && self.sample_count == other.sample_count
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Pass<P> {
+    tag: P,
+    reads: Vec<ResourceId>,
+    writes: Vec<ResourceId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphError {
+    /// No pass writes to the surface, so nothing would be drawn.
+    NoOutput,
+    /// A pass reads a resource that no pass writes.
+    UnwrittenResource { pass: String, resource: ResourceId },
+    /// The passes depend on each other in a loop.
+    Cycle { passes: Vec<String> },
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::NoOutput => write!(f, "no pass writes to the surface"),
+            GraphError::UnwrittenResource { pass, resource } => write!(
+                f,
+                "pass {} reads resource {} but no pass writes it",
+                pass, resource.0
+            ),
+            GraphError::Cycle { passes } => {
+                write!(f, "passes depend on each other: {}", passes.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+/// A texture the graph allocates, backing one or more transient resources.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhysicalTexture {
+    pub descriptor: TransientDescriptor,
+    pub usage: TextureUsages,
+}
+
+/// The result of compiling a graph: which passes run in which order, and which textures back which resources.
+/// Compiling needs no device, so this can be inspected without a GPU.
+#[derive(Debug, Clone)]
+pub struct CompiledGraph<P> {
+    /// Indices into the graph's passes, in execution order.
+    order: Vec<usize>,
+    tags: Vec<P>,
+    textures: Vec<PhysicalTexture>,
+    /// The index into `textures` of each resource, or `None` for the surface and unused transients.
+    assignments: Vec<Option<usize>>,
+}
+
+impl<P> CompiledGraph<P>
+where
+    P: Copy,
+{
+    /// The passes that contribute to the surface, in the order they run.
+    pub fn pass_order(&self) -> Vec<P> {
+        self.order.iter().map(|index| self.tags[*index]).collect()
+    }
+
+    pub fn textures(&self) -> &[PhysicalTexture] {
+        &self.textures
+    }
+
+    /// The index in [`CompiledGraph::textures`] of the texture backing `resource`.
+    pub fn texture_of(&self, resource: ResourceId) -> Option<usize> {
+        self.assignments.get(resource.0).copied().flatten()
+    }
+}
+
+/// Passes and the textures they pass between each other, declared up front so the graph can work out what order to
+/// run the passes in and create the textures they need.
+///
+/// Each pass declares the resources it reads and writes. Every pass writing a resource runs before any pass reading
+/// it, and passes writing the same resource run in the order they were added; otherwise passes may be added in any
+/// order. Passes that don't contribute to the surface are culled.
+///
+/// The graph doesn't record passes itself. Each pass is identified by a tag of the caller's choosing, usually an
+/// enum, which [`RenderGraph::execute`] hands back in order along with the views to draw with.
+pub struct RenderGraph<P> {
+    transients: Vec<Option<TransientDescriptor>>,
+    passes: Vec<Pass<P>>,
+    compiled: Option<CompiledGraph<P>>,
+    textures: Vec<(Texture, TextureView)>,
+    size: Option<(u32, u32)>,
+}
+
+impl<P> RenderGraph<P>
+where
+    P: Copy + fmt::Debug,
+{
+    pub fn new() -> Self {
+        Self {
+            // Resource 0 is the surface.
+            transients: vec![None],
+            passes: Vec::new(),
+            compiled: None,
+            textures: Vec::new(),
+            size: None,
+        }
+    }
+
+    /// The frame being drawn, which is provided when the graph is executed.
+    pub fn surface(&self) -> ResourceId {
+        ResourceId(0)
+    }
+
+    pub fn create_transient(&mut self, descriptor: TransientDescriptor) -> ResourceId {
+        self.transients.push(Some(descriptor));
+        self.invalidate();
+
+        ResourceId(self.transients.len() - 1)
+    }
+
+    pub fn add_pass(&mut self, tag: P, reads: &[ResourceId], writes: &[ResourceId]) {
+        self.passes.push(Pass {
+            tag,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+        });
+        self.invalidate();
+    }
+
+    fn invalidate(&mut self) {
+        self.compiled = None;
+        self.textures.clear();
+        self.size = None;
+    }
+
+    /// Order the passes and assign textures to the resources without creating anything.
+    pub fn compile(&self) -> Result<CompiledGraph<P>, GraphError> {
+        let surface = self.surface();
+
+        let mut writers = vec![Vec::new(); self.transients.len()];
+        let mut readers = vec![Vec::new(); self.transients.len()];
+        for (index, pass) in self.passes.iter().enumerate() {
+            for resource in &pass.writes {
+                writers[resource.0].push(index);
+            }
+            for resource in &pass.reads {
+                readers[resource.0].push(index);
+            }
+        }
+
+        // Work back from the passes drawing to the surface to everything they need.
+        let mut live = vec![false; self.passes.len()];
+        let mut pending = writers[surface.0].clone();
+        if pending.is_empty() {
+            return Err(GraphError::NoOutput);
+        }
+        while let Some(index) = pending.pop() {
+            if live[index] {
+                continue;
+            }
+            live[index] = true;
+
+            let pass = &self.passes[index];
+            for resource in pass.reads.iter().chain(&pass.writes) {
+                if writers[resource.0].is_empty() {
+                    return Err(GraphError::UnwrittenResource {
+                        pass: format!("{:?}", pass.tag),
+                        resource: *resource,
+                    });
+                }
+                pending.extend(&writers[resource.0]);
+            }
+        }
+
+        // Writers run in the order they were added, and all of them before any reader.
+        let mut dependents = vec![HashSet::new(); self.passes.len()];
+        for (writers, readers) in writers.iter().zip(&readers) {
+            let writers = writers
+                .iter()
+                .copied()
+                .filter(|index| live[*index])
+                .collect::<Vec<_>>();
+
+            for pair in writers.windows(2) {
+                dependents[pair[0]].insert(pair[1]);
+            }
+            if let Some(last_writer) = writers.last() {
+                for reader in readers {
+                    if live[*reader] && reader != last_writer {
+                        dependents[*last_writer].insert(*reader);
+                    }
+                }
+            }
+        }
+
+        // Kahn's algorithm, preferring the order passes were added in when there's a choice.
+        let mut dependency_counts = vec![0; self.passes.len()];
+        for dependents in &dependents {
+            for dependent in dependents {
+                dependency_counts[*dependent] += 1;
+            }
+        }
+        let mut ready = (0..self.passes.len())
+            .filter(|index| live[*index] && dependency_counts[*index] == 0)
+            .collect::<BTreeSet<_>>();
+        let mut order = Vec::new();
+        while let Some(index) = ready.pop_first() {
+            order.push(index);
+            for dependent in &dependents[index] {
+                dependency_counts[*dependent] -= 1;
+                if dependency_counts[*dependent] == 0 {
+                    ready.insert(*dependent);
+                }
+            }
+        }
+
+        let live_count = live.iter().filter(|live| **live).count();
+        if order.len() < live_count {
+            return Err(GraphError::Cycle {
+                passes: (0..self.passes.len())
+                    .filter(|index| live[*index] && !order.contains(index))
+                    .map(|index| format!("{:?}", self.passes[index].tag))
+                    .collect(),
+            });
+        }
+
+        let (textures, assignments) = self.assign_textures(&order);
+
+        Ok(CompiledGraph {
+            order,
+            tags: self.passes.iter().map(|pass| pass.tag).collect(),
+            textures,
+            assignments,
+        })
+    }
+
+    /// Give each used transient a texture, reusing textures whose previous resources are no longer needed.
+    fn assign_textures(&self, order: &[usize]) -> (Vec<PhysicalTexture>, Vec<Option<usize>>) {
+        // The first and last position in `order` each resource is used at, and how it's used.
+        let mut lifetimes = vec![None; self.transients.len()];
+        let mut usages = vec![TextureUsages::empty(); self.transients.len()];
+        for (position, index) in order.iter().enumerate() {
+            let pass = &self.passes[*index];
+            let uses = pass
+                .reads
+                .iter()
+                .map(|resource| (resource, TextureUsages::TEXTURE_BINDING))
+                .chain(
+                    pass.writes
+                        .iter()
+                        .map(|resource| (resource, TextureUsages::RENDER_ATTACHMENT)),
+                );
+
+            for (resource, usage) in uses {
+                let lifetime: &mut Option<(usize, usize)> = &mut lifetimes[resource.0];
+                *lifetime = Some(match *lifetime {
+                    Some((first, _)) => (first, position),
+                    None => (position, position),
+                });
+                usages[resource.0] |= usage;
+            }
+        }
+
+        let mut resources = (0..self.transients.len())
+            .filter_map(|index| {
+                let descriptor = self.transients[index].as_ref()?;
+                let lifetime = lifetimes[index]?;
+                Some((index, descriptor, lifetime))
+            })
+            .collect::<Vec<_>>();
+        resources.sort_by_key(|(_, _, (first, _))| *first);
+
+        let mut textures: Vec<PhysicalTexture> = Vec::new();
+        // The last position each texture is in use until.
+        let mut in_use_until = Vec::new();
+        let mut assignments = vec![None; self.transients.len()];
+        for (index, descriptor, (first, last)) in resources {
+            let free = (0..textures.len()).find(|texture| {
+                in_use_until[*texture] < first
+                    && textures[*texture].descriptor.can_alias(descriptor)
+            });
+
+            let texture = match free {
+                Some(texture) => {
+                    textures[texture].usage |= usages[index];
+                    in_use_until[texture] = last;
+                    texture
+                }
+                None => {
+                    textures.push(PhysicalTexture {
+                        descriptor: descriptor.clone(),
+                        usage: usages[index],
+                    });
+                    in_use_until.push(last);
+                    textures.len() - 1
+                }
+            };
+            assignments[index] = Some(texture);
+        }
+
+        (textures, assignments)
+    }
+
+    /// Compile the graph if it's changed and create its textures for a `width` by `height` surface if they don't
+    /// exist yet or the size has changed. Returns whether the textures were recreated, in which case any bind groups
+    /// referring to them have to be recreated too.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        width: u32,
+        height: u32,
+    ) -> Result<bool, GraphError> {
+        if self.compiled.is_none() {
+            self.compiled = Some(self.compile()?);
+        }
+        if self.size == Some((width, height)) {
+            return Ok(false);
+        }
+
+        let compiled = self.compiled.as_ref().expect("compiled above");
+        self.textures = compiled
+            .textures
+            .iter()
+            .map(|physical| {
+                let descriptor = &physical.descriptor;
+                let (texture_width, texture_height) = descriptor.size.resolve(width, height);
+
+                let texture = device.create_texture(&TextureDescriptor {
+                    label: Some(descriptor.label),
+                    size: Extent3d {
+                        width: texture_width,
+                        height: texture_height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: descriptor.sample_count,
+                    dimension: TextureDimension::D2,
+                    format: descriptor.format,
+                    usage: physical.usage,
+                    view_formats: &[],
+                });
+                let texture_view = texture.create_view(&TextureViewDescriptor::default());
+
+                (texture, texture_view)
+            })
+            .collect();
+        self.size = Some((width, height));
+
+        Ok(true)
+    }
+
+    /// The view of a transient texture, e.g. for a bind group that samples it. `None` until the graph is prepared.
+    pub fn view(&self, resource: ResourceId) -> Option<&TextureView> {
+        let texture = self.compiled.as_ref()?.texture_of(resource)?;
+        self.textures.get(texture).map(|(_, view)| view)
+    }
+
+    /// Call `record` for every pass in order, with the views of the graph's textures and `surface_view` for the
+    /// surface. The graph must have been prepared.
+    pub fn execute<F>(
+        &self,
+        encoder: &mut CommandEncoder,
+        surface_view: &TextureView,
+        mut record: F,
+    ) where
+        F: FnMut(P, &mut CommandEncoder, &GraphViews<P>),
+    {
+        let compiled = self
+            .compiled
+            .as_ref()
+            .expect("the render graph must be prepared before it's executed");
+        let views = GraphViews {
+            graph: self,
+            surface_view,
+        };
+
+        for index in &compiled.order {
+            record(compiled.tags[*index], encoder, &views);
+        }
+    }
+}
+
+impl<P> Default for RenderGraph<P>
+where
+    P: Copy + fmt::Debug,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The views of a graph's resources while it executes.
+pub struct GraphViews<'a, P> {
+    graph: &'a RenderGraph<P>,
+    surface_view: &'a TextureView,
+}
+
+impl<'a, P> GraphViews<'a, P>
+where
+    P: Copy + fmt::Debug,
+{
+    pub fn get(&self, resource: ResourceId) -> &'a TextureView {
+        if resource == self.graph.surface() {
+            self.surface_view
+        } else {
+            self.graph
+                .view(resource)
+                .expect("the resource isn't used by any pass")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Tag {
+        Shadow,
+        Scene,
+        Bloom,
+        Blur,
+        Composite,
+        Overlay,
+    }
+
+    fn color_target(label: &'static str) -> TransientDescriptor {
+        TransientDescriptor {
+            label,
+            format: TextureFormat::Rgba16Float,
+            size: TextureSize::Relative(1.0),
+            sample_count: 1,
+        }
+    }
+
+    #[test]
+    fn writers_run_before_readers_whatever_order_they_were_added_in() {
+        let mut graph = RenderGraph::new();
+        let surface = graph.surface();
+        let scene = graph.create_transient(color_target("scene"));
+        let bloom = graph.create_transient(color_target("bloom"));
+
+        graph.add_pass(Tag::Composite, &[scene, bloom], &[surface]);
+        graph.add_pass(Tag::Bloom, &[scene], &[bloom]);
+        graph.add_pass(Tag::Scene, &[], &[scene]);
+
+        let compiled = graph.compile().unwrap();
+        assert_eq!(
+            compiled.pass_order(),
+            [Tag::Scene, Tag::Bloom, Tag::Composite]
+        );
+    }
+
+    #[test]
+    fn writers_of_the_same_resource_run_in_the_order_they_were_added() {
+        let mut graph = RenderGraph::new();
+        let surface = graph.surface();
+
+        graph.add_pass(Tag::Scene, &[], &[surface]);
+        graph.add_pass(Tag::Overlay, &[], &[surface]);
+
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.pass_order(), [Tag::Scene, Tag::Overlay]);
+    }
+
+    #[test]
+    fn passes_not_contributing_to_the_surface_are_culled() {
+        let mut graph = RenderGraph::new();
+        let surface = graph.surface();
+        let scene = graph.create_transient(color_target("scene"));
+        let shadow = graph.create_transient(color_target("shadow"));
+        let bloom = graph.create_transient(color_target("bloom"));
+
+        graph.add_pass(Tag::Shadow, &[], &[shadow]);
+        graph.add_pass(Tag::Scene, &[], &[scene]);
+        graph.add_pass(Tag::Bloom, &[shadow], &[bloom]);
+        graph.add_pass(Tag::Composite, &[scene], &[surface]);
+
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.pass_order(), [Tag::Scene, Tag::Composite]);
+        assert_eq!(compiled.texture_of(shadow), None);
+        assert_eq!(compiled.texture_of(bloom), None);
+        assert_eq!(compiled.texture_of(surface), None);
+        assert_eq!(compiled.textures().len(), 1);
+    }
+
+    #[test]
+    fn graph_without_a_surface_writer_has_no_output() {
+        let mut graph = RenderGraph::new();
+        let scene = graph.create_transient(color_target("scene"));
+        graph.add_pass(Tag::Scene, &[], &[scene]);
+
+        assert_eq!(graph.compile().unwrap_err(), GraphError::NoOutput);
+        assert_eq!(
+            RenderGraph::<Tag>::new().compile().unwrap_err(),
+            GraphError::NoOutput
+        );
+    }
+
+    #[test]
+    fn reading_an_unwritten_resource_fails() {
+        let mut graph = RenderGraph::new();
+        let surface = graph.surface();
+        let scene = graph.create_transient(color_target("scene"));
+        graph.add_pass(Tag::Composite, &[scene], &[surface]);
+
+        assert_eq!(
+            graph.compile().unwrap_err(),
+            GraphError::UnwrittenResource {
+                pass: "Composite".to_string(),
+                resource: scene,
+            }
+        );
+    }
+
+    #[test]
+    fn passes_depending_on_each_other_are_a_cycle() {
+        let mut graph = RenderGraph::new();
+        let surface = graph.surface();
+        let bloom = graph.create_transient(color_target("bloom"));
+        let blur = graph.create_transient(color_target("blur"));
+
+        graph.add_pass(Tag::Bloom, &[blur], &[bloom]);
+        graph.add_pass(Tag::Blur, &[bloom], &[blur]);
+        graph.add_pass(Tag::Composite, &[blur], &[surface]);
+
+        assert_eq!(
+            graph.compile().unwrap_err(),
+            GraphError::Cycle {
+                passes: vec![
+                    "Bloom".to_string(),
+                    "Blur".to_string(),
+                    "Composite".to_string()
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn textures_are_reused_once_their_resources_are_done_with() {
+        let mut graph = RenderGraph::new();
+        let surface = graph.surface();
+        let scene = graph.create_transient(color_target("scene"));
+        let bloom = graph.create_transient(color_target("bloom"));
+        let blur = graph.create_transient(color_target("blur"));
+
+        graph.add_pass(Tag::Scene, &[], &[scene]);
+        graph.add_pass(Tag::Bloom, &[scene], &[bloom]);
+        graph.add_pass(Tag::Blur, &[bloom], &[blur]);
+        graph.add_pass(Tag::Composite, &[blur], &[surface]);
+
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.textures().len(), 2);
+        // The bloom pass reads the scene while writing bloom, so they can't share.
+        assert_ne!(compiled.texture_of(scene), compiled.texture_of(bloom));
+        assert_eq!(compiled.texture_of(scene), compiled.texture_of(blur));
+    }
+
+    #[test]
+    fn textures_are_only_reused_for_the_same_format_and_size() {
+        let mut graph = RenderGraph::new();
+        let surface = graph.surface();
+        let scene = graph.create_transient(color_target("scene"));
+        let bloom = graph.create_transient(color_target("bloom"));
+        let half_size = graph.create_transient(TransientDescriptor {
+            size: TextureSize::Relative(0.5),
+            ..color_target("half_size")
+        });
+        let other_format = graph.create_transient(TransientDescriptor {
+            format: TextureFormat::Rgba8Unorm,
+            ..color_target("other_format")
+        });
+
+        graph.add_pass(Tag::Scene, &[], &[scene]);
+        graph.add_pass(Tag::Bloom, &[scene], &[bloom]);
+        graph.add_pass(Tag::Blur, &[bloom], &[half_size]);
+        graph.add_pass(Tag::Composite, &[half_size], &[other_format]);
+        graph.add_pass(Tag::Overlay, &[other_format], &[surface]);
+
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.textures().len(), 4);
+    }
+
+    #[test]
+    fn reused_textures_have_every_resource_usage() {
+        let mut graph = RenderGraph::new();
+        let surface = graph.surface();
+        let scene = graph.create_transient(color_target("scene"));
+        let scratch = graph.create_transient(color_target("scratch"));
+        let bloom = graph.create_transient(color_target("bloom"));
+
+        graph.add_pass(Tag::Scene, &[], &[scene, scratch]);
+        graph.add_pass(Tag::Bloom, &[scene], &[bloom]);
+        graph.add_pass(Tag::Composite, &[bloom], &[surface]);
+
+        let compiled = graph.compile().unwrap();
+        let texture = compiled.texture_of(scratch).unwrap();
+        assert_eq!(compiled.texture_of(bloom), Some(texture));
+        assert_eq!(
+            compiled.textures()[texture].usage,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING
+        );
+    }
+
+    #[test]
+    fn sizes_resolve_against_the_surface() {
+        assert_eq!(TextureSize::Relative(1.0).resolve(800, 600), (800, 600));
+        assert_eq!(TextureSize::Relative(0.5).resolve(801, 600), (401, 300));
+        assert_eq!(TextureSize::Relative(0.001).resolve(800, 600), (1, 1));
+        assert_eq!(
+            TextureSize::Absolute {
+                width: 256,
+                height: 0
+            }
+            .resolve(800, 600),
+            (256, 1)
+        );
+    }
+}
@@ -0,0 +1,386 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use wgpu::{Device, Error, Surface, SurfaceConfiguration, SurfaceError, SurfaceTexture};
+use wgpu_core::device::DeviceError;
+
+/// Consecutive frames the surface may be lost for, even after reconfiguring it, before the device is assumed lost.
+pub const MAX_LOST_FRAMES: u32 = 3;
+
+/// The parts of a surface that frame acquisition needs, so that the recovery logic in [`FrameAcquirer`] can be
+/// driven by a [`MockSurface`] as well as a real one.
+pub trait SurfaceTarget {
+    type Frame;
+
+    fn configure(&mut self, width: u32, height: u32);
+
+    fn acquire(&mut self) -> Result<Self::Frame, SurfaceError>;
+}
+
+/// A wgpu surface together with the device and configuration needed to reconfigure it.
+pub struct WgpuSurface<'a> {
+    pub surface: &'a Surface,
+    pub device: &'a Device,
+    pub config: &'a mut SurfaceConfiguration,
+}
+
+impl<'a> SurfaceTarget for WgpuSurface<'a> {
+    type Frame = SurfaceTexture;
+
+    fn configure(&mut self, width: u32, height: u32) {
+        self.config.width = width;
+        self.config.height = height;
+        self.surface.configure(self.device, self.config);
+    }
+
+    fn acquire(&mut self) -> Result<Self::Frame, SurfaceError> {
+        self.surface.get_current_texture()
+    }
+}
+
+/// A surface that hands out scripted results, for exercising [`FrameAcquirer`] without a window or GPU. Once the
+/// script runs out every acquire succeeds.
+#[derive(Debug, Default, Clone)]
+pub struct MockSurface {
+    results: VecDeque<Result<(), SurfaceError>>,
+    configurations: Vec<(u32, u32)>,
+    frames: u32,
+}
+
+impl MockSurface {
+    pub fn new<I>(results: I) -> Self
+    where
+        I: IntoIterator<Item = Result<(), SurfaceError>>,
+    {
+        Self {
+            results: results.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Every size the surface has been configured with, in order.
+    pub fn configurations(&self) -> &[(u32, u32)] {
+        &self.configurations
+    }
+
+    /// The number of frames successfully acquired.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+}
+
+impl SurfaceTarget for MockSurface {
+    type Frame = u32;
+
+    fn configure(&mut self, width: u32, height: u32) {
+        self.configurations.push((width, height));
+    }
+
+    fn acquire(&mut self) -> Result<Self::Frame, SurfaceError> {
+        self.results.pop_front().unwrap_or(Ok(()))?;
+        self.frames += 1;
+        Ok(self.frames)
+    }
+}
+
+/// Errors that rendering can't carry on from by skipping a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The GPU ran out of memory for a new frame.
+    OutOfMemory,
+    /// The device stopped working, e.g. because the driver was reset, and everything created from it has to be
+    /// recreated on a new one.
+    DeviceLost,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::OutOfMemory => write!(f, "out of memory acquiring a frame"),
+            FrameError::DeviceLost => write!(f, "the device was lost"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Acquires frames from a surface, recovering from everything that isn't fatal.
+///
+/// Outdated and lost surfaces are reconfigured and the frame retried once, timeouts skip the frame, and nothing is
+/// acquired while the window is minimized, i.e. has a size of zero, since a surface can't be configured that small.
+/// Resizes are recorded with [`FrameAcquirer::resize`] and applied just before the next frame, so a burst of resize
+/// events only reconfigures the surface once.
+#[derive(Debug, Clone)]
+pub struct FrameAcquirer {
+    width: u32,
+    height: u32,
+    needs_configure: bool,
+    lost_frames: u32,
+}
+
+impl FrameAcquirer {
+    /// For a surface that's already been configured as `width` by `height`.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            needs_configure: false,
+            lost_frames: 0,
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.needs_configure = true;
+        }
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// The next frame to draw, or `None` if this frame should be skipped.
+    pub fn acquire<S>(&mut self, surface: &mut S) -> Result<Option<S::Frame>, FrameError>
+    where
+        S: SurfaceTarget,
+    {
+        if self.is_minimized() {
+            return Ok(None);
+        }
+
+        if self.needs_configure {
+            surface.configure(self.width, self.height);
+            self.needs_configure = false;
+        }
+
+        let result = match surface.acquire() {
+            Err(SurfaceError::Lost | SurfaceError::Outdated) => {
+                surface.configure(self.width, self.height);
+                surface.acquire()
+            }
+            result => result,
+        };
+
+        match result {
+            Ok(frame) => {
+                self.lost_frames = 0;
+                Ok(Some(frame))
+            }
+
+            Err(SurfaceError::Timeout) => Ok(None),
+
+            Err(SurfaceError::Lost | SurfaceError::Outdated) => {
+                self.lost_frames += 1;
+                if self.lost_frames >= MAX_LOST_FRAMES {
+                    Err(FrameError::DeviceLost)
+                } else {
+                    Ok(None)
+                }
+            }
+
+            Err(SurfaceError::OutOfMemory) => Err(FrameError::OutOfMemory),
+        }
+    }
+}
+
+/// Watches a device's uncaptured errors for signs that the device has been lost.
+///
+/// wgpu doesn't report device loss directly; instead calls on a lost device fail with an error caused by it.
+/// Installing the watcher replaces the device's default error handler, so any other uncaptured error is still fatal.
+#[derive(Debug, Clone)]
+pub struct DeviceLoss {
+    lost: Arc<AtomicBool>,
+}
+
+impl DeviceLoss {
+    pub fn watch(device: &Device) -> Self {
+        let lost = Arc::new(AtomicBool::new(false));
+
+        let handler_lost = Arc::clone(&lost);
+        device.on_uncaptured_error(Box::new(move |error| {
+            if is_device_lost_error(&error) {
+                handler_lost.store(true, Ordering::Relaxed);
+            } else {
+                panic!("wgpu error: {}", error);
+            }
+        }));
+
+        Self { lost }
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+}
+
+/// Whether `error` was caused by the device being lost, found the same way wgpu finds out of memory errors: by
+/// looking for a [`DeviceError`] among its sources.
+fn is_device_lost_error(error: &Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(error) = source {
+        if let Some(DeviceError::Lost) = error.downcast_ref::<DeviceError>() {
+            return true;
+        }
+        source = error.source();
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validation_error(source: impl std::error::Error + Send + 'static) -> Error {
+        Error::Validation {
+            description: source.to_string(),
+            source: Box::new(source),
+        }
+    }
+
+    /// A script for `frames` frames where the surface is lost, even after being reconfigured.
+    fn lost_frames(frames: u32) -> Vec<Result<(), SurfaceError>> {
+        vec![Err(SurfaceError::Lost); 2 * frames as usize]
+    }
+
+    #[test]
+    fn lost_surface_is_reconfigured_and_retried() {
+        let mut surface = MockSurface::new([Err(SurfaceError::Lost)]);
+        let mut acquirer = FrameAcquirer::new(800, 600);
+
+        assert_eq!(acquirer.acquire(&mut surface), Ok(Some(1)));
+        assert_eq!(surface.configurations(), &[(800, 600)]);
+    }
+
+    #[test]
+    fn outdated_surface_is_reconfigured_and_retried() {
+        let mut surface = MockSurface::new([Err(SurfaceError::Outdated)]);
+        let mut acquirer = FrameAcquirer::new(800, 600);
+
+        assert_eq!(acquirer.acquire(&mut surface), Ok(Some(1)));
+        assert_eq!(surface.configurations(), &[(800, 600)]);
+    }
+
+    #[test]
+    fn timeout_skips_the_frame() {
+        let mut surface = MockSurface::new([Err(SurfaceError::Timeout)]);
+        let mut acquirer = FrameAcquirer::new(800, 600);
+
+        assert_eq!(acquirer.acquire(&mut surface), Ok(None));
+        assert!(surface.configurations().is_empty());
+        assert_eq!(acquirer.acquire(&mut surface), Ok(Some(1)));
+    }
+
+    #[test]
+    fn out_of_memory_is_fatal() {
+        let mut surface = MockSurface::new([Err(SurfaceError::OutOfMemory)]);
+        let mut acquirer = FrameAcquirer::new(800, 600);
+
+        assert_eq!(acquirer.acquire(&mut surface), Err(FrameError::OutOfMemory));
+    }
+
+    #[test]
+    fn minimized_surface_is_neither_configured_nor_acquired() {
+        let mut surface = MockSurface::default();
+        let mut acquirer = FrameAcquirer::new(800, 600);
+
+        acquirer.resize(0, 600);
+        assert!(acquirer.is_minimized());
+        assert_eq!(acquirer.acquire(&mut surface), Ok(None));
+        assert!(surface.configurations().is_empty());
+        assert_eq!(surface.frames(), 0);
+
+        acquirer.resize(800, 600);
+        assert_eq!(acquirer.acquire(&mut surface), Ok(Some(1)));
+        assert_eq!(surface.configurations(), &[(800, 600)]);
+    }
+
+    #[test]
+    fn resizes_within_a_frame_configure_once() {
+        let mut surface = MockSurface::default();
+        let mut acquirer = FrameAcquirer::new(800, 600);
+
+        acquirer.resize(1024, 768);
+        acquirer.resize(1280, 720);
+        acquirer.resize(1920, 1080);
+        assert_eq!(acquirer.acquire(&mut surface), Ok(Some(1)));
+        assert_eq!(surface.configurations(), &[(1920, 1080)]);
+
+        assert_eq!(acquirer.acquire(&mut surface), Ok(Some(2)));
+        assert_eq!(surface.configurations(), &[(1920, 1080)]);
+    }
+
+    #[test]
+    fn resizing_to_the_same_size_does_not_configure() {
+        let mut surface = MockSurface::default();
+        let mut acquirer = FrameAcquirer::new(800, 600);
+
+        acquirer.resize(800, 600);
+        assert_eq!(acquirer.acquire(&mut surface), Ok(Some(1)));
+        assert!(surface.configurations().is_empty());
+    }
+
+    #[test]
+    fn staying_lost_loses_the_device() {
+        let mut surface = MockSurface::new(lost_frames(MAX_LOST_FRAMES));
+        let mut acquirer = FrameAcquirer::new(800, 600);
+
+        for _ in 1..MAX_LOST_FRAMES {
+            assert_eq!(acquirer.acquire(&mut surface), Ok(None));
+        }
+        assert_eq!(acquirer.acquire(&mut surface), Err(FrameError::DeviceLost));
+        assert_eq!(surface.frames(), 0);
+    }
+
+    #[test]
+    fn recovering_resets_the_lost_frames() {
+        let script = lost_frames(MAX_LOST_FRAMES - 1)
+            .into_iter()
+            .chain([Ok(())])
+            .chain(lost_frames(MAX_LOST_FRAMES - 1));
+        let mut surface = MockSurface::new(script);
+        let mut acquirer = FrameAcquirer::new(800, 600);
+
+        for _ in 1..MAX_LOST_FRAMES {
+            assert_eq!(acquirer.acquire(&mut surface), Ok(None));
+        }
+        assert_eq!(acquirer.acquire(&mut surface), Ok(Some(1)));
+        for _ in 1..MAX_LOST_FRAMES {
+            assert_eq!(acquirer.acquire(&mut surface), Ok(None));
+        }
+    }
+
+    #[test]
+    fn device_lost_errors_are_found_among_the_sources() {
+        let error = validation_error(wgpu_core::error::ContextError {
+            string: "Device::create_buffer",
+            cause: Box::new(DeviceError::Lost),
+            label_key: "label",
+            label: String::new(),
+        });
+
+        assert!(is_device_lost_error(&error));
+    }
+
+    #[test]
+    fn other_errors_are_not_device_lost() {
+        assert!(!is_device_lost_error(&validation_error(
+            DeviceError::OutOfMemory
+        )));
+        assert!(!is_device_lost_error(&validation_error(
+            std::io::Error::other("the texture was lost")
+        )));
+    }
+}
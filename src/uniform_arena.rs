@@ -0,0 +1,302 @@
+use std::{marker::PhantomData, mem::size_of};
+
+use bytemuck::{bytes_of, Pod};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType,
+    BufferDescriptor, BufferSize, BufferUsages, Device, DynamicOffset, Queue, ShaderStages,
+    COPY_BUFFER_ALIGNMENT,
+};
+
+/// Values an arena has room for before it first grows.
+pub const DEFAULT_ARENA_CAPACITY: u64 = 64;
+
+/// `value` rounded up to a multiple of `alignment`, which must be a power of two.
+pub fn align_to(value: u64, alignment: u64) -> u64 {
+    debug_assert!(alignment.is_power_of_two());
+    (value + alignment - 1) & !(alignment - 1)
+}
+
+/// Hands out offsets for values of one size packed into a buffer, each starting at a multiple of the alignment. Runs
+/// out of room by doubling its capacity, so the buffer behind it has to grow to [`UniformAllocator::get_buffer_size`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniformAllocator {
+    stride: u64,
+    len: u64,
+    capacity: u64,
+}
+
+impl UniformAllocator {
+    /// An allocator for `size` byte values aligned to `alignment`, with room for at least one.
+    pub fn new(size: u64, alignment: u64, capacity: u64) -> Self {
+        Self {
+            stride: align_to(size.max(1), alignment),
+            len: 0,
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Bytes from the start of one value to the next.
+    pub fn get_stride(&self) -> u64 {
+        self.stride
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get_capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Bytes needed to hold as many values as there's capacity for.
+    pub fn get_buffer_size(&self) -> u64 {
+        self.stride * self.capacity
+    }
+
+    /// The offset of the next value.
+    pub fn allocate(&mut self) -> DynamicOffset {
+        if self.len == self.capacity {
+            self.capacity *= 2;
+        }
+
+        let offset = self.len * self.stride;
+        self.len += 1;
+
+        DynamicOffset::try_from(offset).expect("uniform arena offset out of range")
+    }
+
+    /// Free every value, keeping the capacity.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+/// Per-object uniforms packed into one buffer and bound with a single bind group, drawing each object with its own
+/// dynamic offset instead of a buffer and bind group per object.
+///
+/// Each frame, [`UniformArena::clear`] the arena, [`UniformArena::push`] a value per object, then
+/// [`UniformArena::upload`] them before drawing. The arena's bind group has the value at binding 0, so shaders declare
+/// a single uniform in the group it's bound to.
+#[derive(Debug)]
+pub struct UniformArena<T> {
+    label: String,
+    allocator: UniformAllocator,
+    data: Vec<u8>,
+    layout: BindGroupLayout,
+    buffer: Buffer,
+    bind_group: BindGroup,
+    _marker: PhantomData<T>,
+}
+
+impl<T> UniformArena<T>
+where
+    T: Pod,
+{
+    /// An arena named `label` for uniforms read by the `visibility` stages.
+    pub fn new(device: &Device, label: &str, visibility: ShaderStages) -> Self {
+        Self::with_capacity(device, label, visibility, DEFAULT_ARENA_CAPACITY)
+    }
+
+    pub fn with_capacity(
+        device: &Device,
+        label: &str,
+        visibility: ShaderStages,
+        capacity: u64,
+    ) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let allocator = UniformAllocator::new(size_of::<T>() as u64, alignment, capacity);
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some(&format!("bind_group_layout::{}", label)),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: BufferSize::new(size_of::<T>() as u64),
+                },
+                count: None,
+            }],
+        });
+
+        let (buffer, bind_group) =
+            create_buffer::<T>(device, label, &layout, allocator.get_buffer_size());
+
+        Self {
+            label: label.to_string(),
+            allocator,
+            data: Vec::new(),
+            layout,
+            buffer,
+            bind_group,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn get_bind_group_layout(&self) -> &BindGroupLayout {
+        &self.layout
+    }
+
+    /// The bind group to draw with, together with the offset of a value. Growing replaces it, so get it again after
+    /// every upload.
+    pub fn get_bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    pub fn len(&self) -> u64 {
+        self.allocator.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allocator.is_empty()
+    }
+
+    pub fn get_capacity(&self) -> u64 {
+        self.allocator.get_capacity()
+    }
+
+    /// Add a value, returning the dynamic offset to draw it with.
+    pub fn push(&mut self, value: &T) -> DynamicOffset {
+        let offset = self.allocator.allocate();
+        self.data.resize(offset as usize, 0);
+        self.data.extend_from_slice(bytes_of(value));
+
+        offset
+    }
+
+    /// Remove every value, ready for the next frame's.
+    pub fn clear(&mut self) {
+        self.allocator.clear();
+        self.data.clear();
+    }
+
+    /// Write the values pushed since the arena was last cleared to the GPU, first growing the buffer if they don't fit.
+    pub fn upload(&mut self, device: &Device, queue: &Queue) {
+        let size = self.allocator.get_buffer_size();
+        if self.buffer.size() < size {
+            (self.buffer, self.bind_group) =
+                create_buffer::<T>(device, &self.label, &self.layout, size);
+        }
+
+        if !self.data.is_empty() {
+            let len = align_to(self.data.len() as u64, COPY_BUFFER_ALIGNMENT);
+            self.data.resize(len as usize, 0);
+            queue.write_buffer(&self.buffer, 0, &self.data);
+        }
+    }
+}
+
+/// A `size` byte uniform buffer and a bind group binding one `T` of it at a time.
+fn create_buffer<T>(
+    device: &Device,
+    label: &str,
+    layout: &BindGroupLayout,
+    size: u64,
+) -> (Buffer, BindGroup) {
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some(&format!("ubo::{}", label)),
+        size,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: Some(&format!("bind_group::{}", label)),
+        layout,
+        entries: &[BindGroupEntry {
+            binding: 0,
+            resource: BindingResource::Buffer(BufferBinding {
+                buffer: &buffer,
+                offset: 0,
+                size: BufferSize::new(size_of::<T>() as u64),
+            }),
+        }],
+    });
+
+    (buffer, bind_group)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn align_to_rounds_up_to_the_alignment() {
+        assert_eq!(align_to(0, 256), 0);
+        assert_eq!(align_to(1, 256), 256);
+        assert_eq!(align_to(255, 256), 256);
+        assert_eq!(align_to(256, 256), 256);
+        assert_eq!(align_to(257, 256), 512);
+        assert_eq!(align_to(7, 1), 7);
+    }
+
+    #[test]
+    fn stride_is_a_multiple_of_the_alignment() {
+        assert_eq!(UniformAllocator::new(64, 256, 1).get_stride(), 256);
+        assert_eq!(UniformAllocator::new(256, 256, 1).get_stride(), 256);
+        assert_eq!(UniformAllocator::new(300, 256, 1).get_stride(), 512);
+        assert_eq!(UniformAllocator::new(64, 64, 1).get_stride(), 64);
+        assert_eq!(UniformAllocator::new(0, 256, 1).get_stride(), 256);
+    }
+
+    #[test]
+    fn values_are_allocated_a_stride_apart() {
+        let mut allocator = UniformAllocator::new(64, 256, 4);
+
+        assert!(allocator.is_empty());
+        assert_eq!(allocator.allocate(), 0);
+        assert_eq!(allocator.allocate(), 256);
+        assert_eq!(allocator.allocate(), 512);
+        assert_eq!(allocator.len(), 3);
+    }
+
+    #[test]
+    fn capacity_doubles_when_full() {
+        let mut allocator = UniformAllocator::new(64, 256, 2);
+        assert_eq!(allocator.get_buffer_size(), 512);
+
+        allocator.allocate();
+        allocator.allocate();
+        assert_eq!(allocator.get_capacity(), 2);
+
+        assert_eq!(allocator.allocate(), 512);
+        assert_eq!(allocator.get_capacity(), 4);
+        assert_eq!(allocator.get_buffer_size(), 1024);
+
+        allocator.allocate();
+        allocator.allocate();
+        assert_eq!(allocator.get_capacity(), 8);
+        assert_eq!(allocator.get_buffer_size(), 2048);
+    }
+
+    #[test]
+    fn capacity_is_at_least_one() {
+        let mut allocator = UniformAllocator::new(64, 256, 0);
+        assert_eq!(allocator.get_capacity(), 1);
+
+        allocator.allocate();
+        allocator.allocate();
+        assert_eq!(allocator.get_capacity(), 2);
+    }
+
+    #[test]
+    fn clear_keeps_the_capacity() {
+        let mut allocator = UniformAllocator::new(64, 256, 1);
+        for _ in 0..5 {
+            allocator.allocate();
+        }
+        assert_eq!(allocator.get_capacity(), 8);
+
+        allocator.clear();
+        assert!(allocator.is_empty());
+        assert_eq!(allocator.get_capacity(), 8);
+        assert_eq!(allocator.get_buffer_size(), 8 * 256);
+        assert_eq!(allocator.allocate(), 0);
+    }
+}
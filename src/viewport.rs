@@ -0,0 +1,74 @@
+use glam::Vec2;
+use wgpu::RenderPass;
+
+/// A rectangle of the render target, in pixels from its top left corner.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width: width.max(0.0),
+            height: height.max(0.0),
+        }
+    }
+
+    /// The viewport covering a whole `width` by `height` render target.
+    pub fn from_size(width: u32, height: u32) -> Self {
+        Self::new(0.0, 0.0, width as f32, height as f32)
+    }
+
+    /// A sub-rectangle given as fractions of this viewport, e.g. `(0.5, 0.0, 0.5, 1.0)` is the right half.
+    pub fn sub_viewport(&self, x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self::new(
+            self.x + x * self.width,
+            self.y + y * self.height,
+            width * self.width,
+            height * self.height,
+        )
+    }
+
+    /// Whether the viewport has no area, e.g. because the window is minimized.
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0.0 || self.height <= 0.0
+    }
+
+    /// Width over height, or `None` for an empty viewport.
+    pub fn aspect_ratio(&self) -> Option<f32> {
+        (!self.is_empty()).then(|| self.width / self.height)
+    }
+
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(self.width, self.height)
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.x >= self.x
+            && point.y >= self.y
+            && point.x < self.x + self.width
+            && point.y < self.y + self.height
+    }
+
+    /// `point`, in pixels from the top left of the render target, relative to the top left of the viewport.
+    pub fn to_local(&self, point: Vec2) -> Vec2 {
+        point - Vec2::new(self.x, self.y)
+    }
+
+    /// Restrict drawing in `rpass` to the viewport, clipping anything outside it.
+    pub fn apply(&self, rpass: &mut RenderPass) {
+        rpass.set_viewport(self.x, self.y, self.width, self.height, 0.0, 1.0);
+        rpass.set_scissor_rect(
+            self.x as u32,
+            self.y as u32,
+            self.width as u32,
+            self.height as u32,
+        );
+    }
+}